@@ -1,25 +1,48 @@
 use crate::auth::Auth;
 use crate::auth::AUTH_COOKIE;
 use crate::store::articles::Article;
-use crate::store::contacts::ContactRequest;
-use crate::store::paragraphs::Paragraph;
+use crate::store::articles::ArticleOrder;
+use crate::store::comments::{Ban, BanKind, Comment, CommentStatus};
+use crate::mail::{self, MailConfig};
+use crate::store::contacts::{ContactReply, ContactRequest, InboxFilter};
+use crate::store::media::{Media, MediaVariant};
+use crate::store::pages::{Page, PageTemplate};
+use crate::store::paragraphs::{Owner, Paragraph};
+use crate::store::previews::PreviewToken;
+use crate::store::redirects::{ArticleAlias, RedirectRule};
+use crate::store::series::Series;
+use crate::store::settings::Settings;
+use crate::store::webhooks::{Webhook, WebhookDelivery, WebhookEvent};
 use crate::store::paragraphs::ParagraphType;
+use crate::store::paragraphs::Placement;
 use crate::store::stats::Stats;
+use crate::upload;
+use crate::upload::UploadError;
+use crate::notify;
+use crate::spam;
+use crate::sync;
+use crate::trash::{self, TrashKind};
+use crate::variants;
+use crate::webhooks;
 use crate::Session;
 use crate::UserState;
 
 use super::store::*;
 use super::SharedState;
+use axum::extract::ConnectInfo;
+use axum::extract::DefaultBodyLimit;
 use axum::extract::Query;
 use axum::extract::{Path, State};
 use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::response::Response;
 use axum::response::{Html, IntoResponse};
-use axum::routing::{delete, get, post};
+use axum::routing::{delete, get, post, put};
 use axum::Form;
 use axum::Router;
 use minijinja::context;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -49,6 +72,32 @@ pub fn api_routes() -> Router<Arc<SharedState>, axum::body::Body> {
             "/article/:id",
             get(article_get).delete(article_delete).put(article_update),
         )
+        .route("/article/:id/paragraphs/order", put(paragraph_order))
+        .route("/pages", get(page_list).post(page_create))
+        .route("/page/:id", put(page_update).delete(page_delete))
+        .route("/page/:id/paragraphs/order", put(page_paragraph_order))
+        .route(
+            "/article/:id/previews",
+            get(preview_list).post(preview_create),
+        )
+        .route("/preview/:id", delete(preview_revoke))
+        .route("/article/:id/related", get(article_related))
+        .route(
+            "/article/:id/comments",
+            get(comment_list).post(comment_create).put(comment_toggle),
+        )
+        .route("/comment/:id", put(comment_moderate).delete(comment_delete))
+        .route("/bans", post(ban_create))
+        .route("/ban/:id", delete(ban_delete))
+        .route(
+            "/article/:id/series",
+            get(article_series_get).put(article_series_update),
+        )
+        .route("/series/:id", put(series_update).delete(series_delete))
+        .route("/redirects", get(redirect_list).post(redirect_create))
+        .route("/redirect/:id", delete(redirect_delete))
+        .route("/alias/:id", delete(alias_delete))
+        .route("/trash/:kind/:id", put(trash_restore).delete(trash_purge))
         .route("/paragraph", post(paragraph_create))
         .route(
             "/paragraph/:id",
@@ -56,13 +105,31 @@ pub fn api_routes() -> Router<Arc<SharedState>, axum::body::Body> {
                 .delete(paragraph_delete)
                 .put(paragraph_update),
         )
+        .route("/paragraph/:id/duplicate", post(paragraph_duplicate))
+        .route("/paragraph/:id/move", put(paragraph_move))
         .route("/files", get(file_list))
-        .route("/files/:id", post(file_upload))
+        .route(
+            "/files/:id",
+            post(file_upload).layer(DefaultBodyLimit::max(upload::max_size())),
+        )
+        .route("/media", get(media_list))
+        .route("/media/:id", put(media_update).delete(media_delete))
         .route("/login", post(login))
         .route("/stats", get(get_stats))
+        .route("/settings", put(settings_update))
         .route("/logout", get(logout))
         .route("/contact", post(post_contact))
-        .route("/contact/:id", get(get_contact_message))
+        .route(
+            "/contact/:id",
+            get(get_contact_message).put(contact_labels),
+        )
+        .route("/contact/:id/replies", post(contact_reply))
+        .route("/inbox", get(inbox_list).post(inbox_bulk))
+        .route("/export", get(export_download))
+        .route("/webhooks", get(webhook_list).post(webhook_create))
+        .route("/webhook/:id", put(webhook_toggle).delete(webhook_delete))
+        .route("/webhook/:id/ping", post(webhook_ping))
+        .route("/webhook/delivery/:id", put(webhook_retry))
 }
 
 // ------------------------------------------------------
@@ -89,6 +156,7 @@ async fn login(
         if let Ok(mut sessions) = state.sessions.write() {
             sessions.push(Session {
                 id: cookie_hash,
+                user: form.user.clone(),
                 user_state: UserState::Admin,
             });
         }
@@ -138,8 +206,18 @@ async fn logout(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoR
 // ------------------------------------------------------
 // articles
 // ------------------------------------------------------
-pub async fn article_list(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
-    let mut articles = match Article::find_all(&state.db) {
+#[derive(serde::Deserialize)]
+pub struct ArticleListQuery {
+    #[serde(default, alias = "sort")]
+    order: ArticleOrder,
+}
+
+pub async fn article_list(
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<ArticleListQuery>,
+    auth: Auth,
+) -> impl IntoResponse {
+    let mut articles = match Article::find_all_ordered(query.order, &state.db) {
         Ok(articles) => articles,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find articles")),
     };
@@ -155,7 +233,7 @@ pub async fn article_list(State(state): State<Arc<SharedState>>, auth: Auth) ->
     if !auth.is_admin() {
         articles = articles
             .drain(..)
-            .filter(|a| a.published)
+            .filter(|a| a.is_public())
             .collect::<Vec<_>>();
     }
 
@@ -174,12 +252,54 @@ pub async fn article_list(State(state): State<Arc<SharedState>>, auth: Auth) ->
     ))
 }
 
+/// shown below an article
+const RELATED_SHOWN: usize = 3;
+
+async fn article_related(
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    auth: Auth,
+) -> impl IntoResponse {
+    let mut articles = match related::find(id, &state.db) {
+        Ok(articles) => articles,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find articles")),
+    };
+
+    if !auth.is_admin() {
+        articles.retain(|a| a.is_public());
+    }
+    articles.truncate(RELATED_SHOWN);
+
+    let tmpl = match state
+        .templates
+        .get_template("components/related_articles.html")
+    {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    let mut header = HeaderMap::new();
+    header.insert("X-Robots-Tag", "noindex".parse().unwrap());
+
+    match tmpl.render(context! {
+        articles => articles,
+        auth => auth
+    }) {
+        Ok(html) => Ok((header, Html(html))),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
 pub async fn article_list_paginated_filterd(
     Path((offset, limit, tag)): Path<(i64, i64, String)>,
     State(state): State<Arc<SharedState>>,
+    Query(query): Query<ArticleListQuery>,
     auth: Auth,
 ) -> Response {
-    let mut articles = match Article::find_articles_paginated(&state.db, &tag, offset, limit) {
+    let mut articles = match Article::find_articles_paginated(&state.db, &tag, query.order, offset, limit) {
         Ok(articles) => articles,
         Err(err) => {
             dbg!(err);
@@ -198,7 +318,7 @@ pub async fn article_list_paginated_filterd(
     if !auth.is_admin() {
         articles = articles
             .drain(..)
-            .filter(|a| a.published)
+            .filter(|a| a.is_public())
             .collect::<Vec<_>>();
     }
 
@@ -221,9 +341,10 @@ pub async fn article_list_paginated_filterd(
 pub async fn article_list_paginated(
     Path((offset, limit)): Path<(i64, i64)>,
     State(state): State<Arc<SharedState>>,
+    Query(query): Query<ArticleListQuery>,
     auth: Auth,
 ) -> Response {
-    let mut articles = match Article::find_articles_paginated(&state.db, "", offset, limit) {
+    let mut articles = match Article::find_articles_paginated(&state.db, "", query.order, offset, limit) {
         Ok(articles) => articles,
         Err(_) => return (StatusCode::BAD_REQUEST, "failed to find articles").into_response(),
     };
@@ -238,7 +359,7 @@ pub async fn article_list_paginated(
     if !auth.is_admin() {
         articles = articles
             .drain(..)
-            .filter(|a| a.published)
+            .filter(|a| a.is_public())
             .collect::<Vec<_>>();
     }
 
@@ -264,8 +385,16 @@ async fn article_delete(
     auth: Auth,
 ) -> impl IntoResponse {
     require_admin!(auth);
+    let article = Article::find(id, &state.db).ok();
     match Article::delete(id, &state.db) {
-        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
+        Ok(_) => {
+            refresh_related(id, &state);
+            sync_article(id, &state);
+            if let Some(article) = article {
+                webhooks::emit(WebhookEvent::ArticleDeleted, webhooks::article_data(&article), &state.db);
+            }
+            Ok((StatusCode::OK, Html("deleted".to_string())))
+        }
         Err(e) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
     }
 }
@@ -290,6 +419,21 @@ pub struct ArticleForm {
     alias: Option<String>,
     tags: Option<String>,
     published: bool,
+    /// `datetime-local` input, server local time
+    publish_at: Option<String>,
+}
+
+impl ArticleForm {
+    /// the publication date, if it lies in the future
+    fn publish_at(&self) -> Option<i64> {
+        let date = self.publish_at.as_deref().filter(|d| !d.is_empty())?;
+        let time = chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M").ok()?;
+        let timestamp = time
+            .and_local_timezone(chrono::offset::Local)
+            .earliest()?
+            .timestamp();
+        (timestamp > chrono::offset::Local::now().timestamp()).then_some(timestamp)
+    }
 }
 
 pub async fn article_create(
@@ -299,9 +443,17 @@ pub async fn article_create(
 ) -> impl IntoResponse {
     require_admin!(auth);
     let mut article = Article::new(form.title);
+    article.alias = match Article::unique_alias(&article.title, None, &state.db) {
+        Ok(alias) => alias,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to create")),
+    };
 
     match article.insert(&state.db) {
-        Ok(_) => Ok((StatusCode::CREATED, Html("created".to_string()))),
+        Ok(_) => {
+            refresh_related(article.id.unwrap_or_default(), &state);
+            sync_article(article.id.unwrap_or_default(), &state);
+            Ok((StatusCode::CREATED, Html("created".to_string())))
+        }
         Err(e) => Err((StatusCode::BAD_REQUEST, "failed to create")),
     }
 }
@@ -319,12 +471,53 @@ async fn article_update(
         Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
     };
 
+    // an empty alias is generated from the title
+    let alias = match form.alias.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+        Some(alias) => {
+            let alias = Article::alias_slug(alias);
+            match Article::alias_taken(&alias, Some(id), &state.db) {
+                Ok(false) => alias,
+                Ok(true) => return Err((StatusCode::CONFLICT, "alias already in use")),
+                Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to update")),
+            }
+        }
+        None => match Article::unique_alias(&form.title, Some(id), &state.db) {
+            Ok(alias) => alias,
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to update")),
+        },
+    };
+
+    if article.change_alias(alias, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    article.publish_at = form.publish_at();
     article.title = form.title;
     article.teaser = form.teaser.unwrap_or(String::new());
     article.cover = form.cover.unwrap_or(String::new());
-    article.alias = form.alias.unwrap_or(String::new());
     article.tags = form.tags.unwrap_or(String::new());
-    article.published = form.published;
+    // a scheduled article stays private until the scheduler publishes it
+    let was_published = article.published;
+    article.published = form.published && article.publish_at.is_none();
+
+    if article.update(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    refresh_article_metrics(id, None, &state);
+    refresh_related(id, &state);
+    sync_article(id, &state);
+
+    let article = match Article::find(id, &state.db) {
+        Ok(a) => a,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
+    };
+
+    let data = webhooks::article_data(&article);
+    webhooks::emit(WebhookEvent::ArticleUpdated, data.clone(), &state.db);
+    if article.published && !was_published {
+        webhooks::emit(WebhookEvent::ArticlePublished, data, &state.db);
+    }
 
     let tmpl = match state
         .templates
@@ -346,230 +539,2033 @@ async fn article_update(
         }
     };
 
-    match article.update(&state.db) {
-        Ok(_) => Ok((StatusCode::OK, Html(html))),
-        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to update")),
+    Ok((StatusCode::OK, Html(html)))
+}
+
+/// recomputes the cached related articles of a saved or deleted article
+fn refresh_related(id: i64, state: &SharedState) {
+    if let Err(err) = related::refresh(id, &state.db) {
+        tracing::error!("failed to refresh related articles: {}", err);
     }
 }
 
-// ------------------------------------------------------
-// paragraphs
-// ------------------------------------------------------
-async fn paragraph_get(
-    Path(id): Path<i64>,
-    State(state): State<Arc<SharedState>>,
-) -> impl IntoResponse {
-    match Paragraph::find(id, &state.db) {
-        Ok(p) => {
-            let mut header = HeaderMap::new();
-            header.insert("X-Robots-Tag", "noindex".parse().unwrap());
+/// paragraph changes alter the derived article metrics
+fn refresh_article_metrics(article_id: i64, edited: Option<i64>, state: &SharedState) {
+    if let Err(err) = Article::refresh_metrics(article_id, edited, &state.db) {
+        tracing::error!("failed to refresh article metrics: {}", err);
+    }
+}
 
-            let rendered = match p.rendered {
-                Some(r) => r,
-                None => p.content,
-            };
-            Ok((StatusCode::OK, header, Html(rendered)))
+/// mirrors a saved article into the git tree, if the sync is enabled
+fn sync_article(article_id: i64, state: &SharedState) {
+    sync::mirror(article_id, state.db_path.clone());
+}
+
+/// follows up a paragraph change: articles refresh their metrics and mirror,
+/// pages only note the time for the sitemap
+fn paragraphs_changed(owner: Owner, edited: Option<i64>, state: &SharedState) {
+    match owner {
+        Owner::Article(article_id) => {
+            refresh_article_metrics(article_id, edited, state);
+            sync_article(article_id, state);
+        }
+        Owner::Page(page_id) => {
+            if let Err(err) = Page::touch(page_id, &state.db) {
+                tracing::error!("failed to update page: {}", err);
+            }
         }
-        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to get")),
     }
 }
 
-#[derive(serde::Deserialize)]
-struct ParagraphForm {
-    id: Option<i64>,
+// ------------------------------------------------------
+// preview tokens
+// ------------------------------------------------------
+fn render_preview_tokens(
     article_id: i64,
-    paragraph_type: ParagraphType,
-    content: String,
+    state: &SharedState,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let tokens = match PreviewToken::find_by_article(article_id, &state.db) {
+        Ok(tokens) => tokens,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let tmpl = match state.templates.get_template("components/preview_tokens.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { article_id => article_id, tokens => tokens }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
 }
 
-async fn paragraph_update(
+async fn preview_list(
     auth: Auth,
+    Path(id): Path<i64>,
     State(state): State<Arc<SharedState>>,
-    Form(form): Form<ParagraphForm>,
 ) -> impl IntoResponse {
     require_admin!(auth);
+    render_preview_tokens(id, &state)
+}
 
-    let id = match form.id {
-        Some(id) => id,
-        None => return Err((StatusCode::BAD_REQUEST, "missing id")),
-    };
-
-    let mut paragraph = match Paragraph::find(id, &state.db) {
-        Ok(p) => p,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
-    };
-
-    paragraph.content = form.content;
-    paragraph.paragraph_type = form.paragraph_type;
-
-    match paragraph.update(&state.db) {
-        Ok(_) => Ok((StatusCode::OK, Html("updated".to_string()))),
-        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to update")),
-    }
+#[derive(serde::Deserialize)]
+struct PreviewForm {
+    /// hours, empty for a token that never expires
+    expires_in: Option<String>,
 }
 
-async fn paragraph_create(
+async fn preview_create(
     auth: Auth,
+    Path(id): Path<i64>,
     State(state): State<Arc<SharedState>>,
-    Form(form): Form<ParagraphForm>,
+    Form(form): Form<PreviewForm>,
 ) -> impl IntoResponse {
     require_admin!(auth);
 
-    let paragraph = Paragraph {
-        id: form.id,
-        article_id: form.article_id,
-        paragraph_type: form.paragraph_type,
-        content: form.content,
-        position: 0,
-        title: "".to_string(),
-        description: "".to_string(),
-        rendered: None,
+    if Article::find(id, &state.db).is_err() {
+        return Err((StatusCode::NOT_FOUND, "not found"));
     }
-    .insert(&state.db);
 
-    match paragraph {
-        Ok(p) => Ok((StatusCode::CREATED, Html("created".to_string()))),
-        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to create")),
+    let expires_at = match form.expires_in.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(hours) => match hours.parse::<i64>() {
+            Ok(hours) if hours > 0 => Some(chrono::offset::Local::now().timestamp() + hours * 3600),
+            _ => return Err((StatusCode::BAD_REQUEST, "invalid expiry")),
+        },
+    };
+
+    if PreviewToken::new(id, expires_at).insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to create"));
     }
+
+    render_preview_tokens(id, &state)
 }
 
-async fn paragraph_delete(
+async fn preview_revoke(
+    auth: Auth,
     Path(id): Path<i64>,
     State(state): State<Arc<SharedState>>,
-    auth: Auth,
 ) -> impl IntoResponse {
     require_admin!(auth);
-    match Paragraph::delete(id, &state.db) {
-        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
-        Err(e) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+
+    match PreviewToken::revoke(id, &state.db) {
+        Ok(article_id) => render_preview_tokens(article_id, &state),
+        Err(_) => Err((StatusCode::NOT_FOUND, "not found")),
     }
 }
 
 // ------------------------------------------------------
-// files
+// series
 // ------------------------------------------------------
-async fn file_list(auth: Auth) -> impl IntoResponse {
-    require_admin!(auth);
-
-    let file_list = match crate::util::Util::load_files_rec("static/media".into()) {
-        Ok(file_list) => file_list,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to delete")),
+fn render_article_series(
+    article_id: i64,
+    state: &SharedState,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let (all, current, nav) = match (
+        Series::find_all(&state.db),
+        Series::find_by_article(article_id, &state.db),
+        Series::nav(article_id, false, &state.db),
+    ) {
+        (Ok(all), Ok(current), Ok(nav)) => (all, current, nav),
+        _ => return Err((StatusCode::BAD_REQUEST, "failed to get")),
     };
 
-    let file_string = file_list
-        .iter()
-        .map(|(_, path)| path.to_str().unwrap().to_string())
-        .map(|path| format!("<option value=/{} />", path))
-        .collect::<Vec<_>>()
-        .join("\n");
+    let tmpl = match state.templates.get_template("components/article_series.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
 
-    Ok(Html(file_string))
+    match tmpl.render(context! {
+        article_id => article_id,
+        all_series => all,
+        current => current,
+        nav => nav,
+    }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
 }
 
-async fn file_upload(
-    Path(id): Path<i64>,
+async fn article_series_get(
     auth: Auth,
-    mut multipart: axum::extract::Multipart,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
 ) -> impl IntoResponse {
     require_admin!(auth);
-    // todo safe the unsafe
-    while let Some(mut field) = multipart.next_field().await.unwrap() {
-        let name = field.file_name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-        let folder_path: PathBuf = format!("static/media/{}/", id).into();
-        std::fs::create_dir_all(&folder_path).unwrap();
-        let file_path = folder_path.join(name);
-        std::fs::write(file_path, data).unwrap();
-    }
+    render_article_series(id, &state)
+}
 
-    Ok(Html("file_upload".to_string()))
+#[derive(serde::Deserialize)]
+struct ArticleSeriesForm {
+    /// empty to leave the series
+    series_id: Option<String>,
+    /// 1 based, empty appends
+    part: Option<String>,
+    /// creates a new series and joins it
+    new_title: Option<String>,
 }
 
-// ------------------------------------------------------
-// stats
-// ------------------------------------------------------
-async fn get_stats(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+async fn article_series_update(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ArticleSeriesForm>,
+) -> impl IntoResponse {
     require_admin!(auth);
 
-    let stats = match Stats::get_last_days(3, &state.db) {
-        Ok(stats) => stats,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
-    };
-
-    let message_count = match ContactRequest::count_all(&state.db) {
-        Ok(count) => count,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
-    };
+    if Article::find(id, &state.db).is_err() {
+        return Err((StatusCode::NOT_FOUND, "not found"));
+    }
 
-    let top_ten = match ContactRequest::find_all_orderd(10, 0, &state.db) {
-        Ok(top_ten) => top_ten,
-        Err(e) => {
-            println!("error: {}", e);
-            return Err((StatusCode::BAD_REQUEST, "failed to get"));
+    let series_id = match form.new_title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(title) => {
+            let mut series = Series::new(title.to_string());
+            if series.insert(&state.db).is_err() {
+                return Err((StatusCode::CONFLICT, "series already exists"));
+            }
+            series.id
         }
+        None => form.series_id.as_deref().and_then(|id| id.parse().ok()),
     };
+    let part = form.part.as_deref().and_then(|part| part.parse().ok());
 
-    let tmpl = match state.templates.get_template("components/stats.html") {
-        Ok(tmpl) => tmpl,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
-    };
+    if Series::set_membership(id, series_id, part, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+    refresh_related(id, &state);
 
-    Ok(Html(
-        tmpl.render(context! {stats=>stats, message_count=>message_count, top_ten=>top_ten})
-            .unwrap(),
-    ))
+    render_article_series(id, &state)
 }
 
-// ------------------------------------------------------
-// Contact requests
-// ------------------------------------------------------
 #[derive(serde::Deserialize)]
-struct ContactForm {
-    email: String,
-    subject: String,
-    message: String,
+struct SeriesForm {
+    title: String,
+    slug: Option<String>,
+    description: Option<String>,
 }
 
-async fn post_contact(
+async fn series_update(
+    auth: Auth,
+    Path(id): Path<i64>,
     State(state): State<Arc<SharedState>>,
-    Form(form): Form<ContactForm>,
+    Form(form): Form<SeriesForm>,
 ) -> impl IntoResponse {
-    let mut contact_request = ContactRequest {
-        id: None,
-        created: chrono::offset::Local::now().timestamp(),
-        email: form.email,
-        subject: form.subject,
-        message: form.message,
+    require_admin!(auth);
+
+    let mut series = match Series::find(id, &state.db) {
+        Ok(series) => series,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
     };
 
-    contact_request.insert(&state.db);
+    series.slug = crate::render::slugify(
+        form.slug
+            .as_deref()
+            .filter(|slug| !slug.trim().is_empty())
+            .unwrap_or(&form.title),
+    );
+    series.title = form.title;
+    series.description = form.description.unwrap_or_default();
 
-    let tmpl = match state.templates.get_template("components/success.html") {
-        Ok(tmpl) => tmpl,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
-    };
+    if series.update(&state.db).is_err() {
+        return Err((StatusCode::CONFLICT, "slug already in use"));
+    }
 
-    Ok(Html(tmpl.render(context! {}).unwrap()))
+    // the slug may have changed
+    let mut header = HeaderMap::new();
+    header.insert(
+        "HX-Redirect",
+        format!("/series/{}", series.slug).parse().unwrap(),
+    );
+    Ok((header, Html("updated".to_string())))
 }
 
-// ------------------------------------------------------
-// Contact message get
-// ------------------------------------------------------
-async fn get_contact_message(
-    Path(id): Path<i64>,
+async fn series_delete(
     auth: Auth,
+    Path(id): Path<i64>,
     State(state): State<Arc<SharedState>>,
 ) -> impl IntoResponse {
     require_admin!(auth);
 
-    let message = match ContactRequest::find(id, &state.db) {
-        Ok(count) => count,
+    // the former parts lose their series bonus
+    let parts = Article::find_by_series(id, &state.db).unwrap_or_default();
+    if Series::delete(id, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to delete"));
+    }
+    for part in parts.iter().filter_map(|part| part.id) {
+        refresh_related(part, &state);
+    }
+
+    let mut header = HeaderMap::new();
+    header.insert("HX-Redirect", "/".parse().unwrap());
+    Ok((header, Html("deleted".to_string())))
+}
+
+// ------------------------------------------------------
+// comments
+// ------------------------------------------------------
+/// per ip, in [COMMENT_WINDOW] seconds
+const COMMENT_LIMIT: usize = 5;
+const COMMENT_WINDOW: i64 = 600;
+const MAX_AUTHOR_LEN: usize = 80;
+const MAX_COMMENT_LEN: usize = 5000;
+
+fn render_comments(
+    article: &Article,
+    auth: &Auth,
+    message: Option<&str>,
+    state: &SharedState,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let id = article.id.unwrap_or_default();
+    let comments = match Comment::find_by_article(id, CommentStatus::Approved, &state.db) {
+        Ok(comments) => comments,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
     };
-    let tmpl = match state.templates.get_template("components/mail.html") {
+    let count = comments.len();
+
+    let tmpl = match state.templates.get_template("components/comments.html") {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
     };
 
-    Ok(Html(tmpl.render(context! { mail=>message }).unwrap()))
+    let token = spam::FormToken::new();
+    match tmpl.render(context! {
+        article => article,
+        auth => auth,
+        threads => Comment::thread(comments),
+        count => count,
+        message => message,
+        token => token.encode(),
+        question => token.question(),
+        honeypot => spam::HONEYPOT_FIELD,
+    }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+/// the article, if the client may see it
+fn commented_article(id: i64, auth: &Auth, state: &SharedState) -> Result<Article, (StatusCode, &'static str)> {
+    match Article::find(id, &state.db) {
+        Ok(article) if article.is_public() || auth.is_admin() => Ok(article),
+        _ => Err((StatusCode::NOT_FOUND, "not found")),
+    }
+}
+
+async fn comment_list(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    let article = commented_article(id, &auth, &state)?;
+    render_comments(&article, &auth, None, &state)
+}
+
+#[derive(serde::Deserialize)]
+struct CommentForm {
+    author: String,
+    email: Option<String>,
+    body: String,
+    parent_id: Option<String>,
+    notify: Option<String>,
+    /// signed timestamp and challenge, see [spam::FormToken]
+    token: Option<String>,
+    answer: Option<String>,
+    /// honeypot, see [spam::HONEYPOT_FIELD]
+    website: Option<String>,
+}
+
+async fn comment_create(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(form): Form<CommentForm>,
+) -> impl IntoResponse {
+    let article = commented_article(id, &auth, &state)?;
+    if !article.comments_open {
+        return Err((StatusCode::FORBIDDEN, "comments are closed"));
+    }
+
+    const THANKS: &str = "Thanks! Your comment shows up once it is approved.";
+
+    // bots get the same answer as everyone else
+    if spam::honeypot_filled(&form.website) {
+        return render_comments(&article, &auth, Some(THANKS), &state);
+    }
+
+    // admins skip the challenge, like the rate limit below
+    let token = match form.token.as_deref().and_then(spam::FormToken::decode) {
+        _ if auth.is_admin() => None,
+        Some(token) if token.age() > spam::MAX_FORM_SECONDS => {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "the form expired, please reload the page"));
+        }
+        Some(token) if token.age() < spam::MIN_FORM_SECONDS => {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "that was fast, please try again"));
+        }
+        Some(token) if !token.is_answer(form.answer.as_deref().unwrap_or_default()) => {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "wrong answer, please try again"));
+        }
+        Some(token) => Some(token),
+        None => return Err((StatusCode::UNPROCESSABLE_ENTITY, "the form expired, please reload the page")),
+    };
+    let seconds_taken = token.map(|token| token.age()).unwrap_or(spam::MAX_FORM_SECONDS);
+
+    let author = form.author.trim();
+    let email = form.email.as_deref().unwrap_or_default().trim().to_lowercase();
+    let body = form.body.trim();
+    if author.is_empty() || author.chars().count() > MAX_AUTHOR_LEN {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "please enter a name of up to 80 characters"));
+    }
+    if !email.is_empty() && !spam::valid_email(&email) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "please enter a valid email"));
+    }
+    if body.is_empty() || body.chars().count() > MAX_COMMENT_LEN {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "please write up to 5000 characters"));
+    }
+
+    // replies only go to visible comments of the same article
+    let parent_id = form.parent_id.as_deref().and_then(|id| id.parse::<i64>().ok());
+    if let Some(parent_id) = parent_id {
+        match Comment::find(parent_id, &state.db) {
+            Ok(parent) if parent.article_id == id && parent.status == CommentStatus::Approved => (),
+            _ => return Err((StatusCode::BAD_REQUEST, "cannot reply to this comment")),
+        }
+    }
+
+    // only once the comment is valid, a reader fixing a typo keeps their form
+    if token.is_some_and(|token| !state.used_tokens.spend(&token)) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "this form was already sent, please reload the page"));
+    }
+
+    let ip = addr.ip().to_string();
+    if !auth.is_admin()
+        && !state
+            .rate_limit
+            .allow(&format!("comment:{}", ip), COMMENT_LIMIT, COMMENT_WINDOW)
+    {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "too many comments, try again later"));
+    }
+
+    let banned = match Ban::is_banned(&email, &ip, &state.db) {
+        Ok(banned) => banned,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to create")),
+    };
+
+    // the author name stands in for the subject, links in there are a strong hint
+    let spam_score = spam::score(&email, author, body, seconds_taken);
+
+    let mut comment = Comment {
+        id: None,
+        article_id: id,
+        parent_id,
+        author: author.to_string(),
+        notify: form.notify.is_some() && !email.is_empty(),
+        email,
+        body: body.to_string(),
+        status: match (auth.is_admin(), banned) {
+            (true, _) => CommentStatus::Approved,
+            (false, true) => CommentStatus::Spam,
+            (false, false) if spam_score >= spam::SPAM_THRESHOLD => CommentStatus::Spam,
+            (false, false) => CommentStatus::Pending,
+        },
+        ip,
+        created_at: chrono::offset::Local::now().timestamp(),
+        spam_score,
+    };
+
+    if comment.insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to create"));
+    }
+
+    if comment.status != CommentStatus::Spam {
+        webhooks::emit(WebhookEvent::CommentPosted, webhooks::comment_data(&comment, &article), &state.db);
+    }
+
+    match comment.status {
+        CommentStatus::Approved => {
+            notify_reply(&comment, &article, &state);
+            render_comments(&article, &auth, None, &state)
+        }
+        CommentStatus::Pending => {
+            notify::comment_posted(&comment, &article.title, &state.site.settings());
+            render_comments(&article, &auth, Some(THANKS), &state)
+        }
+        _ => render_comments(&article, &auth, Some(THANKS), &state),
+    }
+}
+
+/// mails the parent's author about an approved reply
+fn notify_reply(comment: &Comment, article: &Article, state: &SharedState) {
+    if let Some(parent) = comment
+        .parent_id
+        .and_then(|id| Comment::find(id, &state.db).ok())
+    {
+        notify::comment_reply(
+            &parent,
+            comment,
+            &article.title,
+            &article.url(),
+            &state.site.settings(),
+        );
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CommentToggleForm {
+    open: bool,
+}
+
+async fn comment_toggle(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<CommentToggleForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if Article::set_comments_open(id, form.open, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    let article = commented_article(id, &auth, &state)?;
+    render_comments(&article, &auth, None, &state)
+}
+
+#[derive(serde::Deserialize)]
+struct ModerateForm {
+    status: CommentStatus,
+}
+
+async fn comment_moderate(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ModerateForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let comment = match Comment::find(id, &state.db) {
+        Ok(comment) => comment,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    if Comment::set_status(id, form.status, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    if form.status == CommentStatus::Approved && comment.status != CommentStatus::Approved {
+        if let Ok(article) = Article::find(comment.article_id, &state.db) {
+            notify_reply(&comment, &article, &state);
+        }
+    }
+
+    Ok((StatusCode::OK, Html(form.status.as_str().to_string())))
+}
+
+async fn comment_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    match Comment::delete(id, &state.db) {
+        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct BanForm {
+    kind: BanKind,
+    value: String,
+}
+
+async fn ban_create(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<BanForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if form.value.trim().is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "nothing to ban"));
+    }
+
+    let mut ban = Ban::new(form.kind, form.value);
+    if ban.insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to create"));
+    }
+    if let Err(err) = Comment::flag_banned(&ban, &state.db) {
+        tracing::error!("failed to flag banned comments: {}", err);
+    }
+
+    // pending comments of the banned sender moved to spam
+    let mut header = HeaderMap::new();
+    header.insert("HX-Refresh", "true".parse().unwrap());
+    Ok((header, Html("banned".to_string())))
+}
+
+async fn ban_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    match Ban::delete(id, &state.db) {
+        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+    }
+}
+
+// ------------------------------------------------------
+// trash
+// ------------------------------------------------------
+async fn trash_restore(
+    auth: Auth,
+    Path((kind, id)): Path<(TrashKind, i64)>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    match trash::restore(kind, id, &state.db) {
+        Ok(owner) => {
+            if let Some(owner) = owner {
+                paragraphs_changed(owner, None, &state);
+            }
+            match kind {
+                TrashKind::Article => refresh_related(id, &state),
+                TrashKind::Page => refresh_menu(&state),
+                _ => (),
+            }
+            Ok((StatusCode::OK, Html("restored".to_string())))
+        }
+        Err(_) => Err((StatusCode::NOT_FOUND, "not in trash")),
+    }
+}
+
+async fn trash_purge(
+    auth: Auth,
+    Path((kind, id)): Path<(TrashKind, i64)>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    match trash::purge(kind, id, &state.db) {
+        Ok(_) => Ok((StatusCode::OK, Html("purged".to_string()))),
+        Err(_) => Err((StatusCode::NOT_FOUND, "not in trash")),
+    }
+}
+
+// ------------------------------------------------------
+// redirects
+// ------------------------------------------------------
+fn render_redirects(state: &SharedState) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let rules = match RedirectRule::find_all(&state.db) {
+        Ok(rules) => rules,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let tmpl = match state.templates.get_template("components/redirect_list.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { rules => rules }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+async fn redirect_list(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+    render_redirects(&state)
+}
+
+#[derive(serde::Deserialize)]
+struct RedirectForm {
+    source: String,
+    target: String,
+    #[serde(default)]
+    permanent: bool,
+}
+
+async fn redirect_create(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<RedirectForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    // rules only apply to paths no route answers, see `pages::redirect_fallback`
+    let source = match form.source.trim().trim_end_matches('/') {
+        "" => "/",
+        source => source,
+    };
+    if !source.starts_with('/') || source.starts_with("/api/") || source.starts_with("/static/") {
+        return Err((StatusCode::BAD_REQUEST, "source must be a free local path"));
+    }
+
+    let target = form.target.trim();
+    if !(target.starts_with('/') || target.starts_with("https://") || target.starts_with("http://"))
+    {
+        return Err((StatusCode::BAD_REQUEST, "target must be a path or url"));
+    }
+
+    let mut rule = RedirectRule {
+        id: None,
+        source: source.to_string(),
+        target: target.to_string(),
+        permanent: form.permanent,
+        created_at: chrono::offset::Local::now().timestamp(),
+    };
+
+    if rule.insert(&state.db).is_err() {
+        return Err((StatusCode::CONFLICT, "source already redirected"));
+    }
+
+    render_redirects(&state)
+}
+
+async fn redirect_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if RedirectRule::delete(id, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to delete"));
+    }
+
+    render_redirects(&state)
+}
+
+/// frees an old alias, links to it stop redirecting
+async fn alias_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    match ArticleAlias::delete(id, &state.db) {
+        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+    }
+}
+
+// ------------------------------------------------------
+// pages
+// ------------------------------------------------------
+fn render_pages(state: &SharedState) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let pages = match Page::find_all(&state.db) {
+        Ok(pages) => pages,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let tmpl = match state.templates.get_template("components/page_list.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { pages => pages }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+/// the header navigation is built from the pages
+fn refresh_menu(state: &SharedState) {
+    if let Err(err) = state.menu.refresh(&state.db) {
+        tracing::error!("failed to refresh the menu: {}", err);
+    }
+}
+
+async fn page_list(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+    render_pages(&state)
+}
+
+#[derive(serde::Deserialize)]
+struct PageCreateForm {
+    title: String,
+}
+
+/// creates an unpublished page and opens it for editing
+async fn page_create(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<PageCreateForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if form.title.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "title is required"));
+    }
+
+    let mut page = Page::new(form.title.trim().to_string());
+    page.slug = match Page::normalize_slug(&page.slug) {
+        Ok(slug) => slug,
+        Err(err) => return Err((StatusCode::BAD_REQUEST, err)),
+    };
+    if Page::slug_taken(&page.slug, None, &state.db).unwrap_or(true) {
+        return Err((StatusCode::CONFLICT, "slug already in use"));
+    }
+
+    if page.insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to create"));
+    }
+
+    let mut header = HeaderMap::new();
+    header.insert("HX-Redirect", page.url().parse().unwrap());
+    Ok((header, Html("created".to_string())))
+}
+
+#[derive(serde::Deserialize)]
+struct PageForm {
+    title: String,
+    /// empty for the front page
+    #[serde(default)]
+    slug: String,
+    description: Option<String>,
+    template: PageTemplate,
+    #[serde(default)]
+    published: bool,
+    /// empty keeps the page out of the menu
+    menu_position: Option<String>,
+}
+
+async fn page_update(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<PageForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let mut page = match Page::find(id, &state.db) {
+        Ok(page) => page,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    if form.title.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "title is required"));
+    }
+
+    page.slug = match Page::normalize_slug(&form.slug) {
+        Ok(slug) => slug,
+        Err(err) => return Err((StatusCode::BAD_REQUEST, err)),
+    };
+    if Page::slug_taken(&page.slug, Some(id), &state.db).unwrap_or(true) {
+        return Err((StatusCode::CONFLICT, "slug already in use"));
+    }
+
+    page.title = form.title.trim().to_string();
+    page.description = form.description.unwrap_or_default();
+    page.template = form.template;
+    page.published = form.published;
+    page.menu_position = form
+        .menu_position
+        .as_deref()
+        .and_then(|position| position.trim().parse().ok());
+    page.updated_at = chrono::offset::Local::now().timestamp();
+
+    if page.update(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+    refresh_menu(&state);
+
+    // the slug may have changed
+    let mut header = HeaderMap::new();
+    header.insert("HX-Redirect", page.url().parse().unwrap());
+    Ok((header, Html("updated".to_string())))
+}
+
+async fn page_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if Page::delete(id, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to delete"));
+    }
+    refresh_menu(&state);
+
+    let mut header = HeaderMap::new();
+    header.insert("HX-Redirect", "/pages".parse().unwrap());
+    Ok((header, Html("deleted".to_string())))
+}
+
+// ------------------------------------------------------
+// paragraphs
+// ------------------------------------------------------
+async fn paragraph_get(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    let owner = match Paragraph::find(id, &state.db) {
+        Ok(p) => p.owner(),
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    // heading slugs depend on the other paragraphs of the article
+    let mut paragraphs = match Paragraph::find_by_owner(owner, &state.db) {
+        Ok(p) => p,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+    if auth.is_admin() {
+        paragraphs.iter_mut().for_each(|p| p.render(true));
+    }
+    Paragraph::anchor_headings(&mut paragraphs);
+
+    match paragraphs.into_iter().find(|p| p.id == Some(id)) {
+        Some(p) => {
+            let mut header = HeaderMap::new();
+            header.insert("X-Robots-Tag", "noindex".parse().unwrap());
+
+            let rendered = match p.rendered {
+                Some(r) => r,
+                None => p.content,
+            };
+            Ok((StatusCode::OK, header, Html(rendered)))
+        }
+        None => Err((StatusCode::BAD_REQUEST, "failed to get")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ParagraphForm {
+    id: Option<i64>,
+    #[serde(default)]
+    article_id: i64,
+    /// set instead of `article_id` for paragraphs of a page
+    page_id: Option<i64>,
+    paragraph_type: ParagraphType,
+    content: String,
+    after: Option<String>,
+    before: Option<String>,
+}
+
+impl ParagraphForm {
+    fn placement(&self) -> Placement {
+        let parse = |v: &Option<String>| v.as_ref().and_then(|v| v.parse::<i64>().ok());
+        match (parse(&self.after), parse(&self.before)) {
+            (Some(id), _) => Placement::After(id),
+            (None, Some(id)) => Placement::Before(id),
+            (None, None) => Placement::End,
+        }
+    }
+}
+
+async fn paragraph_update(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ParagraphForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let id = match form.id {
+        Some(id) => id,
+        None => return Err((StatusCode::BAD_REQUEST, "missing id")),
+    };
+
+    let mut paragraph = match Paragraph::find(id, &state.db) {
+        Ok(p) => p,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
+    };
+
+    paragraph.content = form.content;
+    paragraph.paragraph_type = form.paragraph_type;
+
+    if let Err(err) = paragraph.validate() {
+        return Err((StatusCode::BAD_REQUEST, err));
+    }
+
+    match paragraph.update(&state.db) {
+        Ok(_) => {
+            paragraphs_changed(paragraph.owner(), Some(id), &state);
+            Ok((StatusCode::OK, Html("updated".to_string())))
+        }
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to update")),
+    }
+}
+
+async fn paragraph_create(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ParagraphForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if let Some(page_id) = form.page_id {
+        if Page::find(page_id, &state.db).is_err() {
+            return Err((StatusCode::BAD_REQUEST, "page not found"));
+        }
+    }
+
+    let placement = form.placement();
+    let mut paragraph = Paragraph {
+        id: None,
+        article_id: match form.page_id {
+            Some(_) => None,
+            None => Some(form.article_id),
+        },
+        page_id: form.page_id,
+        paragraph_type: form.paragraph_type,
+        content: form.content,
+        position: 0,
+        title: "".to_string(),
+        description: "".to_string(),
+        rendered: None,
+    };
+
+    if let Err(err) = paragraph.validate() {
+        return Err((StatusCode::BAD_REQUEST, err));
+    }
+
+    match paragraph.insert_at(placement, &state.db) {
+        Ok(_) => {
+            paragraphs_changed(paragraph.owner(), paragraph.id, &state);
+            Ok((StatusCode::CREATED, Html("created".to_string())))
+        }
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to create")),
+    }
+}
+
+async fn paragraph_delete(
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    auth: Auth,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let owner = match Paragraph::find(id, &state.db) {
+        Ok(p) => p.owner(),
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
+    };
+
+    match Paragraph::delete(id, &state.db) {
+        Ok(_) => {
+            paragraphs_changed(owner, None, &state);
+            Ok((StatusCode::OK, Html("deleted".to_string())))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ParagraphOrderForm {
+    /// comma separated paragraph ids in their new order
+    order: String,
+}
+
+async fn paragraph_order(
+    auth: Auth,
+    Path(article_id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ParagraphOrderForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+    reorder_paragraphs(Owner::Article(article_id), &form, &state)
+}
+
+async fn page_paragraph_order(
+    auth: Auth,
+    Path(page_id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ParagraphOrderForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+    reorder_paragraphs(Owner::Page(page_id), &form, &state)
+}
+
+fn reorder_paragraphs(
+    owner: Owner,
+    form: &ParagraphOrderForm,
+    state: &SharedState,
+) -> Result<(StatusCode, Html<String>), (StatusCode, &'static str)> {
+    let ids = match form
+        .order
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| id.trim().parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ids) => ids,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "invalid order")),
+    };
+
+    match Paragraph::reorder(owner, &ids, &state.db) {
+        Ok(_) => {
+            paragraphs_changed(owner, None, state);
+            Ok((StatusCode::OK, Html("updated".to_string())))
+        }
+        Err(_) => Err((StatusCode::BAD_REQUEST, "order does not match paragraphs")),
+    }
+}
+
+async fn paragraph_duplicate(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    // the copy must pass what a new paragraph would, e.g. a wasm demo whose files were deleted
+    match Paragraph::find(id, &state.db) {
+        Ok(p) => {
+            if let Err(err) = p.validate() {
+                return Err((StatusCode::BAD_REQUEST, err));
+            }
+        }
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
+    }
+
+    let mut paragraph = match Paragraph::duplicate(id, &state.db) {
+        Ok(p) => p,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to duplicate")),
+    };
+    paragraph.render(true);
+
+    paragraphs_changed(paragraph.owner(), paragraph.id, &state);
+
+    let tmpl = match state.templates.get_template("components/paragraph.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "missing template")),
+    };
+
+    match tmpl.render(context! { paragraph => paragraph, auth => auth }) {
+        Ok(html) => Ok((StatusCode::CREATED, Html(html))),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ParagraphMoveForm {
+    article_id: i64,
+}
+
+async fn paragraph_move(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ParagraphMoveForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if Article::find(form.article_id, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "article not found"));
+    }
+
+    let source_id = match Paragraph::find(id, &state.db) {
+        Ok(p) => match (p.owner(), p.validate()) {
+            (Owner::Page(_), _) => {
+                return Err((StatusCode::BAD_REQUEST, "page paragraphs stay on their page"))
+            }
+            (Owner::Article(source_id), Ok(_)) => source_id,
+            (_, Err(err)) => return Err((StatusCode::BAD_REQUEST, err)),
+        },
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "not found")),
+    };
+
+    match Paragraph::move_to(id, form.article_id, &state.db) {
+        Ok(_) => {
+            refresh_article_metrics(source_id, None, &state);
+            refresh_article_metrics(form.article_id, Some(id), &state);
+            sync_article(source_id, &state);
+            sync_article(form.article_id, &state);
+            Ok((StatusCode::OK, Html("moved".to_string())))
+        }
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to move")),
+    }
+}
+
+// ------------------------------------------------------
+// files
+// ------------------------------------------------------
+async fn file_list(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let media = match Media::find_all(&state.db) {
+        Ok(media) => media,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to list files")),
+    };
+
+    let file_string = media
+        .iter()
+        .map(|m| format!("<option value=\"{}\" />", m.path))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(Html(file_string))
+}
+
+async fn file_upload(
+    Path(id): Path<i64>,
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    mut multipart: axum::extract::Multipart,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let uploaded_by = auth.user.clone().unwrap_or_default();
+    let mut stored = Vec::new();
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "broken upload")),
+        };
+
+        match upload::store_field(field, id, &uploaded_by, &state).await {
+            Ok(media) => {
+                let has_variants = media
+                    .id
+                    .and_then(|id| MediaVariant::find_by_media(id, &state.db).ok())
+                    .is_some_and(|variants| !variants.is_empty());
+                if let (Some(id), false) = (media.id, has_variants) {
+                    variants::spawn(id, state.db_path.clone());
+                }
+                stored.push(media.path)
+            }
+            Err(err) => {
+                tracing::warn!("rejected upload: {:?}", err);
+                let status = match err {
+                    UploadError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+                    UploadError::UnsupportedType | UploadError::ContentMismatch => {
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE
+                    }
+                    UploadError::Io(_) | UploadError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                return Err((status, err.message()));
+            }
+        }
+    }
+
+    Ok(Html(stored.join("\n")))
+}
+
+// ------------------------------------------------------
+// media library
+// ------------------------------------------------------
+#[derive(serde::Serialize)]
+struct MediaEntry {
+    stem: String,
+    is_image: bool,
+    references: i64,
+    variants: usize,
+    media: Media,
+}
+
+impl MediaEntry {
+    fn load(media: Media, con: &rusqlite::Connection) -> Self {
+        MediaEntry {
+            stem: media.stem().to_string(),
+            is_image: media.mime.starts_with("image/"),
+            references: media.reference_count(con).unwrap_or_default(),
+            variants: media
+                .id
+                .and_then(|id| MediaVariant::find_by_media(id, con).ok())
+                .map(|variants| variants.len())
+                .unwrap_or_default(),
+            media,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct MediaQuery {
+    q: Option<String>,
+    offset: Option<i64>,
+}
+
+const MEDIA_PAGE_SIZE: i64 = 50;
+
+async fn media_list(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<MediaQuery>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let media = match Media::search(
+        query.q.as_deref().unwrap_or_default(),
+        MEDIA_PAGE_SIZE,
+        query.offset.unwrap_or_default(),
+        &state.db,
+    ) {
+        Ok(media) => media,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to search media")),
+    };
+
+    let entries = media
+        .into_iter()
+        .map(|m| MediaEntry::load(m, &state.db))
+        .collect::<Vec<_>>();
+
+    let tmpl = match state.templates.get_template("components/media_browser.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { entries => entries }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct MediaForm {
+    name: Option<String>,
+    alt: Option<String>,
+}
+
+async fn media_update(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<MediaForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let mut media = match Media::find(id, &state.db) {
+        Ok(m) => m,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    if let Some(alt) = form.alt {
+        media.alt = alt;
+    }
+
+    let current_stem = media.stem().to_string();
+    match form.name.filter(|name| !name.is_empty() && *name != current_stem) {
+        Some(name) => {
+            // renaming would break every link to the old path
+            if media.reference_count(&state.db).unwrap_or(1) > 0 {
+                return Err((StatusCode::CONFLICT, "file is still referenced"));
+            }
+            // variants are named after the original, regenerate them under the new name
+            if variants::remove(&media, &state.db).is_err() {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to remove variants"));
+            }
+            if let Err(err) = upload::rename(&mut media, &name, &state.db) {
+                return Err((StatusCode::BAD_REQUEST, err.message()));
+            }
+            variants::spawn(id, state.db_path.clone());
+        }
+        None => {
+            if media.update(&state.db).is_err() {
+                return Err((StatusCode::BAD_REQUEST, "failed to update"));
+            }
+        }
+    }
+
+    let tmpl = match state.templates.get_template("components/media_item.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { entry => MediaEntry::load(media, &state.db) }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+async fn media_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let media = match Media::find(id, &state.db) {
+        Ok(m) => m,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    if media.reference_count(&state.db).unwrap_or(1) > 0 {
+        return Err((StatusCode::CONFLICT, "file is still referenced"));
+    }
+
+    // the file stays on disk until the trash is purged, see [trash::purge_media]
+    match Media::delete(id, &state.db) {
+        Ok(_) => Ok((StatusCode::OK, Html("deleted".to_string()))),
+        Err(_) => Err((StatusCode::BAD_REQUEST, "failed to delete")),
+    }
+}
+
+// ------------------------------------------------------
+// stats
+// ------------------------------------------------------
+async fn get_stats(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let stats = match Stats::get_last_days(3, &state.db) {
+        Ok(stats) => stats,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let message_count = match ContactRequest::count_all(&state.db) {
+        Ok(count) => count,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let unread = ContactRequest::count_unread(&state.db).unwrap_or_default();
+    let pages = Page::find_all(&state.db).unwrap_or_default();
+
+    let top_ten = match ContactRequest::find_all_orderd(10, 0, &state.db) {
+        Ok(top_ten) => top_ten,
+        Err(e) => {
+            println!("error: {}", e);
+            return Err((StatusCode::BAD_REQUEST, "failed to get"));
+        }
+    };
+
+    let tmpl = match state.templates.get_template("components/stats.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    Ok(Html(
+        tmpl.render(context! {stats=>stats, pages=>pages, message_count=>message_count, unread=>unread, top_ten=>top_ten})
+            .unwrap(),
+    ))
+}
+
+// ------------------------------------------------------
+// settings
+// ------------------------------------------------------
+async fn settings_update(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(mut settings): Form<Settings>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    settings.base_url = settings.base_url.trim().trim_end_matches('/').to_string();
+    if !(settings.base_url.is_empty()
+        || settings.base_url.starts_with("https://")
+        || settings.base_url.starts_with("http://"))
+    {
+        return Err((StatusCode::BAD_REQUEST, "base url must start with http:// or https://"));
+    }
+
+    if settings.save(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to save"));
+    }
+    state.site.set(settings);
+
+    Ok((StatusCode::OK, Html("saved".to_string())))
+}
+
+// ------------------------------------------------------
+// Contact requests
+// ------------------------------------------------------
+/// per ip, in [CONTACT_WINDOW] seconds
+const CONTACT_LIMIT: usize = 3;
+const CONTACT_WINDOW: i64 = 3600;
+const MAX_SUBJECT_LEN: usize = 200;
+const MAX_MESSAGE_LEN: usize = 5000;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ContactForm {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    message: String,
+    #[serde(skip_serializing)]
+    token: Option<String>,
+    #[serde(skip_serializing)]
+    answer: Option<String>,
+    /// honeypot, see [spam::HONEYPOT_FIELD]
+    #[serde(skip_serializing)]
+    website: Option<String>,
+}
+
+impl ContactForm {
+    /// field name -> error, empty if the form is fine
+    fn validate(&self) -> HashMap<&'static str, &'static str> {
+        let mut errors = HashMap::new();
+        if !spam::valid_email(self.email.trim()) {
+            errors.insert("email", "please enter a valid email address");
+        }
+        if self.subject.trim().is_empty() || self.subject.chars().count() > MAX_SUBJECT_LEN {
+            errors.insert("subject", "please enter a subject of up to 200 characters");
+        }
+        if self.message.trim().is_empty() || self.message.chars().count() > MAX_MESSAGE_LEN {
+            errors.insert("message", "please write a message of up to 5000 characters");
+        }
+        errors
+    }
+}
+
+/// the contact form again, with the reader's input, the errors and a fresh challenge
+fn render_contact_form(
+    status: StatusCode,
+    form: &ContactForm,
+    errors: HashMap<&'static str, &'static str>,
+    state: &SharedState,
+) -> Result<(StatusCode, Html<String>), (StatusCode, &'static str)> {
+    let tmpl = match state.templates.get_template("components/contact_form.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    let token = spam::FormToken::new();
+    match tmpl.render(context! {
+        values => form,
+        errors => errors,
+        token => token.encode(),
+        question => token.question(),
+        honeypot => spam::HONEYPOT_FIELD,
+    }) {
+        Ok(html) => Ok((status, Html(html))),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+fn render_contact_success(
+    state: &SharedState,
+) -> Result<(StatusCode, Html<String>), (StatusCode, &'static str)> {
+    let tmpl = match state.templates.get_template("components/success.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    Ok((StatusCode::OK, Html(tmpl.render(context! {}).unwrap())))
+}
+
+async fn post_contact(
+    State(state): State<Arc<SharedState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(form): Form<ContactForm>,
+) -> impl IntoResponse {
+    // bots get the same answer as everyone else, their message is dropped
+    if spam::honeypot_filled(&form.website) {
+        return render_contact_success(&state);
+    }
+
+    let token = match form.token.as_deref().and_then(spam::FormToken::decode) {
+        Some(token) if token.age() <= spam::MAX_FORM_SECONDS => token,
+        _ => {
+            let errors = HashMap::from([("form", "the form expired, please try again")]);
+            return render_contact_form(StatusCode::UNPROCESSABLE_ENTITY, &form, errors, &state);
+        }
+    };
+    if token.age() < spam::MIN_FORM_SECONDS {
+        let errors = HashMap::from([("form", "that was fast, please try again")]);
+        return render_contact_form(StatusCode::UNPROCESSABLE_ENTITY, &form, errors, &state);
+    }
+
+    let mut errors = form.validate();
+    if !token.is_answer(form.answer.as_deref().unwrap_or_default()) {
+        errors.insert("answer", "wrong answer, please try again");
+    }
+    if !errors.is_empty() {
+        return render_contact_form(StatusCode::UNPROCESSABLE_ENTITY, &form, errors, &state);
+    }
+
+    if !state.used_tokens.spend(&token) {
+        let errors = HashMap::from([("form", "this form was already sent, please reload the page")]);
+        return render_contact_form(StatusCode::UNPROCESSABLE_ENTITY, &form, errors, &state);
+    }
+
+    // only valid posts count, typos should not lock anyone out
+    let ip = addr.ip().to_string();
+    if !state
+        .rate_limit
+        .allow(&format!("contact:{}", ip), CONTACT_LIMIT, CONTACT_WINDOW)
+    {
+        let errors = HashMap::from([("form", "too many messages, please try again later")]);
+        return render_contact_form(StatusCode::TOO_MANY_REQUESTS, &form, errors, &state);
+    }
+
+    let banned = Ban::is_banned(&form.email, &ip, &state.db).unwrap_or(false);
+    if banned {
+        return render_contact_success(&state);
+    }
+
+    let mut contact_request = ContactRequest {
+        id: None,
+        created: chrono::offset::Local::now().timestamp(),
+        spam_score: spam::score(&form.email, &form.subject, &form.message, token.age()),
+        email: form.email.trim().to_string(),
+        subject: form.subject.trim().to_string(),
+        message: form.message.clone(),
+        read_at: None,
+        archived: false,
+        labels: String::new(),
+    };
+
+    if contact_request.insert(&state.db).is_err() {
+        let errors = HashMap::from([("form", "failed to send, please try again")]);
+        return render_contact_form(StatusCode::INTERNAL_SERVER_ERROR, &form, errors, &state);
+    }
+
+    if contact_request.spam_score < spam::SPAM_THRESHOLD {
+        notify::contact_received(&contact_request, &state.site.settings());
+        webhooks::emit(WebhookEvent::ContactReceived, webhooks::contact_data(&contact_request), &state.db);
+    }
+
+    render_contact_success(&state)
+}
+
+// ------------------------------------------------------
+// Contact message get
+// ------------------------------------------------------
+fn render_contact_message(
+    id: i64,
+    state: &SharedState,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let (message, replies) = match (
+        ContactRequest::find(id, &state.db),
+        ContactReply::find_by_contact(id, &state.db),
+    ) {
+        (Ok(message), Ok(replies)) => (message, replies),
+        _ => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+    let tmpl = match state.templates.get_template("components/mail.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! {
+        mail => message,
+        replies => replies,
+        mail_enabled => mail::is_enabled(),
+    }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+/// opening a message marks it read
+async fn get_contact_message(
+    Path(id): Path<i64>,
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if ContactRequest::set_read(&[id], true, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    render_contact_message(id, &state)
+}
+
+#[derive(serde::Deserialize)]
+struct LabelForm {
+    labels: String,
+}
+
+async fn contact_labels(
+    Path(id): Path<i64>,
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<LabelForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if ContactRequest::set_labels(id, &form.labels, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    render_contact_message(id, &state)
+}
+
+#[derive(serde::Deserialize)]
+struct ReplyForm {
+    body: String,
+}
+
+/// mails an answer to the sender and stores it in the thread, failed ones included
+async fn contact_reply(
+    Path(id): Path<i64>,
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<ReplyForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let config = MailConfig::from_env();
+    if !config.is_enabled() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "mail is not configured"));
+    }
+    if form.body.trim().is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "the reply is empty"));
+    }
+
+    let (contact, replies) = match (
+        ContactRequest::find(id, &state.db),
+        ContactReply::find_by_contact(id, &state.db),
+    ) {
+        (Ok(contact), Ok(replies)) => (contact, replies),
+        _ => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    let message_id = config.message_id(&format!("contact-{}-{:016x}", id, rand::random::<u64>()));
+    let mail = notify::contact_answer(&contact, &replies, form.body.trim(), &message_id, &config);
+
+    let error = match tokio::task::spawn_blocking(move || mail.send(&config)).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some("mail job panicked".to_string()),
+    };
+
+    let mut reply = ContactReply {
+        id: None,
+        contact_id: id,
+        body: form.body.trim().to_string(),
+        message_id,
+        sent_by: auth.user.clone().unwrap_or_default(),
+        sent_at: chrono::offset::Local::now().timestamp(),
+        error,
+    };
+    if reply.insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to store the reply"));
+    }
+    if ContactRequest::set_read(&[id], true, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    render_contact_message(id, &state)
+}
+
+// ------------------------------------------------------
+// inbox
+// ------------------------------------------------------
+const INBOX_PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+struct InboxQuery {
+    search: Option<String>,
+    label: Option<String>,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    page: i64,
+}
+
+impl InboxQuery {
+    fn filter(&self) -> InboxFilter {
+        InboxFilter {
+            search: self.search.clone(),
+            label: self.label.clone(),
+            archived: self.archived,
+        }
+    }
+}
+
+fn render_inbox(
+    query: &InboxQuery,
+    state: &SharedState,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let page = query.page.max(0);
+    let filter = query.filter();
+    let (messages, total) = match (
+        ContactRequest::find_filtered(&filter, INBOX_PAGE_SIZE, page * INBOX_PAGE_SIZE, &state.db),
+        ContactRequest::count_filtered(&filter, &state.db),
+    ) {
+        (Ok(messages), Ok(total)) => (messages, total),
+        _ => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let tmpl = match state.templates.get_template("components/inbox_list.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! {
+        messages => messages,
+        filter => filter,
+        page => page,
+        pages => (total + INBOX_PAGE_SIZE - 1) / INBOX_PAGE_SIZE,
+        total => total,
+    }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+async fn inbox_list(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<InboxQuery>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+    render_inbox(&query, &state)
+}
+
+/// applies `action` to all checked `ids` and renders the list again.
+/// A plain form, since the ids come as repeated keys.
+async fn inbox_bulk(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    let ids = fields
+        .iter()
+        .filter(|(key, _)| key == "ids")
+        .filter_map(|(_, value)| value.parse::<i64>().ok())
+        .collect::<Vec<_>>();
+
+    let result = match field("action").as_deref() {
+        Some("read") => ContactRequest::set_read(&ids, true, &state.db),
+        Some("unread") => ContactRequest::set_read(&ids, false, &state.db),
+        Some("archive") => ContactRequest::set_archived(&ids, true, &state.db),
+        Some("unarchive") => ContactRequest::set_archived(&ids, false, &state.db),
+        Some("delete") => ContactRequest::delete_many(&ids, &state.db),
+        _ => return Err((StatusCode::BAD_REQUEST, "unknown action")),
+    };
+    if result.is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    let query = InboxQuery {
+        search: field("search"),
+        label: field("label"),
+        archived: field("archived").is_some_and(|a| a == "true"),
+        page: field("page").and_then(|p| p.parse().ok()).unwrap_or_default(),
+    };
+    render_inbox(&query, &state)
+}
+
+// ------------------------------------------------------
+// webhooks
+// ------------------------------------------------------
+/// deliveries shown per hook
+const WEBHOOK_LOG_SIZE: i64 = 10;
+
+fn render_webhooks(state: &SharedState) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let hooks = match Webhook::find_all(&state.db) {
+        Ok(hooks) => hooks,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+    };
+
+    let mut entries = Vec::with_capacity(hooks.len());
+    for hook in hooks {
+        let deliveries =
+            match WebhookDelivery::find_by_webhook(hook.id.unwrap_or_default(), WEBHOOK_LOG_SIZE, &state.db) {
+                Ok(deliveries) => deliveries,
+                Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to get")),
+            };
+        entries.push(context! { hook => hook, deliveries => deliveries });
+    }
+
+    let tmpl = match state.templates.get_template("components/webhook_list.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template")),
+    };
+
+    match tmpl.render(context! { hooks => entries }) {
+        Ok(html) => Ok(Html(html)),
+        Err(_) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to render template",
+        )),
+    }
+}
+
+async fn webhook_list(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+    render_webhooks(&state)
+}
+
+/// `url`, `secret` and one `events` field per subscribed event.
+/// An empty secret is generated.
+async fn webhook_create(
+    auth: Auth,
+    State(state): State<Arc<SharedState>>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let url = field("url");
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err((StatusCode::BAD_REQUEST, "url must start with http:// or https://"));
+    }
+
+    let events = fields
+        .iter()
+        .filter(|(key, _)| key == "events")
+        .filter_map(|(_, value)| WebhookEvent::parse(value))
+        .filter(|event| *event != WebhookEvent::Ping)
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "select at least one event"));
+    }
+
+    let secret = match field("secret") {
+        secret if secret.is_empty() => webhooks::generate_secret(),
+        secret => secret,
+    };
+
+    if Webhook::new(url, secret, &events).insert(&state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to create"));
+    }
+
+    render_webhooks(&state)
+}
+
+#[derive(serde::Deserialize)]
+struct WebhookToggleForm {
+    active: bool,
+}
+
+async fn webhook_toggle(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+    Form(form): Form<WebhookToggleForm>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if Webhook::set_active(id, form.active, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to update"));
+    }
+
+    render_webhooks(&state)
+}
+
+async fn webhook_delete(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if Webhook::delete(id, &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to delete"));
+    }
+
+    render_webhooks(&state)
+}
+
+/// queues a test delivery, inactive hooks receive it too
+async fn webhook_ping(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let hook = match Webhook::find(id, &state.db) {
+        Ok(hook) => hook,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found")),
+    };
+
+    let data = serde_json::json!({ "webhook_id": id, "url": hook.url });
+    if webhooks::enqueue(WebhookEvent::Ping, data, &[hook], &state.db).is_err() {
+        return Err((StatusCode::BAD_REQUEST, "failed to queue"));
+    }
+
+    render_webhooks(&state)
+}
+
+/// sends a delivery again on the next poll, with a fresh set of retries
+async fn webhook_retry(
+    auth: Auth,
+    Path(id): Path<i64>,
+    State(state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    require_admin!(auth);
+
+    if WebhookDelivery::retry(id, &state.db).is_err() {
+        return Err((StatusCode::NOT_FOUND, "not found"));
+    }
+
+    render_webhooks(&state)
+}
+
+// ------------------------------------------------------
+// markdown export
+// ------------------------------------------------------
+/// all articles as markdown with their media, packed as `.tar.gz`
+async fn export_download(auth: Auth, State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    require_admin!(auth);
+
+    let db_path = state.db_path.clone();
+    let archive = tokio::task::spawn_blocking(move || {
+        let con = crate::store::open(&db_path)?;
+        crate::export::archive(&con)
+    })
+    .await;
+
+    let archive = match archive {
+        Ok(Ok(archive)) => archive,
+        Ok(Err(err)) => {
+            tracing::error!("export failed: {}", err);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "export failed"));
+        }
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "export failed")),
+    };
+
+    let file_name = format!(
+        "attachment; filename=\"articles-{}.tar.gz\"",
+        chrono::offset::Local::now().format("%Y-%m-%d")
+    );
+    let mut header = HeaderMap::new();
+    header.insert("Content-Type", "application/gzip".parse().unwrap());
+    header.insert("Content-Disposition", file_name.parse().unwrap());
+    Ok((header, archive))
 }
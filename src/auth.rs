@@ -13,13 +13,15 @@ pub const AUTH_COOKIE: &str = "auth";
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct Auth {
     pub id: Option<u128>,
+    pub user: Option<String>,
     pub user_state: UserState,
 }
 
 impl Auth {
-    pub fn new(id: u128, user_state: UserState) -> Self {
+    pub fn new(id: u128, user: String, user_state: UserState) -> Self {
         Auth {
             id: Some(id),
+            user: Some(user),
             user_state,
         }
     }
@@ -100,7 +102,7 @@ impl FromRequestParts<Arc<SharedState>> for Auth {
                         Err(_) => return Ok(Auth::default()),
                     };
                     match sessions.iter().find(|s| s.id == cookie_id) {
-                        Some(s) => Ok(Auth::new(s.id, s.user_state.clone())),
+                        Some(s) => Ok(Auth::new(s.id, s.user.clone(), s.user_state.clone())),
                         None => Ok(Auth::default()),
                     }
                 }
@@ -0,0 +1,337 @@
+use crate::render::MEDIA_DIR;
+use crate::util::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// hours between backups made by the server, `0` or empty disables them
+const BACKUP_INTERVAL_HOURS: &str = "BACKUP_INTERVAL_HOURS";
+const BACKUP_DIR: &str = "BACKUP_DIR";
+/// number of backups kept, older ones are deleted after each backup
+const BACKUP_KEEP: &str = "BACKUP_KEEP";
+/// `true` bundles `static/media` with scheduled backups
+const BACKUP_MEDIA: &str = "BACKUP_MEDIA";
+
+const DEFAULT_DIR: &str = "backups";
+const DEFAULT_KEEP: usize = 7;
+const PREFIX: &str = "backup-";
+const EXTENSION: &str = ".tar.gz";
+/// name of the database inside an archive
+const DB_ENTRY: &str = "site.db";
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+    /// the archive or the database inside it is unusable
+    Invalid(String),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::Io(err) => write!(f, "io: {}", err),
+            BackupError::Db(err) => write!(f, "database: {}", err),
+            BackupError::Invalid(reason) => write!(f, "invalid backup: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(err: std::io::Error) -> Self {
+        BackupError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(err: rusqlite::Error) -> Self {
+        BackupError::Db(err)
+    }
+}
+
+pub fn backup_dir() -> PathBuf {
+    env(BACKUP_DIR).unwrap_or(DEFAULT_DIR.to_string()).into()
+}
+
+pub fn keep() -> usize {
+    env(BACKUP_KEEP)
+        .and_then(|keep| keep.parse().ok())
+        .unwrap_or(DEFAULT_KEEP)
+}
+
+/// copies the live database with sqlite's online backup, so writes may go on meanwhile.
+/// The copy is checked before it is packed into `<dir>/backup-<time>.tar.gz`,
+/// together with `static/media` if asked for. Returns the archive.
+pub fn create(con: &rusqlite::Connection, dir: &Path, media: bool) -> Result<PathBuf, BackupError> {
+    std::fs::create_dir_all(dir)?;
+    let name = format!(
+        "{}{}",
+        PREFIX,
+        chrono::offset::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let snapshot = dir.join(format!(".{}.db", name));
+    let part = dir.join(format!(".{}{}.part", name, EXTENSION));
+    let archive = dir.join(format!("{}{}", name, EXTENSION));
+
+    let result = (|| {
+        con.backup(rusqlite::DatabaseName::Main, &snapshot, None)?;
+        verify(&snapshot)?;
+
+        let file = std::fs::File::create(&part)?;
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_path_with_name(&snapshot, DB_ENTRY)?;
+        if media && Path::new(MEDIA_DIR).is_dir() {
+            tar.append_dir_all(MEDIA_DIR, MEDIA_DIR)?;
+        }
+        tar.into_inner()?.finish()?.sync_all()?;
+        std::fs::rename(&part, &archive)?;
+        Ok(archive)
+    })();
+
+    std::fs::remove_file(&snapshot).ok();
+    if result.is_err() {
+        std::fs::remove_file(&part).ok();
+    }
+    result
+}
+
+/// runs sqlite's integrity check and makes sure it is a database of this site
+fn verify(db: &Path) -> Result<(), BackupError> {
+    let con =
+        rusqlite::Connection::open_with_flags(db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let check: String = con.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(BackupError::Invalid(format!(
+            "integrity check failed: {}",
+            check
+        )));
+    }
+    let tables: i64 = con.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('article', 'paragraph')",
+        [],
+        |row| row.get(0),
+    )?;
+    if tables != 2 {
+        return Err(BackupError::Invalid("no article tables".to_string()));
+    }
+    Ok(())
+}
+
+/// the backups in `dir`, oldest first
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>, BackupError> {
+    let mut backups = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(EXTENSION))
+        })
+        .collect::<Vec<_>>();
+    // the names sort by time
+    backups.sort();
+    Ok(backups)
+}
+
+/// deletes all but the newest `keep` backups, returns how many were deleted
+pub fn rotate(dir: &Path, keep: usize) -> Result<usize, BackupError> {
+    let backups = list(dir)?;
+    let expired = backups.len().saturating_sub(keep.max(1));
+    for backup in &backups[..expired] {
+        std::fs::remove_file(backup)?;
+    }
+    Ok(expired)
+}
+
+/// replaces the database at `db_path`, and `static/media` if the archive has it.
+/// The archive is unpacked and checked first, nothing is touched if that fails.
+/// The replaced database is kept as `<db_path>.before-restore`, the media directory
+/// as `static/media.before-restore`. Stop the server before restoring.
+pub fn restore(archive: &Path, db_path: &Path) -> Result<(), BackupError> {
+    let parent = db_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let staging = parent.join(format!(".restore-{:016x}", rand::random::<u64>()));
+
+    let result = (|| {
+        let file = std::fs::File::open(archive)?;
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        tar.unpack(&staging)
+            .map_err(|err| BackupError::Invalid(format!("broken archive: {}", err)))?;
+
+        let db = staging.join(DB_ENTRY);
+        if !db.is_file() {
+            return Err(BackupError::Invalid(format!("{} is missing", DB_ENTRY)));
+        }
+        verify(&db)?;
+
+        if db_path.exists() {
+            std::fs::copy(db_path, before_restore(db_path))?;
+        }
+        std::fs::rename(&db, db_path)?;
+
+        let media = staging.join(MEDIA_DIR);
+        if media.is_dir() {
+            let target = Path::new(MEDIA_DIR);
+            let previous = before_restore(target);
+            if previous.exists() {
+                std::fs::remove_dir_all(&previous)?;
+            }
+            if target.exists() {
+                std::fs::rename(target, &previous)?;
+            }
+            // staging may live on another file system than the media
+            if std::fs::rename(&media, target).is_err() {
+                copy_dir(&media, target)?;
+            }
+        }
+        Ok(())
+    })();
+
+    std::fs::remove_dir_all(&staging).ok();
+    result
+}
+
+fn before_restore(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".before-restore");
+    name.into()
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        let target = to.join(path.file_name().unwrap_or_default());
+        match path.is_dir() {
+            true => copy_dir(&path, &target)?,
+            false => {
+                std::fs::copy(&path, &target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// backs up every `BACKUP_INTERVAL_HOURS`, see [crate::scheduler::spawn_periodic]
+pub fn spawn(db_path: String) {
+    let hours = env(BACKUP_INTERVAL_HOURS)
+        .and_then(|hours| hours.parse::<u64>().ok())
+        .unwrap_or(0);
+    if hours == 0 {
+        return;
+    }
+    let media = env(BACKUP_MEDIA).is_some_and(|media| media == "true" || media == "1");
+
+    let interval = Duration::from_secs(hours * 60 * 60);
+    crate::scheduler::spawn_periodic("backup", interval, db_path, move |con| {
+        let dir = backup_dir();
+        let result = create(con, &dir, media).and_then(|archive| {
+            rotate(&dir, keep())?;
+            Ok(archive)
+        });
+        match result {
+            Ok(archive) => tracing::info!("backup: wrote {}", archive.display()),
+            Err(err) => tracing::error!("backup: failed: {}", err),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::articles::Article;
+    use crate::store::paragraphs::Paragraph;
+    use crate::store::{Crud, SchemaUp};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(format!("target/{}-{:x}", name, rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn titles(db_path: &Path) -> Vec<String> {
+        let con = rusqlite::Connection::open(db_path).unwrap();
+        let mut titles: Vec<_> = Article::find_all(&con)
+            .unwrap()
+            .into_iter()
+            .map(|a| a.title)
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn restores_what_was_backed_up() {
+        let dir = temp_dir("backup");
+        let db_path = dir.join("site.db");
+        let con = rusqlite::Connection::open(&db_path).unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Article::new("Kept".to_string()).insert(&con).unwrap();
+
+        let archive = create(&con, &dir.join("backups"), false).unwrap();
+        assert_eq!(list(&dir.join("backups")).unwrap(), vec![archive.clone()]);
+
+        Article::new("Added later".to_string()).insert(&con).unwrap();
+        drop(con);
+
+        restore(&archive, &db_path).unwrap();
+        assert_eq!(titles(&db_path), vec!["Kept"]);
+        assert_eq!(titles(&before_restore(&db_path)), vec!["Added later", "Kept"]);
+        // the staging directory is gone
+        let left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(".restore-"))
+            .collect();
+        assert!(left.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_databases_of_other_apps() {
+        let dir = temp_dir("backup");
+        let con = rusqlite::Connection::open(dir.join("other.db")).unwrap();
+        con.execute("CREATE TABLE note (id INTEGER PRIMARY KEY)", ()).unwrap();
+
+        assert!(matches!(create(&con, &dir, false), Err(BackupError::Invalid(_))));
+        assert!(list(&dir).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_keeps_the_newest() {
+        let dir = temp_dir("rotate");
+        let names = [
+            "backup-20230103-000000.tar.gz",
+            "backup-20230101-120000.tar.gz",
+            "backup-20230102-000000.tar.gz",
+            "backup-20230101-000000.tar.gz",
+        ];
+        for name in names {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+        std::fs::write(dir.join(".backup-20230104-000000.tar.gz.part"), b"").unwrap();
+
+        let names = |dir: &Path| -> Vec<String> {
+            list(dir)
+                .unwrap()
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        assert_eq!(rotate(&dir, 2).unwrap(), 2);
+        assert_eq!(names(&dir), vec!["backup-20230102-000000.tar.gz", "backup-20230103-000000.tar.gz"]);
+        // the newest one always stays
+        assert_eq!(rotate(&dir, 0).unwrap(), 1);
+        assert_eq!(names(&dir), vec!["backup-20230103-000000.tar.gz"]);
+        assert!(dir.join("notes.txt").is_file());
+        assert!(dir.join(".backup-20230104-000000.tar.gz.part").is_file());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
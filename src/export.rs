@@ -0,0 +1,351 @@
+use crate::import::{FrontMatter, Tags, MARKER_END, MARKER_START};
+use crate::render::MEDIA_DIR;
+use crate::store::articles::{Article, ArticleOrder};
+use crate::store::paragraphs::{Paragraph, ParagraphType};
+use crate::store::Crud;
+use chrono::TimeZone;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// the markdown file inside an article's directory
+pub const ARTICLE_FILE: &str = "index.md";
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+    FrontMatter(serde_yaml::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(err) => write!(f, "io: {}", err),
+            ExportError::Db(err) => write!(f, "database: {}", err),
+            ExportError::FrontMatter(err) => write!(f, "front matter: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(err: std::io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for ExportError {
+    fn from(err: rusqlite::Error) -> Self {
+        ExportError::Db(err)
+    }
+}
+
+impl From<serde_yaml::Error> for ExportError {
+    fn from(err: serde_yaml::Error) -> Self {
+        ExportError::FrontMatter(err)
+    }
+}
+
+/// writes every article, drafts included, to `<dir>/<alias>/index.md`
+/// with its media next to it. `import` reads the result back.
+pub fn export_dir(dir: &Path, con: &rusqlite::Connection) -> Result<usize, ExportError> {
+    let articles = Article::find_all_ordered(ArticleOrder::Created, con)?;
+    for article in articles.iter() {
+        let article = Article::find(article.id.unwrap_or_default(), con)?;
+        write_article(&article, dir)?;
+    }
+    Ok(articles.len())
+}
+
+/// the directory of an article inside an export
+pub fn article_dir(article: &Article, dir: &Path) -> PathBuf {
+    match article.alias.is_empty() {
+        true => dir.join(format!("article-{}", article.id.unwrap_or_default())),
+        false => dir.join(&article.alias),
+    }
+}
+
+/// writes one article with its paragraphs loaded, replacing an earlier export of it
+pub fn write_article(article: &Article, dir: &Path) -> Result<PathBuf, ExportError> {
+    let dir = article_dir(article, dir);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    let (text, media) = render(article)?;
+    let file = dir.join(ARTICLE_FILE);
+    std::fs::write(&file, text)?;
+
+    for (url, name) in media.names.iter() {
+        let source = PathBuf::from(url.trim_start_matches('/'));
+        match source.is_file() {
+            true => {
+                std::fs::copy(&source, dir.join(name))?;
+            }
+            false => tracing::warn!("export: missing media {}", url),
+        }
+    }
+    Ok(file)
+}
+
+/// the markdown file `write_article` writes for an article
+pub fn markdown(article: &Article) -> Result<String, ExportError> {
+    render(article).map(|(text, _)| text)
+}
+
+fn render(article: &Article) -> Result<(String, MediaNames), ExportError> {
+    let mut media = MediaNames::default();
+    let cover = match article.cover.is_empty() {
+        true => None,
+        false => Some(media.local(&article.cover)),
+    };
+
+    let mut body = String::new();
+    for paragraph in article.paragraphs.iter().flatten() {
+        body.push_str(&format!(
+            "{}{}{}\n",
+            MARKER_START,
+            paragraph_type_name(&paragraph.paragraph_type),
+            MARKER_END
+        ));
+        body.push_str(&paragraph_content(paragraph, &mut media));
+        body.push_str("\n\n");
+    }
+
+    let front = FrontMatter {
+        title: Some(article.title.clone()),
+        alias: (!article.alias.is_empty()).then(|| article.alias.clone()),
+        tags: Some(Tags::List(
+            article
+                .tags
+                .split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        )),
+        teaser: Some(article.teaser.clone()),
+        cover,
+        date: Some(rfc3339(article.created_at)),
+        updated: Some(rfc3339(article.updated_at)),
+        published: article.published,
+        publish_at: article.publish_at.map(rfc3339),
+    };
+
+    let text = format!("---\n{}---\n\n{}", serde_yaml::to_string(&front)?, body.trim_end()) + "\n";
+    Ok((text, media))
+}
+
+fn paragraph_type_name(paragraph_type: &ParagraphType) -> &'static str {
+    match paragraph_type {
+        ParagraphType::Markdown => "markdown",
+        ParagraphType::Html => "html",
+        ParagraphType::Wasm => "wasm",
+    }
+}
+
+/// the stored content with media urls pointing to the copies.
+/// Wasm demos keep their urls, they reference their files by site path.
+fn paragraph_content(paragraph: &Paragraph, media: &mut MediaNames) -> String {
+    let content = paragraph.content.trim_matches(['\n', '\r']).to_string();
+    if let ParagraphType::Wasm = paragraph.paragraph_type {
+        return content;
+    }
+
+    let prefix = format!("/{}/", MEDIA_DIR);
+    let mut urls = content
+        .match_indices(&prefix)
+        .map(|(start, _)| {
+            let end = content[start..]
+                .find(|c: char| c.is_whitespace() || "()\"'<>".contains(c))
+                .map(|len| start + len)
+                .unwrap_or(content.len());
+            content[start..end].to_string()
+        })
+        .collect::<Vec<_>>();
+    // longest first, so a url is never replaced inside a longer one
+    urls.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    urls.dedup();
+
+    urls.iter().fold(content.clone(), |content, url| {
+        let local = media.local(url);
+        content
+            .replace(&format!("]({}", url), &format!("]({}", local))
+            .replace(&format!("\"{}\"", url), &format!("\"{}\"", local))
+    })
+}
+
+/// media urls of an article and the file names of their copies
+#[derive(Default)]
+struct MediaNames {
+    names: BTreeMap<String, String>,
+}
+
+impl MediaNames {
+    /// the copy's file name for a media url, other urls stay as they are
+    fn local(&mut self, url: &str) -> String {
+        if !url.starts_with(&format!("/{}/", MEDIA_DIR)) {
+            return url.to_string();
+        }
+        if let Some(name) = self.names.get(url) {
+            return name.clone();
+        }
+        let file_name = url.rsplit('/').next().unwrap_or_default();
+        let mut name = file_name.to_string();
+        let mut n = 1;
+        while self.names.values().any(|taken| *taken == name) || name == ARTICLE_FILE {
+            n += 1;
+            name = format!("{}-{}", n, file_name);
+        }
+        self.names.insert(url.to_string(), name.clone());
+        name
+    }
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    chrono::offset::Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// the export of all articles as a `.tar.gz`, for the admin download
+pub fn archive(con: &rusqlite::Connection) -> Result<Vec<u8>, ExportError> {
+    let dir = std::env::temp_dir().join(format!("export-{:016x}", rand::random::<u64>()));
+    let result = export_dir(&dir, con).and_then(|_| {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut tar = tar::Builder::new(encoder);
+        tar.append_dir_all("articles", &dir)?;
+        Ok(tar.into_inner()?.finish()?)
+    });
+    std::fs::remove_dir_all(&dir).ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{import_dir, Split};
+    use crate::store::media::{Media, MediaVariant};
+    use crate::store::redirects::ArticleAlias;
+    use crate::store::related::Related;
+    use crate::store::series::Series;
+    use crate::store::SchemaUp;
+
+    /// media lands in `static/media/<article id>`, far away ids keep it apart from other tests
+    fn db() -> (rusqlite::Connection, i64) {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        MediaVariant::up(&con).unwrap();
+        ArticleAlias::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+        let first_id = rand::random::<u32>() as i64 + 1_000_000;
+        let mut placeholder = Article::new("Placeholder".to_string());
+        placeholder.insert(&con).unwrap();
+        con.execute("UPDATE article SET id = ?", [first_id - 1]).unwrap();
+        (con, first_id)
+    }
+
+    fn media_dir(id: i64) -> PathBuf {
+        Path::new(MEDIA_DIR).join(id.to_string())
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let (source, id) = db();
+        let other = id + 1;
+        std::fs::create_dir_all(media_dir(id)).unwrap();
+        std::fs::create_dir_all(media_dir(other)).unwrap();
+        image::RgbImage::new(30, 10).save(media_dir(id).join("cover.png")).unwrap();
+        image::RgbImage::new(20, 10).save(media_dir(id).join("chart.png")).unwrap();
+        image::RgbImage::new(10, 10).save(media_dir(other).join("chart.png")).unwrap();
+        std::fs::write(media_dir(id).join("demo_bg.wasm"), b"\0asm").unwrap();
+        std::fs::write(media_dir(id).join("demo.js"), b"export default {}").unwrap();
+
+        let url = |id: i64, name: &str| format!("/{}/{}/{}", MEDIA_DIR, id, name);
+        let wasm = format!(
+            r#"{{"wasm": "{}", "script": "{}", "height": 300}}"#,
+            url(id, "demo_bg.wasm"),
+            url(id, "demo.js")
+        );
+        let mut article = Article::new("Round Trip".to_string());
+        article.alias = "round-trip".to_string();
+        article.tags = "rust,wasm".to_string();
+        article.teaser = "There and back".to_string();
+        article.cover = url(id, "cover.png");
+        article.created_at = 1_690_000_000;
+        article.updated_at = 1_690_100_000;
+        article.published = true;
+        article.insert(&source).unwrap();
+        assert_eq!(article.id, Some(id));
+        source.execute("DELETE FROM article WHERE id = ?", [id - 1]).unwrap();
+        let contents = [
+            (
+                ParagraphType::Markdown,
+                format!("# Charts\n\n![chart]({})\n![other]({})", url(id, "chart.png"), url(other, "chart.png")),
+            ),
+            (ParagraphType::Html, format!("<img src=\"{}\">", url(id, "chart.png"))),
+            (ParagraphType::Wasm, wasm.clone()),
+        ];
+        for (position, (paragraph_type, content)) in contents.into_iter().enumerate() {
+            Paragraph {
+                id: None,
+                article_id: Some(id),
+                page_id: None,
+                title: String::new(),
+                description: String::new(),
+                paragraph_type,
+                position: position as i64,
+                content,
+                rendered: None,
+            }
+            .insert(&source)
+            .unwrap();
+        }
+
+        let dir = PathBuf::from(format!("target/export-{:x}", rand::random::<u64>()));
+        assert_eq!(export_dir(&dir, &source).unwrap(), 1);
+        for name in [ARTICLE_FILE, "cover.png", "chart.png", "2-chart.png"] {
+            assert!(dir.join("round-trip").join(name).is_file(), "{} missing", name);
+        }
+
+        let (target, imported_id) = db();
+        let report = import_dir(&dir, Split::Auto, 2, &target).unwrap();
+        assert_eq!(report.failed, 0);
+        let imported = Article::find_by_alias("round-trip", &target).unwrap();
+        assert_eq!(imported.id, Some(imported_id));
+        assert_eq!(imported.title, "Round Trip");
+        assert_eq!(imported.tags, "rust,wasm");
+        assert_eq!(imported.teaser, "There and back");
+        assert_eq!(imported.cover, url(imported_id, "cover.png"));
+        assert_eq!((imported.created_at, imported.updated_at), (1_690_000_000, 1_690_100_000));
+        assert!(imported.published);
+
+        let paragraphs = Paragraph::find_by_article_id(imported_id, &target).unwrap();
+        let types: Vec<_> = paragraphs
+            .iter()
+            .map(|p| paragraph_type_name(&p.paragraph_type))
+            .collect();
+        assert_eq!(types, vec!["markdown", "html", "wasm"]);
+        assert_eq!(
+            paragraphs[0].content,
+            format!(
+                "# Charts\n\n![chart]({})\n![other]({})",
+                url(imported_id, "chart.png"),
+                url(imported_id, "2-chart.png")
+            )
+        );
+        assert_eq!(paragraphs[1].content, format!("<img src=\"{}\">", url(imported_id, "chart.png")));
+        assert_eq!(paragraphs[2].content, wasm);
+        assert_eq!(Media::find_all(&target).unwrap().len(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        for id in [id, other, imported_id] {
+            let _ = std::fs::remove_dir_all(media_dir(id));
+        }
+        let _ = std::fs::remove_dir(MEDIA_DIR);
+    }
+}
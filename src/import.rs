@@ -0,0 +1,589 @@
+use crate::render::MEDIA_DIR;
+use crate::store::articles::Article;
+use crate::store::media::{Media, MediaVariant};
+use crate::store::paragraphs::{Paragraph, ParagraphType};
+use crate::store::{related, Crud};
+use crate::upload;
+use crate::util::Util;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// starts a paragraph in exported files, e.g. `<!-- paragraph: html -->`
+pub const MARKER_START: &str = "<!-- paragraph: ";
+pub const MARKER_END: &str = " -->";
+/// recorded as uploader of imported media
+const IMPORTER: &str = "import";
+
+/// where the content of a file is cut into paragraphs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Split {
+    /// paragraph markers if the file has them, headings otherwise
+    #[default]
+    Auto,
+    /// `<!-- paragraph: <type> -->` comments, as written by `export`
+    Marker,
+    /// before every heading up to `--heading-level`
+    Heading,
+    /// at horizontal rules, the rule itself is dropped
+    Rule,
+    /// the whole file is one paragraph
+    None,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    FrontMatter(String),
+    MissingTitle,
+    /// the alias belongs to a trashed article or is an old alias of another one
+    AliasTaken(String),
+    /// a paragraph the editor would refuse, numbered from 1
+    InvalidParagraph(usize, &'static str),
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::FrontMatter(err) => write!(f, "bad front matter: {}", err),
+            ImportError::MissingTitle => write!(f, "front matter has no title"),
+            ImportError::AliasTaken(alias) => write!(f, "alias '{}' is used by another article", alias),
+            ImportError::InvalidParagraph(number, err) => write!(f, "paragraph {}: {}", number, err),
+            ImportError::Io(err) => write!(f, "io: {}", err),
+            ImportError::Db(err) => write!(f, "database: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(err: std::io::Error) -> Self {
+        ImportError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(err: rusqlite::Error) -> Self {
+        ImportError::Db(err)
+    }
+}
+
+/// tags may be a list or a comma separated string
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Tags {
+    List(Vec<String>),
+    Text(String),
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Tags>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub teaser: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// `2023-08-01`, `2023-08-01 12:00` or rfc3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+    #[serde(default)]
+    pub published: bool,
+    /// scheduled publication, ignored once it lies in the past
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<String>,
+}
+
+impl FrontMatter {
+    pub fn tags(&self) -> String {
+        match &self.tags {
+            Some(Tags::List(tags)) => tags.join(","),
+            Some(Tags::Text(tags)) => tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>()
+                .join(","),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub failed: usize,
+}
+
+/// imports every `.md` file below `dir`. Files are matched to articles by alias,
+/// importing again updates those articles instead of adding new ones.
+pub fn import_dir(
+    dir: &Path,
+    split: Split,
+    heading_level: usize,
+    con: &rusqlite::Connection,
+) -> Result<ImportReport, ImportError> {
+    let mut files = Util::load_files_rec(dir.to_path_buf())?
+        .into_iter()
+        .map(|(_, path)| path)
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut report = ImportReport::default();
+    let mut imported = Vec::new();
+    for file in files {
+        match import_file(&file, split, heading_level, con) {
+            Ok((article, true)) => {
+                tracing::info!("import: created {} from {}", article.url(), file.display());
+                report.created += 1;
+                imported.extend(article.id);
+            }
+            Ok((article, false)) => {
+                tracing::info!("import: updated {} from {}", article.url(), file.display());
+                report.updated += 1;
+                imported.extend(article.id);
+            }
+            Err(err) => {
+                tracing::error!("import: skipped {}: {}", file.display(), err);
+                report.failed += 1;
+            }
+        }
+    }
+
+    for id in imported {
+        if let Err(err) = related::refresh(id, con) {
+            tracing::error!("import: failed to compute related articles: {}", err);
+        }
+    }
+    Ok(report)
+}
+
+/// imports one file, returns the article and whether it is new
+pub fn import_file(
+    file: &Path,
+    split: Split,
+    heading_level: usize,
+    con: &rusqlite::Connection,
+) -> Result<(Article, bool), ImportError> {
+    import_into(file, None, split, heading_level, con)
+}
+
+/// imports one file into the article `target`, or the one matching its alias if `None`.
+/// A different alias in the front matter renames the target, keeping the old one.
+pub fn import_into(
+    file: &Path,
+    target: Option<i64>,
+    split: Split,
+    heading_level: usize,
+    con: &rusqlite::Connection,
+) -> Result<(Article, bool), ImportError> {
+    let text = std::fs::read_to_string(file)?;
+    let (front, body) = parse_front_matter(&text)?;
+    let title = front
+        .title
+        .clone()
+        .filter(|t| !t.trim().is_empty())
+        .ok_or(ImportError::MissingTitle)?;
+    let alias = Article::alias_slug(front.alias.as_deref().unwrap_or(&title));
+
+    let tx = con.unchecked_transaction()?;
+    let (mut article, created) = match target {
+        Some(id) => {
+            if Article::alias_taken(&alias, Some(id), &tx)? {
+                return Err(ImportError::AliasTaken(alias));
+            }
+            let mut article = Article::find(id, &tx)?;
+            article.change_alias(alias, &tx)?;
+            (article, false)
+        }
+        None => match Article::find_by_alias(&alias, &tx) {
+            Ok(article) => (article, false),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                if Article::alias_taken(&alias, None, &tx)? {
+                    return Err(ImportError::AliasTaken(alias));
+                }
+                let mut article = Article::new(title.clone());
+                article.alias = alias;
+                article.insert(&tx)?;
+                (article, true)
+            }
+            Err(err) => return Err(err.into()),
+        },
+    };
+    let id = article.id.ok_or(rusqlite::Error::InvalidQuery)?;
+
+    // local images go to the media library, references point to the copies
+    let base = file.parent().unwrap_or(Path::new("."));
+    let mut copied = HashMap::new();
+    let mut body = body.to_string();
+    for url in image_urls(&body) {
+        if let Some(media_url) = import_image(&url, base, id, &mut copied, &tx)? {
+            body = body
+                .replace(&format!("]({}", url), &format!("]({}", media_url))
+                .replace(&format!("\"{}\"", url), &format!("\"{}\"", media_url));
+        }
+    }
+    let cover = match front.cover.as_deref().filter(|c| !c.is_empty()) {
+        Some(cover) => import_image(cover, base, id, &mut copied, &tx)?.unwrap_or(cover.to_string()),
+        None => String::new(),
+    };
+
+    article.title = title;
+    article.teaser = front.teaser.clone().unwrap_or_default();
+    article.cover = cover;
+    article.tags = front.tags();
+    // a scheduled article stays private until the scheduler publishes it
+    article.publish_at = front
+        .publish_at
+        .as_deref()
+        .and_then(parse_date)
+        .filter(|at| *at > chrono::offset::Local::now().timestamp());
+    article.published = front.published && article.publish_at.is_none();
+    if let Some(date) = front.date.as_deref().and_then(parse_date) {
+        article.created_at = date;
+        article.updated_at = date;
+    }
+    if let Some(updated) = front.updated.as_deref().and_then(parse_date) {
+        article.updated_at = updated;
+    }
+    article.update(&tx)?;
+
+    Paragraph::delete_by_article(id, &tx)?;
+    for (position, (paragraph_type, content)) in split_paragraphs(&body, split, heading_level)
+        .into_iter()
+        .enumerate()
+    {
+        let mut paragraph = Paragraph {
+            id: None,
+            article_id: Some(id),
+            page_id: None,
+            title: String::new(),
+            description: String::new(),
+            paragraph_type,
+            position: position as i64,
+            content,
+            rendered: None,
+        };
+        // returning drops the transaction, the article stays as it was
+        paragraph
+            .validate()
+            .map_err(|err| ImportError::InvalidParagraph(position + 1, err))?;
+        paragraph.insert(&tx)?;
+    }
+    Article::refresh_metrics(id, None, &tx)?;
+    tx.commit()?;
+
+    for media in copied.values().filter_map(|(media_id, _)| *media_id) {
+        if !MediaVariant::find_by_media(media, con)?.is_empty() {
+            continue;
+        }
+        if let Err(err) = crate::variants::generate(media, con) {
+            tracing::error!("import: failed to create variants for media {}: {:?}", media, err);
+        }
+    }
+
+    Ok((article, created))
+}
+
+/// copies `url` if it names a local file next to the markdown, returns the new url
+fn import_image(
+    url: &str,
+    base: &Path,
+    article_id: i64,
+    copied: &mut HashMap<String, (Option<i64>, String)>,
+    con: &rusqlite::Connection,
+) -> Result<Option<String>, ImportError> {
+    if url.starts_with('/') || url.contains("://") || url.starts_with("data:") {
+        return Ok(None);
+    }
+    if let Some((_, media_url)) = copied.get(url) {
+        return Ok(Some(media_url.clone()));
+    }
+    let source = base.join(url.split(['?', '#']).next().unwrap_or(url));
+    if !source.is_file() {
+        tracing::warn!("import: image {} not found", source.display());
+        return Ok(None);
+    }
+
+    // variants may have rewritten the original, so the hash no longer matches on a re-run
+    let previous = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(upload::sanitize_file_name)
+        .and_then(|name| {
+            Media::find_by_path(&format!("/{}/{}/{}", MEDIA_DIR, article_id, name), con).ok()
+        });
+    // a file the library refuses stays a plain reference, it must not fail the article
+    let media = match previous {
+        Some(media) => media,
+        None => match upload::import_file(&source, article_id, IMPORTER, con) {
+            Ok(media) => media,
+            Err(err) => {
+                tracing::warn!("import: skipped {}: {}", source.display(), err.message());
+                return Ok(None);
+            }
+        },
+    };
+    // only raster images get variants
+    let variant_source = media.id.filter(|_| upload::is_raster(&media.mime));
+    copied.insert(url.to_string(), (variant_source, media.path.clone()));
+    Ok(Some(media.path))
+}
+
+/// `---` yaml or `+++` toml front matter, and the rest of the file
+pub fn parse_front_matter(text: &str) -> Result<(FrontMatter, &str), ImportError> {
+    let text = text.trim_start_matches('\u{feff}');
+    for (fence, yaml) in [("---", true), ("+++", false)] {
+        let Some(rest) = text
+            .strip_prefix(fence)
+            .and_then(|rest| rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")))
+        else {
+            continue;
+        };
+        let end = rest
+            .match_indices(fence)
+            .map(|(i, _)| i)
+            .find(|i| *i == 0 || rest[..*i].ends_with('\n'))
+            .ok_or(ImportError::FrontMatter("front matter is not closed".to_string()))?;
+        let body = rest[end + fence.len()..].trim_start_matches(['\r', '\n']);
+        let front = match yaml {
+            true => serde_yaml::from_str(&rest[..end])
+                .map_err(|err| ImportError::FrontMatter(err.to_string()))?,
+            false => parse_toml(&rest[..end])?,
+        };
+        return Ok((front, body));
+    }
+    Ok((FrontMatter::default(), text))
+}
+
+/// toml dates are their own type, they are read like the yaml strings
+fn parse_toml(text: &str) -> Result<FrontMatter, ImportError> {
+    let mut table = text
+        .parse::<toml::Table>()
+        .map_err(|err| ImportError::FrontMatter(err.to_string()))?;
+    for key in ["date", "updated"] {
+        if let Some(toml::Value::Datetime(date)) = table.get(key) {
+            let date = date.to_string();
+            table.insert(key.to_string(), toml::Value::String(date));
+        }
+    }
+    table
+        .try_into()
+        .map_err(|err: toml::de::Error| ImportError::FrontMatter(err.to_string()))
+}
+
+/// unix timestamp of a front matter date, local time unless it has an offset
+pub fn parse_date(date: &str) -> Option<i64> {
+    let date = date.trim();
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(date) {
+        return Some(time.timestamp());
+    }
+    let time = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        })?;
+    time.and_local_timezone(chrono::offset::Local)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+/// urls of markdown images and html `<img src>`, plain links are left alone
+fn image_urls(content: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for part in content.split("![").skip(1) {
+        let Some(target) = part
+            .find(']')
+            .and_then(|close| part[close..].strip_prefix("]("))
+        else {
+            continue;
+        };
+        let end = target.find([')', ' ']).unwrap_or(target.len());
+        urls.push(target[..end].trim_matches(['<', '>']).to_string());
+    }
+    for part in content.split("<img").skip(1) {
+        let tag = part.split('>').next().unwrap_or_default();
+        if let Some(src) = tag.split("src=\"").nth(1) {
+            urls.push(src.split('"').next().unwrap_or_default().to_string());
+        }
+    }
+    urls.retain(|url| !url.is_empty());
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+fn is_rule(line: &str) -> bool {
+    let line = line.trim().replace(' ', "");
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|c| line.chars().all(|l| l == *c))
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    (hashes > 0 && hashes <= 6 && line[hashes..].starts_with(' ')).then_some(hashes)
+}
+
+/// the type named by a paragraph marker line
+fn marker_type(line: &str) -> Option<ParagraphType> {
+    let name = line.trim().strip_prefix(MARKER_START)?.strip_suffix(MARKER_END)?;
+    match name.trim() {
+        "markdown" => Some(ParagraphType::Markdown),
+        "html" => Some(ParagraphType::Html),
+        "wasm" => Some(ParagraphType::Wasm),
+        _ => None,
+    }
+}
+
+/// cuts the body into paragraphs, code blocks are never cut
+pub fn split_paragraphs(body: &str, split: Split, level: usize) -> Vec<(ParagraphType, String)> {
+    let split = match split {
+        Split::Auto if body.lines().any(|line| marker_type(line).is_some()) => Split::Marker,
+        Split::Auto => Split::Heading,
+        split => split,
+    };
+
+    let mut paragraphs = Vec::new();
+    let mut kind = ParagraphType::Markdown;
+    let mut current = Vec::new();
+    let mut in_code = false;
+
+    let mut flush = |kind: ParagraphType, lines: &mut Vec<&str>| {
+        let content = lines.join("\n").trim_matches(['\n', '\r']).to_string();
+        if !content.trim().is_empty() {
+            paragraphs.push((kind, content));
+        }
+        lines.clear();
+    };
+
+    for line in body.lines() {
+        if is_fence(line) {
+            in_code = !in_code;
+        }
+        if !in_code {
+            match split {
+                Split::Marker => {
+                    if let Some(next) = marker_type(line) {
+                        flush(std::mem::replace(&mut kind, next), &mut current);
+                        continue;
+                    }
+                }
+                Split::Heading if heading_level(line).is_some_and(|l| l <= level) => {
+                    flush(ParagraphType::Markdown, &mut current);
+                }
+                Split::Rule if is_rule(line) => {
+                    flush(ParagraphType::Markdown, &mut current);
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        current.push(line);
+    }
+    flush(kind, &mut current);
+    paragraphs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::redirects::ArticleAlias;
+    use crate::store::related::Related;
+    use crate::store::series::Series;
+    use crate::store::SchemaUp;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        MediaVariant::up(&con).unwrap();
+        ArticleAlias::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+        con
+    }
+
+    #[test]
+    fn only_images_go_to_the_library() {
+        let urls = image_urls(concat!(
+            "[part two](part-two.md) ![diagram](diagram.png \"title\")\n",
+            "[![badge](badge.svg)](https://example.com) <a href=\"page.html\">page</a>\n",
+            "<img alt=\"x\" src=\"photo.jpg\"> <script src=\"app.js\"></script>",
+        ));
+        assert_eq!(urls, vec!["badge.svg", "diagram.png", "photo.jpg"]);
+    }
+
+    #[test]
+    fn imports_linked_files_with_images() {
+        let con = db();
+        // media lands in `static/media/<article id>`, far away ids keep it apart from other tests
+        let first_id = rand::random::<u32>() as i64 + 1_000_000;
+        con.execute("INSERT INTO article (id, title) VALUES (?, 'placeholder')", [first_id - 1])
+            .unwrap();
+
+        let dir = PathBuf::from(format!("target/import-{:x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(40, 20).save(dir.join("diagram.png")).unwrap();
+        std::fs::write(dir.join("notes.bin"), b"not media").unwrap();
+        std::fs::write(
+            dir.join("a-part-one.md"),
+            "---\ntitle: Part One\n---\nRead [part two](b-part-two.md) next.\n\n![diagram](diagram.png)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("b-part-two.md"),
+            "---\ntitle: Part Two\n---\nBack to [part one](a-part-one.md).\n\n![raw](notes.bin)\n",
+        )
+        .unwrap();
+
+        let report = import_dir(&dir, Split::None, 2, &con).unwrap();
+        assert_eq!((report.created, report.failed), (2, 0));
+
+        let one = Article::find_by_alias("part-one", &con).unwrap();
+        let two = Article::find_by_alias("part-two", &con).unwrap();
+        let media = Media::find_all(&con).unwrap();
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0].article_id, one.id);
+        assert_eq!(media[0].mime, "image/png");
+
+        let body = |id: i64| {
+            Paragraph::find_by_article_id(id, &con)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.content)
+                .collect::<String>()
+        };
+        assert_eq!(
+            body(one.id.unwrap()),
+            format!("Read [part two](b-part-two.md) next.\n\n![diagram]({})", media[0].path)
+        );
+        assert_eq!(
+            body(two.id.unwrap()),
+            "Back to [part one](a-part-one.md).\n\n![raw](notes.bin)"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        for id in [one.id.unwrap(), two.id.unwrap()] {
+            let _ = std::fs::remove_dir_all(Path::new(MEDIA_DIR).join(id.to_string()));
+        }
+        let _ = std::fs::remove_dir(MEDIA_DIR);
+    }
+}
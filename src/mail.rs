@@ -0,0 +1,215 @@
+use crate::util::env;
+use lettre::message::header::ContentType;
+use lettre::message::{Mailbox, Mailboxes, MessageBuilder};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SendmailTransport, SmtpTransport, Transport};
+
+/// relay host, mail is off unless this or [SENDMAIL] is set
+const SMTP_HOST: &str = "SMTP_HOST";
+const SMTP_PORT: &str = "SMTP_PORT";
+const SMTP_USER: &str = "SMTP_USER";
+const SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+/// `starttls` (default), `tls` for implicit tls or `none` for local stand-ins
+const SMTP_TLS: &str = "SMTP_TLS";
+/// path of a sendmail compatible binary, used if no relay is configured
+const SENDMAIL: &str = "SENDMAIL";
+/// sender of all mails
+const MAIL_FROM: &str = "MAIL_FROM";
+/// comma separated, receive notifications
+const ADMIN_EMAIL: &str = "ADMIN_EMAIL";
+
+#[derive(Debug)]
+pub enum MailError {
+    /// neither a relay nor sendmail is configured
+    Disabled,
+    Address(lettre::address::AddressError),
+    Message(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+    Sendmail(lettre::transport::sendmail::Error),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::Disabled => write!(f, "mail is not configured"),
+            MailError::Address(err) => write!(f, "invalid address: {}", err),
+            MailError::Message(err) => write!(f, "invalid message: {}", err),
+            MailError::Smtp(err) => write!(f, "smtp: {}", err),
+            MailError::Sendmail(err) => write!(f, "sendmail: {}", err),
+        }
+    }
+}
+
+impl From<lettre::address::AddressError> for MailError {
+    fn from(err: lettre::address::AddressError) -> Self {
+        MailError::Address(err)
+    }
+}
+
+impl From<lettre::error::Error> for MailError {
+    fn from(err: lettre::error::Error) -> Self {
+        MailError::Message(err)
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for MailError {
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        MailError::Smtp(err)
+    }
+}
+
+impl From<lettre::transport::sendmail::Error> for MailError {
+    fn from(err: lettre::transport::sendmail::Error) -> Self {
+        MailError::Sendmail(err)
+    }
+}
+
+/// how mail is sent, read from the environment by [MailConfig::from_env]
+#[derive(Debug, Clone, Default)]
+pub struct MailConfig {
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+    /// see [SMTP_TLS], `None` means `starttls`
+    pub smtp_tls: Option<String>,
+    pub sendmail: Option<String>,
+    pub from: Option<String>,
+    /// receive notifications
+    pub admins: Vec<String>,
+}
+
+impl MailConfig {
+    pub fn from_env() -> Self {
+        MailConfig {
+            smtp_host: env(SMTP_HOST),
+            smtp_port: env(SMTP_PORT).and_then(|port| port.parse().ok()),
+            smtp_user: env(SMTP_USER),
+            smtp_password: env(SMTP_PASSWORD),
+            smtp_tls: env(SMTP_TLS),
+            sendmail: env(SENDMAIL),
+            from: env(MAIL_FROM),
+            admins: env(ADMIN_EMAIL)
+                .map(|admins| {
+                    admins
+                        .split(',')
+                        .map(|a| a.trim().to_string())
+                        .filter(|a| !a.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.smtp_host.is_some() || self.sendmail.is_some()
+    }
+
+    /// `<local>@<domain of MAIL_FROM>`, stable ids let replies thread in mail clients
+    pub fn message_id(&self, local: &str) -> String {
+        let domain = self
+            .from
+            .as_deref()
+            .and_then(|from| {
+                from.rsplit_once('@')
+                    .map(|(_, domain)| domain.trim_end_matches('>').to_string())
+            })
+            .unwrap_or_else(|| "localhost".to_string());
+        format!("{}@{}", local, domain)
+    }
+}
+
+pub fn is_enabled() -> bool {
+    MailConfig::from_env().is_enabled()
+}
+
+/// a plain text mail
+#[derive(Debug, Clone, Default)]
+pub struct Mail {
+    pub to: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub body: String,
+    /// without angle brackets, generated if empty
+    pub message_id: Option<String>,
+    /// message ids of the thread, oldest first. The last one is the mail replied to.
+    pub references: Vec<String>,
+}
+
+impl Mail {
+    fn builder(&self, config: &MailConfig) -> Result<MessageBuilder, MailError> {
+        let from: Mailbox = config
+            .from
+            .as_deref()
+            .unwrap_or("blog@localhost")
+            .parse()?;
+
+        let mut builder = Message::builder()
+            .from(from)
+            .subject(self.subject.replace(['\r', '\n'], " "))
+            .header(ContentType::TEXT_PLAIN);
+
+        let to = self.to.join(", ").parse::<Mailboxes>()?;
+        for mailbox in to {
+            builder = builder.to(mailbox);
+        }
+        if let Some(reply_to) = &self.reply_to {
+            builder = builder.reply_to(reply_to.parse()?);
+        }
+        if let Some(id) = &self.message_id {
+            builder = builder.message_id(Some(format!("<{}>", id)));
+        }
+        if let Some(parent) = self.references.last() {
+            builder = builder
+                .in_reply_to(format!("<{}>", parent))
+                .references(
+                    self.references
+                        .iter()
+                        .map(|id| format!("<{}>", id))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+        }
+        Ok(builder)
+    }
+
+    /// sends right away, blocks until the relay accepted or refused the mail
+    pub fn send(&self, config: &MailConfig) -> Result<(), MailError> {
+        let message = self.builder(config)?.body(self.body.clone())?;
+
+        if let Some(host) = &config.smtp_host {
+            let mut transport = match config.smtp_tls.as_deref() {
+                Some("tls") => SmtpTransport::relay(host)?,
+                Some("none") => SmtpTransport::builder_dangerous(host),
+                _ => SmtpTransport::starttls_relay(host)?,
+            };
+            if let Some(port) = config.smtp_port {
+                transport = transport.port(port);
+            }
+            if let (Some(user), Some(password)) = (&config.smtp_user, &config.smtp_password) {
+                transport = transport.credentials(Credentials::new(user.clone(), password.clone()));
+            }
+            transport.build().send(&message)?;
+            return Ok(());
+        }
+
+        if let Some(sendmail) = &config.sendmail {
+            SendmailTransport::new_with_command(sendmail).send(&message)?;
+            return Ok(());
+        }
+
+        Err(MailError::Disabled)
+    }
+
+    /// sends on a blocking thread with the config from the environment, failures are only logged
+    pub fn spawn(self) {
+        let config = MailConfig::from_env();
+        if !config.is_enabled() || self.to.is_empty() {
+            return;
+        }
+        tokio::task::spawn_blocking(move || match self.send(&config) {
+            Ok(_) => tracing::info!("mail: sent '{}' to {}", self.subject, self.to.join(", ")),
+            Err(err) => tracing::error!("mail: failed to send '{}': {}", self.subject, err),
+        });
+    }
+}
@@ -11,8 +11,8 @@ use axum::{
 };
 
 use axum_server::tls_rustls::RustlsConfig;
-use chrono::NaiveDateTime;
-use clap::{Args, Parser};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use clap::{Args, Parser, Subcommand};
 use minijinja::{context, value::Value};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -27,13 +27,27 @@ use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::Subs
 
 use crate::store::{articles::Article, paragraphs::Paragraph};
 use dotenv::dotenv;
-use store::{SchemaUp, stats::Stats, contacts::ContactRequest};
+use store::{SchemaUp, stats::Stats, contacts::{ContactReply, ContactRequest}, media::{Media, MediaVariant}, pages::{Menu, Page}, previews::PreviewToken, redirects::{ArticleAlias, RedirectRule}, series::Series, settings::Settings, related::Related, comments::{Ban, Comment}, sync::SyncState, webhooks::{Webhook, WebhookDelivery}};
 
 mod api;
 mod auth;
+mod backup;
+mod export;
+mod import;
+mod mail;
+mod notify;
 mod pages;
+mod render;
+mod scheduler;
+mod spam;
+mod static_site;
 mod store;
+mod sync;
+mod trash;
+mod upload;
 mod util;
+mod variants;
+mod webhooks;
 
 const TEMPLATE_DIR: &str = "templates";
 const PAGE_DIR: &str = "pages";
@@ -53,19 +67,64 @@ pub enum UserState {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Session {
     pub id: u128,
+    pub user: String,
     pub user_state: UserState,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct GlobalContext {
-    pub user_state: UserState,
+/// what every template sees as `site`, the settings are read at render time
+/// so a saved form shows up without reloading the templates
+#[derive(Debug, Clone, Default)]
+pub struct GlobalContext(Arc<RwLock<Settings>>);
+
+impl GlobalContext {
+    pub fn settings(&self) -> Settings {
+        self.0.read().map(|settings| settings.clone()).unwrap_or_default()
+    }
+
+    pub fn set(&self, settings: Settings) {
+        if let Ok(mut current) = self.0.write() {
+            *current = settings;
+        }
+    }
+
+    pub fn refresh(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        self.set(Settings::load(con)?);
+        Ok(())
+    }
+}
+
+impl minijinja::value::StructObject for GlobalContext {
+    fn get_field(&self, name: &str) -> Option<Value> {
+        Value::from_serializable(&self.settings())
+            .get_attr(name)
+            .ok()
+            .filter(|value| !value.is_undefined())
+    }
+
+    fn static_fields(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "site_title",
+            "title_prefix",
+            "author",
+            "description",
+            "twitter",
+            "base_url",
+        ])
+    }
 }
 
 #[derive(Debug)]
 pub struct SharedState {
     pub db: rusqlite::Connection,
+    /// for background jobs, which open their own connection
+    pub db_path: String,
     pub templates: minijinja::Environment<'static>,
+    /// navigation shown in the header, see [Page::find_menu]
+    pub menu: Menu,
+    pub site: GlobalContext,
     pub sessions: RwLock<Vec<Session>>,
+    pub rate_limit: spam::RateLimiter,
+    pub used_tokens: spam::UsedTokens,
 }
 
 unsafe impl Send for SharedState {}
@@ -76,6 +135,45 @@ enum Command {
     Init,
     Dev,
     Prod,
+    /// renders the public site into an empty directory, for static hosting
+    ExportStatic { dir: PathBuf },
+    /// writes all articles as markdown with front matter and their media, `import` reads it back
+    Export { dir: PathBuf },
+    /// imports a directory of markdown files with front matter, matched to articles by alias
+    Import {
+        dir: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        split: import::Split,
+        /// deepest heading that starts a new paragraph
+        #[arg(long, default_value_t = 2)]
+        heading_level: usize,
+    },
+    /// copies the database into a checked `.tar.gz`, keeping the newest `BACKUP_KEEP`
+    Backup {
+        /// defaults to `BACKUP_DIR`
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// bundles `static/media` with the database
+        #[arg(long)]
+        media: bool,
+    },
+    /// replaces the database, and media if bundled, with a backup. Stop the server first
+    Restore { archive: PathBuf },
+    /// mirrors articles to the git working tree in `SYNC_DIR`
+    #[command(subcommand)]
+    Sync(SyncCommand),
+}
+
+#[derive(Subcommand)]
+enum SyncCommand {
+    /// writes every article into the tree, creating the repository if needed
+    Init,
+    /// pulls the remote and applies changes of the tree, reporting articles changed on both sides
+    Pull {
+        /// resolves conflicts in favour of one side
+        #[arg(long, value_enum)]
+        prefer: Option<sync::Side>,
+    },
 }
 
 // --------------------------------------------------------
@@ -91,12 +189,24 @@ async fn main() {
         .init();
 
     let db_path = std::env::var("DATABASE_PATH").expect("DATABASE_PATH must be set");
-    let db = rusqlite::Connection::open(&db_path).expect("Failed to open database");
+    let db = store::open(&db_path).expect("Failed to open database");
+
+    // fails before `init` created the tables, defaults are used then
+    let menu = Menu::default();
+    menu.refresh(&db).ok();
+    let site = GlobalContext::default();
+    site.refresh(&db).ok();
+    variants::load_cache(&db).ok();
 
     let state = Arc::new(SharedState {
         db,
-        templates: load_templates(),
+        db_path,
+        templates: load_templates(menu.clone(), site.clone()),
+        menu,
+        site,
         sessions: RwLock::new(Vec::new()),
+        rate_limit: spam::RateLimiter::default(),
+        used_tokens: spam::UsedTokens::default(),
     });
 
     let cmd = Command::parse();
@@ -107,10 +217,44 @@ async fn main() {
             Paragraph::up(&state.db).unwrap();
             Stats::up(&state.db).unwrap();
             ContactRequest::up(&state.db).unwrap();
+            ContactReply::up(&state.db).unwrap();
+            Media::up(&state.db).unwrap();
+            MediaVariant::up(&state.db).unwrap();
+            PreviewToken::up(&state.db).unwrap();
+            ArticleAlias::up(&state.db).unwrap();
+            RedirectRule::up(&state.db).unwrap();
+            Series::up(&state.db).unwrap();
+            Related::up(&state.db).unwrap();
+            Comment::up(&state.db).unwrap();
+            Ban::up(&state.db).unwrap();
+            Webhook::up(&state.db).unwrap();
+            WebhookDelivery::up(&state.db).unwrap();
+            SyncState::up(&state.db).unwrap();
+            Page::up(&state.db).unwrap();
+            Page::seed(&state.db).unwrap();
+            Settings::up(&state.db).unwrap();
+            match Article::refresh_all_metrics(&state.db) {
+                Ok(count) => tracing::info!("computed metrics of {} articles", count),
+                Err(err) => tracing::error!("failed to compute article metrics: {}", err),
+            }
+            if let Err(err) = store::related::refresh_all(&state.db) {
+                tracing::error!("failed to compute related articles: {}", err);
+            }
+            match upload::import_existing(&state.db) {
+                Ok(count) => tracing::info!("registered {} existing media files", count),
+                Err(err) => tracing::error!("failed to register media files: {:?}", err),
+            }
+            match variants::generate_missing(&state.db) {
+                Ok(count) => tracing::info!("created variants for {} media files", count),
+                Err(err) => tracing::error!("failed to create variants: {:?}", err),
+            }
         }
         Command::Dev => {
             let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
             tracing::info!("listening on {}", addr);
+            scheduler::spawn(state.db_path.clone());
+            webhooks::spawn(state.db_path.clone());
+            backup::spawn(state.db_path.clone());
             let app = setup_router(state);
             axum::Server::bind(&addr).serve(app).await.unwrap();
         }
@@ -131,10 +275,14 @@ async fn main() {
                     .await
                     .expect("failed to load cert");
 
+            #[allow(clippy::clone_on_copy)]
             let mut addr = SocketAddr::from(([0, 0, 0, 0], https_port.clone()));
 
             tracing::info!("listening on {}", addr);
 
+            scheduler::spawn(state.db_path.clone());
+            webhooks::spawn(state.db_path.clone());
+            backup::spawn(state.db_path.clone());
             let app = setup_router(state);
 
             tokio::spawn(redirect_http_to_https(https_port, http_port));
@@ -143,6 +291,94 @@ async fn main() {
                 .await
                 .unwrap();
         }
+        Command::Import {
+            dir,
+            split,
+            heading_level,
+        } => match import::import_dir(&dir, split, heading_level, &state.db) {
+            Ok(report) => tracing::info!(
+                "imported {}: {} created, {} updated, {} failed",
+                dir.display(),
+                report.created,
+                report.updated,
+                report.failed
+            ),
+            Err(err) => {
+                tracing::error!("import failed: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Command::Export { dir } => match export::export_dir(&dir, &state.db) {
+            Ok(count) => tracing::info!("exported {} articles to {}", count, dir.display()),
+            Err(err) => {
+                tracing::error!("export failed: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Command::ExportStatic { dir } => match static_site::export(&dir, &state) {
+            Ok(count) => tracing::info!("exported {} pages to {}", count, dir.display()),
+            Err(err) => {
+                tracing::error!("export failed: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Command::Backup { dir, media } => {
+            let dir = dir.unwrap_or_else(backup::backup_dir);
+            match backup::create(&state.db, &dir, media)
+                .and_then(|archive| Ok((archive, backup::rotate(&dir, backup::keep())?)))
+            {
+                Ok((archive, removed)) => tracing::info!(
+                    "wrote {}, removed {} old backups",
+                    archive.display(),
+                    removed
+                ),
+                Err(err) => {
+                    tracing::error!("backup failed: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Sync(SyncCommand::Init) => match sync::init(&state.db) {
+            Ok(count) => tracing::info!("mirrored {} articles", count),
+            Err(err) => {
+                tracing::error!("sync failed: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Command::Sync(SyncCommand::Pull { prefer }) => match sync::pull(&state.db, prefer) {
+            Ok(report) => {
+                tracing::info!(
+                    "synced: {} imported, {} exported, {} removed, {} failed",
+                    report.imported,
+                    report.exported,
+                    report.removed,
+                    report.failed
+                );
+                for path in report.conflicts.iter() {
+                    tracing::warn!("conflict: {} changed in the tree and the database", path);
+                }
+                if !report.conflicts.is_empty() {
+                    std::process::exit(2);
+                }
+            }
+            Err(err) => {
+                tracing::error!("sync failed: {}", err);
+                std::process::exit(1);
+            }
+        },
+        Command::Restore { archive } => {
+            match backup::restore(&archive, std::path::Path::new(&state.db_path)) {
+                Ok(()) => tracing::info!(
+                    "restored {}, the previous database is kept as {}.before-restore",
+                    archive.display(),
+                    state.db_path
+                ),
+                Err(err) => {
+                    tracing::error!("restore failed: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
     }
 }
 
@@ -152,6 +388,7 @@ fn setup_router(state: Arc<SharedState>) -> IntoMakeServiceWithConnectInfo<Route
         .route("/favicon.ico", get(get_favicon))
         .nest("/", pages::page_routes())
         .nest("/api", api::api_routes())
+        .fallback(pages::redirect_fallback)
         .with_state(state)
         .into_make_service_with_connect_info::<SocketAddr>()
 }
@@ -235,6 +472,13 @@ async fn asset_handle(uri: Uri) -> impl IntoResponse {
         .skip(1)
         .collect::<PathBuf>();
 
+    if path
+        .components()
+        .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err((StatusCode::NOT_FOUND, "file not found"));
+    }
+
     let file = match tokio::fs::File::open(&path).await {
         Ok(file) => file,
         Err(e) => {
@@ -243,18 +487,39 @@ async fn asset_handle(uri: Uri) -> impl IntoResponse {
         }
     };
 
-    let content_type = match mime_guess::from_path(&path).first_raw() {
-        Some(mime) => mime,
-        None => {
-            return Err((StatusCode::BAD_REQUEST, "unknown content type"));
-        }
+    // wasm must be served as application/wasm for streaming compilation
+    let content_type = match path.extension().and_then(|e| e.to_str()) {
+        Some("wasm") => "application/wasm",
+        Some("js") => "text/javascript",
+        _ => match mime_guess::from_path(&path).first_raw() {
+            Some(mime) => mime,
+            None => {
+                return Err((StatusCode::BAD_REQUEST, "unknown content type"));
+            }
+        },
     };
 
     tracing::info!("serving asset: {:?}", path.to_str());
 
     let stream = tokio_util::io::ReaderStream::new(file);
     let body = StreamBody::new(stream);
-    let headers = [(axum::http::header::CONTENT_TYPE, content_type)];
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        axum::http::HeaderValue::from_static(content_type),
+    );
+    // demo bundles are replaced under the same name, the rest of the media library isn't
+    let cache_control = match path.extension().and_then(|e| e.to_str()) {
+        Some("wasm") | Some("js") => Some("no-cache"),
+        _ if path.starts_with(render::MEDIA_DIR) => Some("public, max-age=86400"),
+        _ => None,
+    };
+    if let Some(cache_control) = cache_control {
+        headers.insert(
+            axum::http::header::CACHE_CONTROL,
+            axum::http::HeaderValue::from_static(cache_control),
+        );
+    }
 
     Ok((headers, body))
 }
@@ -280,10 +545,11 @@ fn load_dir(dir: PathBuf) -> Vec<(String, PathBuf)> {
     files
 }
 
-fn load_templates() -> minijinja::Environment<'static> {
+fn load_templates(menu: Menu, site: GlobalContext) -> minijinja::Environment<'static> {
     let templates = load_dir(TEMPLATE_DIR.into());
 
     let mut env = minijinja::Environment::new();
+    env.add_global("site", Value::from_struct_object(site));
 
     templates.iter().for_each(|(_, path)| {
         let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
@@ -301,6 +567,11 @@ fn load_templates() -> minijinja::Environment<'static> {
 
         env.add_filter("date", date_format);
         env.add_filter("weekday", date_format_smol);
+        env.add_filter("datetime", datetime_format);
+        env.add_filter("datetime_local", datetime_local_format);
+        env.add_function("picture", picture);
+        let menu = menu.clone();
+        env.add_function("menu", move || Value::from_serializable(&menu.items()));
         env.add_template_owned(name, template)
             .expect("error loading template");
     });
@@ -316,6 +587,21 @@ fn date_format(state: &minijinja::State, value: i64) -> String {
     time
 }
 
+fn datetime_format(state: &minijinja::State, value: i64) -> String {
+    match Local.timestamp_opt(value, 0).single() {
+        Some(time) => time.format("%a %d. %B %Y %H:%M").to_string(),
+        None => "".to_string(),
+    }
+}
+
+/// value for `<input type="datetime-local">`
+fn datetime_local_format(state: &minijinja::State, value: Option<i64>) -> String {
+    value
+        .and_then(|value| Local.timestamp_opt(value, 0).single())
+        .map(|time| time.format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_default()
+}
+
 fn date_format_smol(state: &minijinja::State, value: i64) -> String {
     let time = match NaiveDateTime::from_timestamp_opt(value, 0) {
         Some(time) => time.format("%a").to_string(),
@@ -324,3 +610,12 @@ fn date_format_smol(state: &minijinja::State, value: i64) -> String {
     time
 }
 
+/// `{{ picture(src, alt, class, sizes) }}`, responsive markup for media images
+fn picture(src: String, alt: Option<String>, class: Option<String>, sizes: Option<String>) -> Value {
+    Value::from_safe_string(variants::picture(
+        &src,
+        &alt.unwrap_or_default(),
+        &class.unwrap_or_default(),
+        &sizes.unwrap_or_else(|| "100vw".to_string()),
+    ))
+}
@@ -0,0 +1,256 @@
+use crate::mail::{Mail, MailConfig};
+use crate::store::comments::Comment;
+use crate::store::contacts::{ContactReply, ContactRequest};
+use crate::store::settings::Settings;
+
+/// tells the admins a comment waits for moderation
+pub fn comment_posted(comment: &Comment, article_title: &str, settings: &Settings) {
+    Mail {
+        to: MailConfig::from_env().admins,
+        subject: format!("New comment on {}", article_title),
+        body: format!(
+            "{} ({}) wrote:\n\n{}\n\nModerate at {}",
+            comment.author,
+            comment.email,
+            comment.body,
+            settings.link("/comments")
+        ),
+        ..Default::default()
+    }
+    .spawn();
+}
+
+/// tells the parent's author about an approved reply, if they asked for it
+pub fn comment_reply(
+    parent: &Comment,
+    reply: &Comment,
+    article_title: &str,
+    url: &str,
+    settings: &Settings,
+) {
+    if !parent.notify || parent.email.is_empty() || parent.email == reply.email {
+        return;
+    }
+    Mail {
+        to: vec![parent.email.clone()],
+        subject: format!("New reply on {}", article_title),
+        body: format!(
+            "{} replied to your comment:\n\n{}\n\nRead it at {}#comment-{}",
+            reply.author,
+            reply.body,
+            settings.link(url),
+            reply.id.unwrap_or_default()
+        ),
+        ..Default::default()
+    }
+    .spawn();
+}
+
+/// forwards a new contact message to the admins, answering it replies to the sender
+pub fn contact_received(contact: &ContactRequest, settings: &Settings) {
+    contact_forward(contact, settings, &MailConfig::from_env()).spawn();
+}
+
+fn contact_forward(contact: &ContactRequest, settings: &Settings, config: &MailConfig) -> Mail {
+    Mail {
+        to: config.admins.clone(),
+        reply_to: Some(contact.email.clone()),
+        subject: format!("Contact: {}", contact.subject),
+        body: format!(
+            "{} wrote:\n\n{}\n\nSpam score: {}\nInbox: {}",
+            contact.email,
+            contact.message,
+            contact.spam_score,
+            settings.link("/inbox")
+        ),
+        message_id: Some(config.message_id(&format!(
+            "contact-{}",
+            contact.id.unwrap_or_default()
+        ))),
+        ..Default::default()
+    }
+}
+
+/// an answer from the inbox. The forwarded message starts the thread, every
+/// reply that went out answers the previous one, failed ones never reached anybody.
+pub fn contact_answer(
+    contact: &ContactRequest,
+    replies: &[ContactReply],
+    body: &str,
+    message_id: &str,
+    config: &MailConfig,
+) -> Mail {
+    let mut references = vec![config.message_id(&format!(
+        "contact-{}",
+        contact.id.unwrap_or_default()
+    ))];
+    references.extend(
+        replies
+            .iter()
+            .filter(|reply| reply.error.is_none())
+            .map(|reply| reply.message_id.clone()),
+    );
+
+    let quote = contact
+        .message
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Mail {
+        to: vec![contact.email.clone()],
+        subject: match contact.subject.starts_with("Re:") {
+            true => contact.subject.clone(),
+            false => format!("Re: {}", contact.subject),
+        },
+        body: format!("{}\n\n{}", body, quote),
+        message_id: Some(message_id.to_string()),
+        references,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// accepts `count` mails over plain smtp and hands out their raw data
+    fn smtp_listener(count: usize) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                stream.write_all(b"220 localhost\r\n").unwrap();
+
+                let mut data = String::new();
+                let mut in_data = false;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    if in_data {
+                        if line == ".\r\n" {
+                            in_data = false;
+                            stream.write_all(b"250 queued\r\n").unwrap();
+                        } else {
+                            data.push_str(&line);
+                        }
+                    } else {
+                        let command = line.to_ascii_uppercase();
+                        let answer: &[u8] = match command.get(..4).unwrap_or_default() {
+                            "EHLO" => b"250-localhost\r\n250 8BITMIME\r\n",
+                            "DATA" => {
+                                in_data = true;
+                                b"354 go ahead\r\n"
+                            }
+                            "QUIT" => {
+                                let _ = stream.write_all(b"221 bye\r\n");
+                                break;
+                            }
+                            _ => b"250 ok\r\n",
+                        };
+                        stream.write_all(answer).unwrap();
+                    }
+                    line.clear();
+                }
+                tx.send(data).unwrap();
+            }
+        });
+
+        (port, rx)
+    }
+
+    fn header(mail: &str, name: &str) -> Option<String> {
+        let head = mail.split("\r\n\r\n").next().unwrap_or_default();
+        head.replace("\r\n ", " ")
+            .replace("\r\n\t", " ")
+            .split("\r\n")
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+            })
+    }
+
+    fn reply(message_id: &str, error: Option<&str>) -> ContactReply {
+        ContactReply {
+            id: None,
+            contact_id: 7,
+            body: "thanks".to_string(),
+            message_id: message_id.to_string(),
+            sent_by: "admin".to_string(),
+            sent_at: 0,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn contact_mails_thread_over_smtp() {
+        let (port, received) = smtp_listener(2);
+        let config = MailConfig {
+            smtp_host: Some("127.0.0.1".to_string()),
+            smtp_port: Some(port),
+            smtp_tls: Some("none".to_string()),
+            from: Some("Blog <blog@example.com>".to_string()),
+            admins: vec!["admin@example.com".to_string(), "second@example.com".to_string()],
+            ..Default::default()
+        };
+
+        let contact = ContactRequest {
+            id: Some(7),
+            email: "visitor@example.org".to_string(),
+            subject: "Hello".to_string(),
+            message: "first line\nsecond line".to_string(),
+            created: 0,
+            spam_score: 0,
+            read_at: None,
+            archived: false,
+            labels: String::new(),
+        };
+
+        contact_forward(&contact, &Settings::default(), &config)
+            .send(&config)
+            .unwrap();
+        let forwarded = received.recv().unwrap();
+        assert_eq!(
+            header(&forwarded, "To").as_deref(),
+            Some("admin@example.com, second@example.com")
+        );
+        assert_eq!(header(&forwarded, "Reply-To").as_deref(), Some("visitor@example.org"));
+        assert_eq!(
+            header(&forwarded, "Message-ID").as_deref(),
+            Some("<contact-7@example.com>")
+        );
+        assert_eq!(header(&forwarded, "In-Reply-To"), None);
+
+        let replies = [
+            reply("contact-7-a@example.com", None),
+            reply("contact-7-b@example.com", Some("relay refused")),
+        ];
+        contact_answer(&contact, &replies, "an answer", "contact-7-c@example.com", &config)
+            .send(&config)
+            .unwrap();
+        let answer = received.recv().unwrap();
+        assert_eq!(header(&answer, "To").as_deref(), Some("visitor@example.org"));
+        assert_eq!(header(&answer, "Reply-To"), None);
+        assert_eq!(header(&answer, "Subject").as_deref(), Some("Re: Hello"));
+        assert_eq!(
+            header(&answer, "Message-ID").as_deref(),
+            Some("<contact-7-c@example.com>")
+        );
+        assert_eq!(
+            header(&answer, "In-Reply-To").as_deref(),
+            Some("<contact-7-a@example.com>")
+        );
+        assert_eq!(
+            header(&answer, "References").as_deref(),
+            Some("<contact-7@example.com> <contact-7-a@example.com>")
+        );
+        assert!(answer.contains("> first line\r\n> second line"));
+    }
+}
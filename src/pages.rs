@@ -1,13 +1,20 @@
 use crate::store::articles::Article;
+use crate::store::comments::{Ban, Comment, CommentStatus};
+use crate::store::contacts::ContactRequest;
+use crate::store::pages::{Page, PageTemplate};
 use crate::store::paragraphs::Paragraph;
+use crate::store::previews::PreviewToken;
+use crate::store::redirects::{ArticleAlias, RedirectRule};
+use crate::store::series::Series;
 use crate::store::stats::Stats;
+use crate::store::webhooks::WebhookEvent;
 
 use super::auth::Auth;
 use super::store::*;
 use super::SharedState;
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::response::{Html, IntoResponse};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{delete, get, post};
 use axum::Form;
 use axum::Router;
@@ -19,30 +26,115 @@ use tokio_util::either::Either;
 pub fn page_routes() -> Router<Arc<SharedState>, axum::body::Body> {
     Router::new()
         .route("/", get(get_home))
-        .route("/about", get(get_about))
+        .route("/:slug", get(get_page))
         .route("/article/:alias", get(get_article_detail))
-        .route("/donate", get(get_donate))
-        .route("/contact", get(get_contact))
+        .route("/pages", get(get_pages))
+        .route("/settings", get(get_settings))
+        .route("/media", get(get_media))
+        .route("/schedule", get(get_schedule))
+        .route("/redirects", get(get_redirects))
+        .route("/trash", get(get_trash))
+        .route("/comments", get(get_comments))
+        .route("/inbox", get(get_inbox))
+        .route("/webhooks", get(get_webhooks))
+        .route("/series/:slug", get(get_series))
 }
 
 // ----------------------------------------
-// home
-// lommix.de/
+// pages from the database
+// lommix.de/ and lommix.de/:slug
 // ----------------------------------------
-async fn get_home(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
-    let tmpl = match state.templates.get_template("pages/home.html") {
+async fn get_home(
+    State(state): State<Arc<SharedState>>,
+    auth: Auth,
+    uri: Uri,
+) -> Result<Response, (StatusCode, String)> {
+    render_page("", state, auth, uri).await
+}
+
+async fn get_page(
+    Path(slug): Path<String>,
+    State(state): State<Arc<SharedState>>,
+    auth: Auth,
+    uri: Uri,
+) -> Result<Response, (StatusCode, String)> {
+    render_page(&slug, state, auth, uri).await
+}
+
+async fn render_page(
+    slug: &str,
+    state: Arc<SharedState>,
+    auth: Auth,
+    uri: Uri,
+) -> Result<Response, (StatusCode, String)> {
+    // unknown slugs may be covered by a redirect rule
+    let mut page = match Page::find_by_slug(slug, &state.db) {
+        Ok(page) => page,
+        Err(_) => return Ok(redirect_fallback(State(state), uri).await),
+    };
+
+    if !page.published && !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    if auth.is_admin() {
+        page.render_for_admin();
+    }
+
+    let tmpl = match state.templates.get_template(page.template.file()) {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
     };
 
-    match Stats::find_or_create_today(&state.db){
-        Ok(mut stats) => {
-            stats.home_views += 1;
-            stats.update(&state.db);
+    if let Ok(mut stats) = Stats::find_or_create_today(&state.db) {
+        stats.page_views.add(page.id.unwrap());
+        stats.update(&state.db);
+    }
+
+    let templates = PageTemplate::ALL
+        .iter()
+        .map(|template| template.as_str())
+        .collect::<Vec<_>>();
+
+    let rendered = match page.template {
+        PageTemplate::Contact => {
+            let token = crate::spam::FormToken::new();
+            tmpl.render(context! {
+                auth => auth,
+                page => page,
+                templates => templates,
+                token => token.encode(),
+                question => token.question(),
+                honeypot => crate::spam::HONEYPOT_FIELD,
+            })
         }
-        Err(_) => {}
+        _ => tmpl.render(context! {
+            auth => auth,
+            page => page,
+            templates => templates,
+        }),
+    };
+
+    match rendered {
+        Ok(html) => Ok(Html(html).into_response()),
+        Err(_) => Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    }
+}
+
+// ----------------------------------------
+// all pages, admin only
+// lommix.de/pages
+// ----------------------------------------
+async fn get_pages(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
     }
 
+    let tmpl = match state.templates.get_template("pages/pages.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
     let rendered = match tmpl.render(context! {
         auth => auth,
     }) {
@@ -52,24 +144,45 @@ async fn get_home(State(state): State<Arc<SharedState>>, auth: Auth) -> impl Int
 
     Ok(Html(rendered))
 }
+
 // ----------------------------------------
-// about
-// lommix.de/about
+// site settings, admin only
+// lommix.de/settings
 // ----------------------------------------
-async fn get_about(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
-    let tmpl = match state.templates.get_template("pages/about.html") {
+async fn get_settings(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let tmpl = match state.templates.get_template("pages/settings.html") {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
     };
 
-    match Stats::find_or_create_today(&state.db){
-        Ok(mut stats) => {
-            stats.about_views += 1;
-            stats.update(&state.db);
-        }
-        Err(_) => {}
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+// ----------------------------------------
+// media library, admin only
+// lommix.de/media
+// ----------------------------------------
+async fn get_media(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
     }
 
+    let tmpl = match state.templates.get_template("pages/media.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
     let rendered = match tmpl.render(context! {
         auth => auth,
     }) {
@@ -81,17 +194,36 @@ async fn get_about(State(state): State<Arc<SharedState>>, auth: Auth) -> impl In
 }
 
 // ----------------------------------------
-// contact
-// lommix.de/contact
+// series landing page
+// lommix.de/series/:slug
 // ----------------------------------------
-async fn get_contact(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
-    let tmpl = match state.templates.get_template("pages/contact.html") {
+async fn get_series(
+    Path(slug): Path<String>,
+    State(state): State<Arc<SharedState>>,
+    auth: Auth,
+) -> impl IntoResponse {
+    let series = match Series::find_by_slug(&slug, &state.db) {
+        Ok(series) => series,
+        Err(_) => return Err((StatusCode::NOT_FOUND, "not found".to_string())),
+    };
+
+    let articles = match Article::find_by_series(series.id.unwrap(), &state.db) {
+        Ok(articles) => articles
+            .into_iter()
+            .filter(|a| a.is_public() || auth.is_admin())
+            .collect::<Vec<_>>(),
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find articles".to_string())),
+    };
+
+    let tmpl = match state.templates.get_template("pages/series.html") {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
     };
 
     let rendered = match tmpl.render(context! {
         auth => auth,
+        series => series,
+        articles => articles,
     }) {
         Ok(html) => html,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
@@ -101,25 +233,61 @@ async fn get_contact(State(state): State<Arc<SharedState>>, auth: Auth) -> impl
 }
 
 // ----------------------------------------
-// about
-// lommix.de/donate
+// trashed articles and paragraphs, admin only
+// lommix.de/trash
 // ----------------------------------------
-async fn get_donate(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
-    let tmpl = match state.templates.get_template("pages/donate.html") {
+async fn get_trash(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let items = match crate::trash::find_all(&state.db) {
+        Ok(items) => items,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find trash".to_string())),
+    };
+
+    let tmpl = match state.templates.get_template("pages/trash.html") {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
     };
 
-    match Stats::find_or_create_today(&state.db){
-        Ok(mut stats) => {
-            stats.donate_views += 1;
-            stats.update(&state.db);
-        }
-        Err(_) => {}
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+        items => items,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+// ----------------------------------------
+// contact messages, admin only
+// lommix.de/inbox
+// ----------------------------------------
+async fn get_inbox(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
     }
 
+    let (labels, unread) = match (
+        ContactRequest::find_labels(&state.db),
+        ContactRequest::count_unread(&state.db),
+    ) {
+        (Ok(labels), Ok(unread)) => (labels, unread),
+        _ => return Err((StatusCode::BAD_REQUEST, "failed to find messages".to_string())),
+    };
+
+    let tmpl = match state.templates.get_template("pages/inbox.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
     let rendered = match tmpl.render(context! {
         auth => auth,
+        labels => labels,
+        unread => unread,
     }) {
         Ok(html) => html,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
@@ -128,15 +296,212 @@ async fn get_donate(State(state): State<Arc<SharedState>>, auth: Auth) -> impl I
     Ok(Html(rendered))
 }
 
+// ----------------------------------------
+// comment moderation queue, admin only
+// lommix.de/comments?status=pending
+// ----------------------------------------
+#[derive(serde::Deserialize)]
+struct CommentQuery {
+    status: Option<CommentStatus>,
+}
+
+/// a queued comment with the article it belongs to
+#[derive(serde::Serialize)]
+struct QueueEntry {
+    comment: Comment,
+    article_title: String,
+    article_url: String,
+}
+
+async fn get_comments(
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<CommentQuery>,
+    auth: Auth,
+) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let status = query.status.unwrap_or(CommentStatus::Pending);
+    let (comments, bans) = match (
+        Comment::find_by_status(status, &state.db),
+        Ban::find_all(&state.db),
+    ) {
+        (Ok(comments), Ok(bans)) => (comments, bans),
+        _ => return Err((StatusCode::BAD_REQUEST, "failed to find comments".to_string())),
+    };
+
+    let counts = [
+        CommentStatus::Pending,
+        CommentStatus::Approved,
+        CommentStatus::Rejected,
+        CommentStatus::Spam,
+    ]
+    .iter()
+    .map(|s| (s.as_str(), Comment::count_by_status(*s, &state.db).unwrap_or_default()))
+    .collect::<Vec<_>>();
+
+    // comments of trashed articles stay hidden until the article is back
+    let entries = comments
+        .into_iter()
+        .filter_map(|comment| {
+            let article = Article::find(comment.article_id, &state.db).ok()?;
+            Some(QueueEntry {
+                article_title: article.title.clone(),
+                article_url: article.url(),
+                comment,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let tmpl = match state.templates.get_template("pages/comments.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+        status => status,
+        counts => counts,
+        entries => entries,
+        bans => bans,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+// ----------------------------------------
+// upcoming scheduled articles, admin only
+// lommix.de/schedule
+// ----------------------------------------
+async fn get_schedule(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let articles = match Article::find_scheduled(&state.db) {
+        Ok(articles) => articles,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find articles".to_string())),
+    };
+
+    let tmpl = match state.templates.get_template("pages/schedule.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+        articles => articles,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+// ----------------------------------------
+// redirect rules and old aliases, admin only
+// lommix.de/redirects
+// ----------------------------------------
+async fn get_redirects(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let aliases = match ArticleAlias::find_all(&state.db) {
+        Ok(aliases) => aliases,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "failed to find aliases".to_string())),
+    };
+
+    let tmpl = match state.templates.get_template("pages/redirects.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+        aliases => aliases,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+async fn get_webhooks(State(state): State<Arc<SharedState>>, auth: Auth) -> impl IntoResponse {
+    if !auth.is_admin() {
+        return Err((StatusCode::NOT_FOUND, "not found".to_string()));
+    }
+
+    let tmpl = match state.templates.get_template("pages/webhooks.html") {
+        Ok(tmpl) => tmpl,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
+    };
+
+    let events = WebhookEvent::ALL
+        .iter()
+        .map(|event| event.as_str())
+        .collect::<Vec<_>>();
+
+    let rendered = match tmpl.render(context! {
+        auth => auth,
+        events => events,
+    }) {
+        Ok(html) => html,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
+    };
+
+    Ok(Html(rendered))
+}
+
+// ----------------------------------------
+// unknown paths, answered by the admin defined redirect rules
+// ----------------------------------------
+/// 301/302, search engines know those better than axum's 307/308
+fn moved(url: &str, permanent: bool) -> Response {
+    let status = match permanent {
+        true => StatusCode::MOVED_PERMANENTLY,
+        false => StatusCode::FOUND,
+    };
+    match HeaderValue::from_str(url) {
+        Ok(location) => (status, [(header::LOCATION, location)]).into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "not found").into_response(),
+    }
+}
+
+pub async fn redirect_fallback(State(state): State<Arc<SharedState>>, uri: Uri) -> Response {
+    let path = match uri.path().trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+
+    match RedirectRule::find_by_source(path, &state.db) {
+        Ok(rule) => moved(&rule.target, rule.permanent),
+        Err(_) => (StatusCode::NOT_FOUND, "not found").into_response(),
+    }
+}
+
 // ----------------------------------------
 // home
 // lommix.de/article/:alias
 // ----------------------------------------
+#[derive(serde::Deserialize)]
+struct ArticleQuery {
+    preview: Option<String>,
+}
+
 async fn get_article_detail(
     Path(alias): Path<String>,
     auth: Auth,
     State(state): State<Arc<SharedState>>,
-) -> impl IntoResponse {
+    Query(query): Query<ArticleQuery>,
+    uri: Uri,
+) -> Result<Response, (StatusCode, String)> {
 
     //parse alias to int
     let result = match alias.parse::<i64>() {
@@ -144,15 +509,44 @@ async fn get_article_detail(
         Err(_) => Article::find_by_alias(&alias, &state.db),
     };
 
-    let article = match result {
+    // unknown aliases may be old ones or covered by a redirect rule
+    let mut article = match result {
         Ok(article) => article,
-        Err(_) => return Err((StatusCode::NOT_FOUND, "not found".to_string())),
+        Err(_) => match ArticleAlias::find_article_id(&alias, &state.db)
+            .and_then(|id| Article::find(id, &state.db))
+        {
+            Ok(article) => article,
+            Err(_) => return Ok(redirect_fallback(State(state), uri).await),
+        },
     };
 
-    if (!article.published && !auth.is_admin()) {
+    // drafts can be shared with a preview token, see `PreviewToken`
+    let preview = !article.is_public()
+        && !auth.is_admin()
+        && query
+            .preview
+            .as_deref()
+            .and_then(|token| PreviewToken::is_valid(article.id.unwrap(), token, &state.db).ok())
+            .unwrap_or(false);
+
+    // checked before the redirect, which would give away the alias of a draft
+    if (!article.is_public() && !auth.is_admin() && !preview) {
         return Err((StatusCode::NOT_FOUND, "not found".to_string()));
     }
 
+    // ids and old aliases point to the canonical url
+    if alias != article.alias && !article.alias.is_empty() {
+        let url = match uri.query() {
+            Some(query) => format!("{}?{}", article.url(), query),
+            None => article.url(),
+        };
+        return Ok(moved(&url, true));
+    }
+
+    if auth.is_admin() {
+        article.render_for_admin();
+    }
+
     let tmpl = match state.templates.get_template("pages/article.html") {
         Ok(tmpl) => tmpl,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "missing template".to_string())),
@@ -160,20 +554,31 @@ async fn get_article_detail(
 
 
     match Stats::find_or_create_today(&state.db){
-        Ok(mut stats) => {
+        Ok(mut stats) if !preview => {
             stats.article_views.add(article.id.unwrap());
             stats.update(&state.db);
         }
-        Err(_) => {}
+        _ => {}
     }
 
+    let series = Series::nav(article.id.unwrap(), !auth.is_admin(), &state.db)
+        .ok()
+        .flatten();
+
     let rendered = match tmpl.render(context! {
         auth => auth,
         article => article,
+        preview => preview,
+        series => series,
     }) {
         Ok(html) => html,
         Err(_) => return Err((StatusCode::BAD_REQUEST, "fucked up template".to_string())),
     };
 
-    Ok(Html(rendered))
+    let mut header = HeaderMap::new();
+    if preview {
+        header.insert("X-Robots-Tag", "noindex".parse().unwrap());
+    }
+
+    Ok((header, Html(rendered)).into_response())
 }
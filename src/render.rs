@@ -0,0 +1,696 @@
+use crate::store::paragraphs::{Paragraph, ParagraphType};
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+pub const MEDIA_DIR: &str = "static/media";
+const DEFAULT_WASM_HEIGHT: i64 = 600;
+
+// --------------------------------------------------------
+// markdown
+// `$..$` and `$$..$$` are rendered to MathML. Math is cut out
+// before the markdown pass and put back afterwards, so
+// markdown never touches the formulas.
+// `admin` keeps render errors in the output, public renders
+// and the static export never contain them.
+// --------------------------------------------------------
+pub fn markdown(content: &str, admin: bool) -> String {
+    let (content, formulas) = extract_math(content);
+    let mut html = markdown::to_html(&content);
+
+    for (i, (tex, style)) in formulas.iter().enumerate() {
+        let placeholder = math_placeholder(i);
+        let rendered = render_math(tex, *style, admin);
+        if *style == DisplayStyle::Block {
+            html = html.replace(&format!("<p>{}</p>", placeholder), &rendered);
+        }
+        html = html.replace(&placeholder, &rendered);
+    }
+
+    responsive_images(&html)
+}
+
+// --------------------------------------------------------
+// images
+// --------------------------------------------------------
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}='", name))? + name.len() + 3;
+    let end = tag[start..].find('\'')?;
+    Some(&tag[start..start + end])
+}
+
+/// swaps markdown images pointing into the media library for `<picture>` markup
+fn responsive_images(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<img ") {
+        let end = match rest[start..].find("/>") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        let tag = &rest[start..end];
+        out.push_str(&rest[..start]);
+        match attribute(tag, "src").filter(|src| src.starts_with(&format!("/{}/", MEDIA_DIR))) {
+            Some(src) => out.push_str(&crate::variants::picture(
+                src,
+                attribute(tag, "alt").unwrap_or_default(),
+                "",
+                "(min-width: 1024px) 960px, 100vw",
+            )),
+            None => out.push_str(tag),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// --------------------------------------------------------
+// math
+// --------------------------------------------------------
+fn math_placeholder(index: usize) -> String {
+    format!("%%MATH{}%%", index)
+}
+
+/// renders a formula to MathML. On failure the raw tex is shown as plain text,
+/// followed by the error message if `admin` is set.
+pub fn render_math(tex: &str, style: DisplayStyle, admin: bool) -> String {
+    let result = latex_to_mathml(tex, style)
+        .map_err(|err| err.to_string())
+        .and_then(|mathml| match mathml.find("[PARSE ERROR: ") {
+            Some(start) => {
+                let rest = &mathml[start + 1..];
+                Err(rest[..rest.find("]<").unwrap_or(rest.len())].to_string())
+            }
+            None => Ok(mathml),
+        });
+
+    match (result, style) {
+        (Ok(mathml), DisplayStyle::Block) => {
+            format!(r#"<div class="math-block">{}</div>"#, escape_mathml_operators(&mathml))
+        }
+        (Ok(mathml), DisplayStyle::Inline) => escape_mathml_operators(&mathml),
+        (Err(err), style) => {
+            let delimiter = if style == DisplayStyle::Block { "$$" } else { "$" };
+            let error = match admin {
+                true => format!(r#"<span class="math-error">{}</span>"#, escape_html(&err)),
+                false => String::new(),
+            };
+            format!(
+                r#"<span class="math-fallback">{d}{tex}{d}{error}</span>"#,
+                d = delimiter,
+                tex = escape_html(tex),
+                error = error,
+            )
+        }
+    }
+}
+
+/// latex2mathml writes single char operators unescaped (`<mo><</mo>`)
+fn escape_mathml_operators(mathml: &str) -> String {
+    mathml
+        .replace("><</", ">&lt;</")
+        .replace(">></", ">&gt;</")
+        .replace(">&</", ">&amp;</")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// replaces formulas with placeholders. Code fences, inline code and
+/// escaped dollars (`\$`) are left alone. An inline formula must not start or end
+/// with whitespace and must not be followed by a digit, so `$5 and $10` stays text.
+/// `$$..$$` is display math only on a line of its own, inside text it stays inline.
+pub fn extract_math(content: &str) -> (String, Vec<(String, DisplayStyle)>) {
+    let mut out = String::with_capacity(content.len());
+    let mut formulas = Vec::new();
+    let mut in_fence = false;
+    let mut rest = content;
+
+    while !rest.is_empty() {
+        // at line start: handle fenced code blocks
+        if out.is_empty() || out.ends_with('\n') {
+            let line_end = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+            let trimmed = rest[..line_end].trim_start();
+            let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+            if is_fence {
+                in_fence = !in_fence;
+            }
+            if is_fence || in_fence {
+                out.push_str(&rest[..line_end]);
+                rest = &rest[line_end..];
+                continue;
+            }
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+
+        if c == '`' {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let fence = &rest[..ticks];
+            let end = rest[ticks..]
+                .find(fence)
+                .map(|i| ticks + i + ticks)
+                .unwrap_or(ticks);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if rest.starts_with("\\$") {
+            out.push('$');
+            rest = &rest[2..];
+            continue;
+        }
+
+        if let Some(inner) = rest.strip_prefix("$$") {
+            if let Some(end) = inner.find("$$") {
+                let line_start = out.rsplit('\n').next().unwrap_or_default();
+                let line_end = inner[end + 2..].split('\n').next().unwrap_or_default();
+                let style = match line_start.trim().is_empty() && line_end.trim().is_empty() {
+                    true => DisplayStyle::Block,
+                    false => DisplayStyle::Inline,
+                };
+                formulas.push((inner[..end].trim().to_string(), style));
+                out.push_str(&math_placeholder(formulas.len() - 1));
+                rest = &inner[end + 2..];
+                continue;
+            }
+        } else if let Some(inner) = rest.strip_prefix('$') {
+            if let Some(end) = find_inline_end(inner) {
+                formulas.push((inner[..end].to_string(), DisplayStyle::Inline));
+                out.push_str(&math_placeholder(formulas.len() - 1));
+                rest = &inner[end + 1..];
+                continue;
+            }
+        }
+
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    (out, formulas)
+}
+
+fn find_inline_end(inner: &str) -> Option<usize> {
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let line = &inner[..inner.find('\n').unwrap_or(inner.len())];
+    let mut prev = None;
+    for (i, c) in line.char_indices() {
+        if c == '$' && i > 0 && prev != Some('\\') {
+            // a dollar after whitespace opens something else, not a closing one
+            let next = line[i + 1..].chars().next();
+            if prev.is_some_and(char::is_whitespace) || next.is_some_and(|n| n.is_ascii_digit()) {
+                return None;
+            }
+            return Some(i);
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+// --------------------------------------------------------
+// headings
+// every heading of an article gets a slug id and an anchor link.
+// A slug used by several paragraphs stays bare in the oldest one,
+// the others are suffixed with their paragraph id. Adding or
+// moving paragraphs never renames the headings of existing ones.
+// --------------------------------------------------------
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub title: String,
+    pub slug: String,
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct Headings {
+    used: HashSet<String>,
+    /// the paragraph owning the bare slug, see [Headings::claim]
+    owners: HashMap<String, i64>,
+    found: Vec<TocEntry>,
+}
+
+impl Headings {
+    /// registers the headings of a paragraph before any is anchored,
+    /// the lowest paragraph id gets the bare slug
+    pub fn claim(&mut self, html: &str, paragraph_id: i64, keep_ids: bool) {
+        let mut rest = html;
+        while let Some((start, open_end, close, close_tag)) = next_heading(rest) {
+            if !keep_ids || heading_id(&rest[start..open_end]).is_none() {
+                let owner = self
+                    .owners
+                    .entry(slugify(&strip_tags(&rest[open_end..close])))
+                    .or_insert(paragraph_id);
+                *owner = (*owner).min(paragraph_id);
+            }
+            rest = &rest[close + close_tag.len()..];
+        }
+    }
+
+    /// rewrites all `<h1>`..`<h6>` of a rendered paragraph. Other attributes are kept,
+    /// with `keep_ids` an id written by hand is used as is.
+    pub fn anchor(&mut self, html: &str, paragraph_id: i64, keep_ids: bool) -> String {
+        let mut out = String::with_capacity(html.len());
+        let mut rest = html;
+
+        while let Some((start, open_end, close, close_tag)) = next_heading(rest) {
+            let level = rest.as_bytes()[start + 2] - b'0';
+            let open_tag = &rest[start..open_end];
+
+            let inner = &rest[open_end..close];
+            let title = strip_tags(inner);
+            let (slug, open_tag) = match heading_id(open_tag).filter(|_| keep_ids) {
+                Some(id) => {
+                    self.used.insert(id.to_string());
+                    (id.to_string(), open_tag.to_string())
+                }
+                None => {
+                    let slug = self.unique_slug(&title, paragraph_id);
+                    let attributes = without_id(&open_tag[3..open_tag.len() - 1]);
+                    (slug.clone(), format!(r#"<h{} id="{}"{}>"#, level, slug, attributes))
+                }
+            };
+
+            out.push_str(&rest[..start]);
+            out.push_str(&format!(
+                r##"{open_tag}{inner}<a class="heading-anchor" href="#{slug}" aria-hidden="true">#</a>{close_tag}"##,
+            ));
+
+            self.found.push(TocEntry {
+                level,
+                title,
+                slug,
+                children: Vec::new(),
+            });
+
+            rest = &rest[close + close_tag.len()..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    fn unique_slug(&mut self, title: &str, paragraph_id: i64) -> String {
+        let base = slugify(title);
+        let mut slug = base.clone();
+        let mut n = 1;
+        let owned = self.owners.get(&base).is_none_or(|owner| *owner == paragraph_id);
+        if !owned {
+            slug = format!("{}-{}", base, paragraph_id);
+            n = 2;
+        }
+        while self.used.contains(&slug) {
+            slug = match n {
+                1 => format!("{}-{}", base, paragraph_id),
+                _ => format!("{}-{}-{}", base, paragraph_id, n),
+            };
+            n += 1;
+        }
+        self.used.insert(slug.clone());
+        slug
+    }
+
+    /// nests the headings found so far by level
+    pub fn toc(self) -> Vec<TocEntry> {
+        fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+            match entries.last_mut() {
+                Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+                _ => entries.push(entry),
+            }
+        }
+
+        let mut toc = Vec::new();
+        for entry in self.found {
+            insert(&mut toc, entry);
+        }
+        toc
+    }
+}
+
+fn find_heading(html: &str) -> Option<usize> {
+    html.match_indices("<h").map(|(i, _)| i).find(|i| {
+        let bytes = &html.as_bytes()[*i + 2..];
+        bytes.len() > 1 && (b'1'..=b'6').contains(&bytes[0]) && matches!(bytes[1], b'>' | b' ')
+    })
+}
+
+/// start and end of the opening tag, start of the closing tag and the closing tag of the next heading
+fn next_heading(html: &str) -> Option<(usize, usize, usize, String)> {
+    let start = find_heading(html)?;
+    let close_tag = format!("</h{}>", html.as_bytes()[start + 2] - b'0');
+    let open_end = start + html[start..].find('>')? + 1;
+    let close = open_end + html[open_end..].find(&close_tag)?;
+    Some((start, open_end, close, close_tag))
+}
+
+/// position of the ` id=` attribute in a tag and its quote
+fn find_id(tag: &str) -> Option<(usize, char)> {
+    let start = tag.find(" id=")?;
+    let quote = tag[start + 4..].chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    Some((start, quote))
+}
+
+/// the `id` of an opening heading tag
+fn heading_id(open_tag: &str) -> Option<&str> {
+    let (start, quote) = find_id(open_tag)?;
+    let value = &open_tag[start + 5..];
+    let end = value.find(quote)?;
+    Some(&value[..end]).filter(|id| !id.is_empty())
+}
+
+/// the attributes of a tag without its `id`
+fn without_id(attributes: &str) -> String {
+    match find_id(attributes) {
+        Some((start, quote)) => {
+            let value = &attributes[start + 5..];
+            let end = value.find(quote).map(|end| start + 5 + end + 1).unwrap_or(attributes.len());
+            format!("{}{}", &attributes[..start], &attributes[end..])
+        }
+        None => attributes.to_string(),
+    }
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    match slug.is_empty() {
+        true => "section".to_string(),
+        false => slug,
+    }
+}
+
+// --------------------------------------------------------
+// metrics
+// --------------------------------------------------------
+/// returns words, code blocks and images of a paragraph. Code is not counted as words.
+pub fn text_metrics(paragraph: &Paragraph) -> (i64, i64, i64) {
+    let html = match (&paragraph.paragraph_type, &paragraph.rendered) {
+        (ParagraphType::Wasm, _) => return (0, 0, 0),
+        (_, Some(rendered)) => rendered.as_str(),
+        (_, None) => paragraph.content.as_str(),
+    };
+
+    let code_blocks = html.matches("<pre").count() as i64;
+    let images = html.matches("<img").count() as i64;
+
+    let mut prose = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("<pre") {
+        prose.push_str(&rest[..start]);
+        rest = match rest[start..].find("</pre>") {
+            Some(end) => &rest[start + end + "</pre>".len()..],
+            None => "",
+        };
+    }
+    prose.push_str(rest);
+
+    let words = strip_tags(&prose).split_whitespace().count() as i64;
+    (words, code_blocks, images)
+}
+
+// --------------------------------------------------------
+// wasm demo
+// content is a small json document:
+// {"wasm": "/static/media/1/game_bg.wasm", "script": "/static/media/1/game.js", "height": 600}
+// --------------------------------------------------------
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WasmDemo {
+    pub wasm: String,
+    pub script: String,
+    #[serde(default = "default_wasm_height")]
+    pub height: i64,
+}
+
+fn default_wasm_height() -> i64 {
+    DEFAULT_WASM_HEIGHT
+}
+
+impl WasmDemo {
+    pub fn parse(content: &str) -> Result<Self, &'static str> {
+        serde_json::from_str::<WasmDemo>(content).map_err(|_| "invalid wasm paragraph")
+    }
+
+    /// both files must be uploaded to the media library and have the right extension
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.height <= 0 || self.height > 4096 {
+            return Err("invalid wasm height");
+        }
+        media_file_exists(&self.wasm, "wasm").map_err(|_| "wasm file not found")?;
+        media_file_exists(&self.script, "js").map_err(|_| "script file not found")?;
+        Ok(())
+    }
+
+    /// the urls end up inside a js string in an html attribute,
+    /// anything but a plain media path is refused
+    pub fn to_html(&self, paragraph_id: i64) -> Result<String, &'static str> {
+        if !is_media_url(&self.wasm) || !is_media_url(&self.script) {
+            return Err("invalid wasm paragraph");
+        }
+        let canvas_id = format!("wasm-{}", paragraph_id);
+        Ok(format!(
+            r#"<div class="wasm-demo w-full relative" style="min-height: {height}px;">
+	<canvas id="{canvas_id}" class="hidden"></canvas>
+	<button class="wasm-start w-full bg-black text-white text-4xl flex justify-center items-center" style="height: {height}px;"
+		onclick="this.remove(); run_wasm('{wasm}', '{script}', '#{canvas_id}', {height})">
+		Click to load
+	</button>
+</div>"#,
+            height = self.height,
+            canvas_id = canvas_id,
+            wasm = self.wasm,
+            script = self.script,
+        ))
+    }
+}
+
+/// a `/static/media/..` url made of plain path characters only, safe inside html and js
+fn is_media_url(url: &str) -> bool {
+    url.strip_prefix('/').is_some_and(|relative| {
+        relative.starts_with(MEDIA_DIR)
+            && !relative.contains("..")
+            && relative
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '-' | '_'))
+    })
+}
+
+/// checks that a `/static/media/..` url points to an existing file with the given extension.
+fn media_file_exists(url: &str, extension: &str) -> Result<PathBuf, ()> {
+    if !is_media_url(url) {
+        return Err(());
+    }
+
+    let path = PathBuf::from(&url[1..]);
+    if path.extension().and_then(|e| e.to_str()) != Some(extension) || !path.is_file() {
+        return Err(());
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formulas(content: &str) -> Vec<(String, DisplayStyle)> {
+        extract_math(content).1
+    }
+
+    #[test]
+    fn extracts_inline_math() {
+        let (out, found) = extract_math("mass $E = mc^2$ energy");
+        assert_eq!(out, "mass %%MATH0%% energy");
+        assert_eq!(found, vec![("E = mc^2".to_string(), DisplayStyle::Inline)]);
+    }
+
+    #[test]
+    fn extracts_display_math() {
+        let (out, found) = extract_math("before\n\n$$ \\sum_i x_i $$\n\nafter");
+        assert_eq!(out, "before\n\n%%MATH0%%\n\nafter");
+        assert_eq!(found, vec![("\\sum_i x_i".to_string(), DisplayStyle::Block)]);
+    }
+
+    #[test]
+    fn keeps_escaped_dollars() {
+        let (out, found) = extract_math("costs \\$5 or \\$10");
+        assert_eq!(out, "costs $5 or $10");
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn keeps_unbalanced_dollars() {
+        assert!(formulas("costs $5 and $10").is_empty());
+        assert!(formulas("a single $ sign").is_empty());
+        assert!(formulas("$$ never closed").is_empty());
+        assert!(formulas("$x\n$").is_empty());
+    }
+
+    #[test]
+    fn skips_code() {
+        let (out, found) = extract_math("run `echo $HOME $PATH` or ``a $b$ c``");
+        assert_eq!(out, "run `echo $HOME $PATH` or ``a $b$ c``");
+        assert!(found.is_empty());
+
+        let fenced = "```sh\nexport A=$x$\n$$y$$\n```\n$z$";
+        let (out, found) = extract_math(fenced);
+        assert_eq!(out, "```sh\nexport A=$x$\n$$y$$\n```\n%%MATH0%%");
+        assert_eq!(found, vec![("z".to_string(), DisplayStyle::Inline)]);
+    }
+
+    #[test]
+    fn renders_valid_math() {
+        let inline = render_math("x^2", DisplayStyle::Inline, false);
+        assert!(inline.starts_with("<math"));
+        assert!(!inline.contains("math-error"));
+
+        let block = render_math("a < b", DisplayStyle::Block, false);
+        assert!(block.starts_with(r#"<div class="math-block"><math"#));
+        assert!(block.contains("&lt;"));
+    }
+
+    #[test]
+    fn falls_back_on_invalid_tex() {
+        let html = render_math("\\frac{1}{<b>", DisplayStyle::Inline, true);
+        assert!(html.starts_with(r#"<span class="math-fallback">$\frac{1}{&lt;b&gt;$<span class="math-error">"#));
+        assert!(!html.contains("<math"));
+
+        let html = render_math("\\frac{", DisplayStyle::Block, true);
+        assert!(html.starts_with(r#"<span class="math-fallback">$$\frac{$$"#));
+    }
+
+    #[test]
+    fn hides_tex_errors_from_readers() {
+        let html = render_math("\\frac{", DisplayStyle::Inline, false);
+        assert_eq!(html, r#"<span class="math-fallback">$\frac{$</span>"#);
+        assert!(!markdown("broken $\\frac{$ tex", false).contains("math-error"));
+        assert!(markdown("broken $\\frac{$ tex", true).contains("math-error"));
+    }
+
+    #[test]
+    fn display_math_only_on_its_own_line() {
+        assert_eq!(formulas("text $$x$$ text")[0].1, DisplayStyle::Inline);
+        assert_eq!(formulas("text\n  $$x$$  \ntext")[0].1, DisplayStyle::Block);
+        assert_eq!(formulas("$$\nx\n$$")[0].1, DisplayStyle::Block);
+
+        let html = markdown("sum $$\\sum_i x_i$$ of all", false);
+        assert!(!html.contains("math-block"));
+        assert!(html.starts_with("<p>sum <math"));
+    }
+
+    #[test]
+    fn markdown_renders_math_untouched() {
+        let html = markdown("some *emph* and $a_1 * b_2$\n\n$$x_1$$", false);
+        assert!(html.contains("<em>emph</em>"));
+        assert!(!html.contains("%%MATH"));
+        assert!(!html.contains("<p><div"));
+        assert_eq!(html.matches("<math").count(), 2);
+        assert!(html.contains(r#"<div class="math-block">"#));
+    }
+
+    #[test]
+    fn markdown_keeps_dollars_in_code() {
+        let html = markdown("`$x$`\n\n```\n$$y$$\n```", false);
+        assert!(!html.contains("<math"));
+        assert!(html.contains("<code>$x$</code>"));
+        assert!(html.contains("$$y$$"));
+    }
+
+    fn paragraph(id: i64, paragraph_type: ParagraphType, content: &str) -> Paragraph {
+        let mut para = Paragraph {
+            id: Some(id),
+            article_id: Some(1),
+            page_id: None,
+            title: String::new(),
+            description: String::new(),
+            paragraph_type,
+            position: 0,
+            content: content.to_string(),
+            rendered: None,
+        };
+        para.render(false);
+        para
+    }
+
+    fn anchored(mut paragraphs: Vec<Paragraph>) -> Vec<String> {
+        Paragraph::anchor_headings(&mut paragraphs);
+        paragraphs.into_iter().filter_map(|p| p.rendered).collect()
+    }
+
+    #[test]
+    fn heading_ids_survive_earlier_duplicates() {
+        let before = anchored(vec![paragraph(5, ParagraphType::Markdown, "## Setup")]);
+        assert!(before[0].starts_with(r#"<h2 id="setup">"#));
+
+        // a newer paragraph with the same heading placed in front
+        let after = anchored(vec![
+            paragraph(9, ParagraphType::Markdown, "## Setup"),
+            paragraph(5, ParagraphType::Markdown, "## Setup\n\n## Setup"),
+        ]);
+        assert!(after[0].starts_with(r#"<h2 id="setup-9">"#));
+        assert!(after[1].starts_with(r#"<h2 id="setup">"#));
+        assert!(after[1].contains(r#"<h2 id="setup-5">"#));
+    }
+
+    #[test]
+    fn anchors_html_headings() {
+        let html = anchored(vec![
+            paragraph(1, ParagraphType::Html, r#"<h2 class="big">Intro</h2><h3 id="custom">Custom</h3>"#),
+            paragraph(2, ParagraphType::Markdown, "## Custom"),
+        ]);
+        assert!(html[0].starts_with(r##"<h2 id="intro" class="big">Intro<a class="heading-anchor" href="#intro""##));
+        assert!(html[0].contains(r##"<h3 id="custom">Custom<a class="heading-anchor" href="#custom""##));
+        assert!(html[1].starts_with(r#"<h2 id="custom-2">"#));
+    }
+
+    #[test]
+    fn wasm_demo_refuses_unsafe_urls() {
+        let demo = |wasm: &str| WasmDemo {
+            wasm: wasm.to_string(),
+            script: "/static/media/1/demo.js".to_string(),
+            height: 600,
+        };
+        assert!(demo("/static/media/1/demo_bg.wasm").to_html(1).is_ok());
+        assert!(demo("/static/media/1/x.wasm', alert(1), '").to_html(1).is_err());
+        assert!(demo("/static/media/1/x.wasm\" onmouseover=\"alert(1)").to_html(1).is_err());
+        assert!(demo("/static/media/../../x.wasm").to_html(1).is_err());
+        assert!(demo("https://example.com/x.wasm").to_html(1).is_err());
+    }
+}
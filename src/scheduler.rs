@@ -0,0 +1,73 @@
+use crate::store::articles::Article;
+use crate::store::webhooks::WebhookEvent;
+use crate::store::Crud;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// how often the jobs run
+const INTERVAL: Duration = Duration::from_secs(30);
+
+/// periodic background jobs: publishing scheduled articles and emptying the trash
+pub fn spawn(db_path: String) {
+    spawn_periodic("scheduler", INTERVAL, db_path, tick);
+}
+
+/// runs `job` every `interval` for the lifetime of the server, on a blocking thread with
+/// its own connection. The first run is one interval after the start, a restart should not
+/// trigger a burst of work. `job` logs its own failures.
+pub fn spawn_periodic<F>(name: &'static str, interval: Duration, db_path: String, job: F)
+where
+    F: Fn(&rusqlite::Connection) + Send + Sync + 'static,
+{
+    let job = Arc::new(job);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            interval.tick().await;
+            let db_path = db_path.clone();
+            let job = job.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let con = crate::store::open(&db_path)?;
+                job(&con);
+                Ok::<_, rusqlite::Error>(())
+            })
+            .await;
+
+            match result {
+                Ok(Ok(_)) => (),
+                Ok(Err(err)) => tracing::error!("{}: failed to open database: {}", name, err),
+                Err(err) => tracing::error!("{}: task failed: {}", name, err),
+            }
+        }
+    });
+}
+
+fn tick(con: &rusqlite::Connection) {
+    match Article::publish_due(chrono::offset::Local::now().timestamp(), con) {
+        Ok(ids) if ids.is_empty() => (),
+        Ok(ids) => {
+            tracing::info!("scheduler: published articles {:?}", ids);
+            for article in ids.iter().filter_map(|id| Article::find(*id, con).ok()) {
+                crate::webhooks::emit(
+                    WebhookEvent::ArticlePublished,
+                    crate::webhooks::article_data(&article),
+                    con,
+                );
+                if let Err(err) = crate::sync::mirror_article(article.id.unwrap_or_default(), con) {
+                    tracing::error!("scheduler: failed to mirror article: {}", err);
+                }
+                // related articles are cached, the new ones only show up after a refresh
+                if let Err(err) = crate::store::related::refresh(article.id.unwrap_or_default(), con) {
+                    tracing::error!("scheduler: failed to refresh related articles: {}", err);
+                }
+            }
+        }
+        Err(err) => tracing::error!("scheduler: failed to publish: {}", err),
+    }
+
+    match crate::trash::purge_expired(con) {
+        Ok(0) => (),
+        Ok(count) => tracing::info!("scheduler: purged {} items from the trash", count),
+        Err(err) => tracing::error!("scheduler: failed to purge trash: {}", err),
+    }
+}
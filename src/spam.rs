@@ -0,0 +1,334 @@
+use crate::util::{hex, unhex};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// name of the hidden form field bots tend to fill in
+pub const HONEYPOT_FIELD: &str = "website";
+/// signs form tokens, a random one per start is used if unset
+const FORM_SECRET: &str = "FORM_SECRET";
+/// humans need at least this many seconds to fill in a form
+pub const MIN_FORM_SECONDS: i64 = 3;
+/// forms older than this must be reloaded
+pub const MAX_FORM_SECONDS: i64 = 24 * 60 * 60;
+/// messages scoring this or more are most likely spam
+pub const SPAM_THRESHOLD: i64 = 5;
+
+/// a filled honeypot field means the form was not posted by a human
+pub fn honeypot_filled(value: &Option<String>) -> bool {
+    value.as_deref().map(|v| !v.trim().is_empty()).unwrap_or(false)
+}
+
+/// counts posts per client in a sliding window, shared by comments and the contact form
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    hits: Mutex<HashMap<String, Vec<i64>>>,
+}
+
+impl RateLimiter {
+    /// records a post of `key` and tells whether it stays within `max` posts per `window` seconds.
+    /// Rejected posts are not recorded, a blocked client is free again once the window passed.
+    pub fn allow(&self, key: &str, max: usize, window: i64) -> bool {
+        let now = chrono::offset::Local::now().timestamp();
+        let mut hits = match self.hits.lock() {
+            Ok(hits) => hits,
+            Err(_) => return true,
+        };
+
+        hits.retain(|_, times| {
+            times.retain(|t| *t > now - window);
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key.to_string()).or_default();
+        if times.len() >= max {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(key: &[u8], message: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(message);
+    mac
+}
+
+/// hmac-sha256 as of rfc 2104
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    mac(key, message).finalize().into_bytes().into()
+}
+
+fn secret() -> &'static [u8] {
+    static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+    SECRET.get_or_init(|| match std::env::var(FORM_SECRET) {
+        Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+        _ => rand::random::<[u8; 32]>().to_vec(),
+    })
+}
+
+/// signed into each form: when it was served and a small sum the reader has to solve.
+/// Bots posting right away or tampering with the challenge are caught without any state,
+/// replays are caught by [UsedTokens].
+#[derive(Debug, Clone, Copy)]
+pub struct FormToken {
+    pub issued_at: i64,
+    a: u8,
+    b: u8,
+    /// tells apart forms served in the same second with the same sum
+    nonce: u32,
+    signature: [u8; 32],
+}
+
+impl FormToken {
+    pub fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut token = FormToken {
+            issued_at: chrono::offset::Local::now().timestamp(),
+            a: rng.gen_range(1..10),
+            b: rng.gen_range(1..10),
+            nonce: rng.gen(),
+            signature: [0; 32],
+        };
+        token.signature = hmac_sha256(secret(), token.payload().as_bytes());
+        token
+    }
+
+    fn payload(&self) -> String {
+        format!("{}.{}.{}.{}", self.issued_at, self.a, self.b, self.nonce)
+    }
+
+    /// `<issued_at>.<a>.<b>.<nonce>.<signature>`
+    pub fn encode(&self) -> String {
+        format!("{}.{}", self.payload(), hex(&self.signature))
+    }
+
+    /// `None` if the token was not issued by us
+    pub fn decode(token: &str) -> Option<Self> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let mut parts = payload.splitn(4, '.');
+        let token = FormToken {
+            issued_at: parts.next()?.parse().ok()?,
+            a: parts.next()?.parse().ok()?,
+            b: parts.next()?.parse().ok()?,
+            nonce: parts.next()?.parse().ok()?,
+            signature: unhex(signature)?.try_into().ok()?,
+        };
+        mac(secret(), token.payload().as_bytes())
+            .verify_slice(&token.signature)
+            .ok()?;
+        Some(token)
+    }
+
+    pub fn question(&self) -> String {
+        format!("What is {} + {}?", self.a, self.b)
+    }
+
+    pub fn is_answer(&self, answer: &str) -> bool {
+        answer.trim().parse::<u16>().ok() == Some(self.a as u16 + self.b as u16)
+    }
+
+    /// seconds since the form was served
+    pub fn age(&self) -> i64 {
+        chrono::offset::Local::now().timestamp() - self.issued_at
+    }
+}
+
+impl Default for FormToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// signatures of form tokens already posted, kept until the tokens expire anyway
+#[derive(Debug, Default)]
+pub struct UsedTokens {
+    used: Mutex<HashMap<[u8; 32], i64>>,
+}
+
+impl UsedTokens {
+    /// marks the token as used and tells whether this is its first use
+    pub fn spend(&self, token: &FormToken) -> bool {
+        let now = chrono::offset::Local::now().timestamp();
+        let mut used = match self.used.lock() {
+            Ok(used) => used,
+            Err(_) => return true,
+        };
+
+        used.retain(|_, issued_at| *issued_at >= now - MAX_FORM_SECONDS);
+        used.insert(token.signature, token.issued_at).is_none()
+    }
+}
+
+fn count_links(text: &str) -> usize {
+    text.matches("http://").count() + text.matches("https://").count() + text.matches("www.").count()
+}
+
+/// a rough guess how spammy a message is, see [SPAM_THRESHOLD]
+pub fn score(email: &str, subject: &str, message: &str, seconds_taken: i64) -> i64 {
+    let mut score = 0;
+
+    score += 2 * count_links(message).min(5) as i64;
+    if count_links(subject) > 0 {
+        score += 3;
+    }
+    if message.contains("[url") || message.contains("<a href") {
+        score += 3;
+    }
+    if message.split_whitespace().count() < 3 {
+        score += 1;
+    }
+    if subject.trim() == message.trim() {
+        score += 1;
+    }
+    let letters = message.chars().filter(|c| c.is_alphabetic()).count();
+    let upper = message.chars().filter(|c| c.is_uppercase()).count();
+    if letters > 20 && upper * 2 > letters {
+        score += 2;
+    }
+    if seconds_taken < 10 {
+        score += 2;
+    }
+    if email.split('@').next().is_some_and(|local| local.chars().filter(|c| c.is_ascii_digit()).count() > 5) {
+        score += 1;
+    }
+
+    score
+}
+
+/// `name@domain.tld` without spaces, good enough to catch typos
+pub fn valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    email.len() <= 254
+        && !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issued(seconds_ago: i64) -> FormToken {
+        let mut token = FormToken::new();
+        token.issued_at -= seconds_ago;
+        token.signature = hmac_sha256(secret(), token.payload().as_bytes());
+        token
+    }
+
+    #[test]
+    fn form_token_round_trip() {
+        let token = FormToken::new();
+        let decoded = FormToken::decode(&token.encode()).unwrap();
+        assert_eq!(decoded.issued_at, token.issued_at);
+        assert_eq!(decoded.question(), token.question());
+        assert_eq!(decoded.signature, token.signature);
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let token = FormToken { a: 2, b: 3, ..FormToken::new() };
+        let token = FormToken {
+            signature: hmac_sha256(secret(), token.payload().as_bytes()),
+            ..token
+        };
+        let encoded = token.encode();
+        assert!(FormToken::decode(&encoded).is_some());
+
+        let (payload, signature) = encoded.rsplit_once('.').unwrap();
+        let easier = payload.replacen(".2.3.", ".1.1.", 1);
+        assert!(FormToken::decode(&format!("{}.{}", easier, signature)).is_none());
+        let older = format!("{}.{}", token.issued_at - 60, &payload[payload.find('.').unwrap() + 1..]);
+        assert!(FormToken::decode(&format!("{}.{}", older, signature)).is_none());
+
+        let mut flipped = signature.to_string();
+        let last = if flipped.ends_with('0') { "1" } else { "0" };
+        flipped.replace_range(flipped.len() - 1.., last);
+        assert!(FormToken::decode(&format!("{}.{}", payload, flipped)).is_none());
+
+        assert!(FormToken::decode(payload).is_none());
+        assert!(FormToken::decode(&format!("{}.{}", payload, &signature[2..])).is_none());
+        assert!(FormToken::decode(&format!("{}.zz{}", payload, &signature[2..])).is_none());
+        assert!(FormToken::decode("").is_none());
+    }
+
+    #[test]
+    fn form_token_age() {
+        let fresh = FormToken::decode(&FormToken::new().encode()).unwrap();
+        assert!(fresh.age() < MIN_FORM_SECONDS);
+
+        let filled = FormToken::decode(&issued(30).encode()).unwrap();
+        assert!((MIN_FORM_SECONDS..=MAX_FORM_SECONDS).contains(&filled.age()));
+
+        let stale = FormToken::decode(&issued(MAX_FORM_SECONDS + 1).encode()).unwrap();
+        assert!(stale.age() > MAX_FORM_SECONDS);
+    }
+
+    #[test]
+    fn is_answer_takes_the_sum() {
+        let token = FormToken { a: 4, b: 9, ..FormToken::new() };
+        assert_eq!(token.question(), "What is 4 + 9?");
+        assert!(token.is_answer("13"));
+        assert!(token.is_answer(" 13\n"));
+        assert!(!token.is_answer("12"));
+        assert!(!token.is_answer("thirteen"));
+        assert!(!token.is_answer(""));
+    }
+
+    #[test]
+    fn tokens_are_spent_once() {
+        let used = UsedTokens::default();
+        let token = FormToken::decode(&issued(30).encode()).unwrap();
+        assert!(used.spend(&token));
+        assert!(!used.spend(&FormToken::decode(&token.encode()).unwrap()));
+        assert!(used.spend(&issued(30)));
+
+        // expired signatures are dropped, the tokens are refused by age anyway
+        used.spend(&issued(MAX_FORM_SECONDS + 1));
+        assert!(used.spend(&issued(30)));
+        assert_eq!(used.used.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rate_limiter_window() {
+        let limiter = RateLimiter::default();
+        assert!(limiter.allow("a", 2, 60));
+        assert!(limiter.allow("a", 2, 60));
+        assert!(!limiter.allow("a", 2, 60));
+        assert!(limiter.allow("b", 2, 60));
+
+        // once the window passed the client is free again
+        let now = chrono::offset::Local::now().timestamp();
+        limiter
+            .hits
+            .lock()
+            .unwrap()
+            .insert("a".to_string(), vec![now - 61, now - 61]);
+        assert!(limiter.allow("a", 2, 60));
+        assert_eq!(limiter.hits.lock().unwrap()["a"].len(), 1);
+    }
+
+    #[test]
+    fn valid_emails() {
+        assert!(valid_email("reader@example.com"));
+        assert!(valid_email("first.last+tag@mail.example.org"));
+        assert!(!valid_email("reader"));
+        assert!(!valid_email("@example.com"));
+        assert!(!valid_email("reader@localhost"));
+        assert!(!valid_email("reader@.example.com"));
+        assert!(!valid_email("reader@example.com."));
+        assert!(!valid_email("reader@exa@mple.com"));
+        assert!(!valid_email("read er@example.com"));
+        assert!(!valid_email(&format!("{}@example.com", "a".repeat(250))));
+    }
+}
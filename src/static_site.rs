@@ -0,0 +1,532 @@
+use crate::auth::Auth;
+use crate::store::articles::{Article, ArticleOrder};
+use crate::store::pages::Page as SitePage;
+use crate::store::series::Series;
+use crate::store::settings::Settings;
+use crate::store::{related, Crud};
+use crate::SharedState;
+use minijinja::context;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// related articles shown below an article, same as the live site
+const RELATED_SHOWN: usize = 3;
+/// url attributes rewritten to relative paths
+const URL_ATTRIBUTES: [&str; 4] = ["href", "src", "poster", "data"];
+
+#[derive(Debug)]
+pub enum StaticExportError {
+    /// the output directory must be empty, stale files would break the diff
+    NotEmpty(PathBuf),
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+    Template(minijinja::Error),
+}
+
+impl std::fmt::Display for StaticExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StaticExportError::NotEmpty(dir) => write!(f, "{} is not empty", dir.display()),
+            StaticExportError::Io(err) => write!(f, "io: {}", err),
+            StaticExportError::Db(err) => write!(f, "database: {}", err),
+            StaticExportError::Template(err) => write!(f, "template: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for StaticExportError {
+    fn from(err: std::io::Error) -> Self {
+        StaticExportError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for StaticExportError {
+    fn from(err: rusqlite::Error) -> Self {
+        StaticExportError::Db(err)
+    }
+}
+
+impl From<minijinja::Error> for StaticExportError {
+    fn from(err: minijinja::Error) -> Self {
+        StaticExportError::Template(err)
+    }
+}
+
+/// a rendered page and its site path
+struct Page {
+    path: String,
+    html: String,
+    /// for the sitemap
+    updated_at: Option<i64>,
+}
+
+/// renders the public site as seen by a visitor into `dir`.
+/// Pages become `<path>/index.html` with relative links, so the tree works from
+/// any static host or straight from disk. Rendering the same database twice
+/// gives the same files.
+pub fn export(dir: &Path, state: &SharedState) -> Result<usize, StaticExportError> {
+    if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+        return Err(StaticExportError::NotEmpty(dir.to_path_buf()));
+    }
+
+    let pages = render_pages(state)?;
+    let mut assets = BTreeSet::new();
+
+    for page in pages.iter() {
+        let file = page_file(&page.path);
+        let html = relative_links(&page.html, &prefix(&file), &mut assets);
+        write(&dir.join(&file), html.as_bytes())?;
+    }
+
+    // stylesheets may pull in fonts and images themselves
+    for css in assets.clone().iter().filter(|a| a.ends_with(".css")) {
+        if let Ok(content) = std::fs::read_to_string(css.trim_start_matches('/')) {
+            collect_css_urls(&content, &mut assets);
+        }
+    }
+
+    for asset in assets.iter() {
+        let source = PathBuf::from(asset.trim_start_matches('/'));
+        if !is_safe(&source) || !source.is_file() {
+            tracing::warn!("export: missing asset {}", asset);
+            continue;
+        }
+        let target = dir.join(&source);
+        match source.extension().and_then(|e| e.to_str()) {
+            Some("css") => {
+                let content = std::fs::read_to_string(&source)?;
+                let css = relative_css_urls(&content, &prefix(&source.to_string_lossy()));
+                write(&target, css.as_bytes())?;
+            }
+            _ => {
+                create_parent(&target)?;
+                std::fs::copy(&source, &target)?;
+            }
+        }
+    }
+
+    if Path::new("static/favicon.ico").is_file() {
+        std::fs::copy("static/favicon.ico", dir.join("favicon.ico"))?;
+    }
+
+    let articles = public_articles(state)?;
+    let settings = state.site.settings();
+    write(&dir.join("feed.xml"), atom_feed(&articles, &settings).as_bytes())?;
+    write(&dir.join("sitemap.xml"), sitemap(&pages, &settings).as_bytes())?;
+
+    Ok(pages.len())
+}
+
+/// newest first, with paragraphs
+fn public_articles(state: &SharedState) -> Result<Vec<Article>, StaticExportError> {
+    let mut articles = Vec::new();
+    for article in Article::find_all_ordered(ArticleOrder::Created, &state.db)? {
+        if article.is_public() {
+            articles.push(Article::find(article.id.unwrap_or_default(), &state.db)?);
+        }
+    }
+    Ok(articles)
+}
+
+fn render_pages(state: &SharedState) -> Result<Vec<Page>, StaticExportError> {
+    let auth = Auth::default();
+    let articles = public_articles(state)?;
+    let mut pages = Vec::new();
+
+    let page = |path: &str, template: &str, ctx: minijinja::value::Value| -> Result<Page, StaticExportError> {
+        Ok(Page {
+            path: path.to_string(),
+            html: state.templates.get_template(template)?.render(ctx)?,
+            updated_at: None,
+        })
+    };
+
+    // the contact form needs the server, the page explains that instead
+    for mut site_page in SitePage::find_all(&state.db)? {
+        if !site_page.published {
+            continue;
+        }
+        site_page.load_paragraphs(&state.db)?;
+        let mut rendered = page(
+            &site_page.url(),
+            site_page.template.file(),
+            context! {
+                auth => auth,
+                page => site_page,
+                articles => articles,
+                static_export => true,
+            },
+        )?;
+        rendered.updated_at = Some(site_page.updated_at);
+        pages.push(rendered);
+    }
+
+    for series in Series::find_all(&state.db)? {
+        let members = Article::find_by_series(series.id.unwrap_or_default(), &state.db)?
+            .into_iter()
+            .filter(|a| a.is_public())
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            continue;
+        }
+        let mut series_page = page(
+            &format!("/series/{}", series.slug),
+            "pages/series.html",
+            context! { auth => auth, series => series, articles => members },
+        )?;
+        series_page.updated_at = members.iter().map(|a| a.updated_at).max();
+        pages.push(series_page);
+    }
+
+    for article in articles.iter() {
+        let id = article.id.unwrap_or_default();
+        let series = Series::nav(id, true, &state.db)?;
+        let mut related = related::find(id, &state.db)?;
+        related.retain(|a| a.is_public());
+        related.truncate(RELATED_SHOWN);
+
+        let mut article_page = page(
+            &article.url(),
+            "pages/article.html",
+            context! {
+                auth => auth,
+                article => article,
+                preview => false,
+                series => series,
+                related => related,
+                static_export => true,
+            },
+        )?;
+        article_page.updated_at = Some(article.updated_at);
+        pages.push(article_page);
+    }
+
+    pages.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(pages)
+}
+
+/// `/` -> `index.html`, `/article/foo` -> `article/foo/index.html`
+fn page_file(path: &str) -> String {
+    match path.trim_matches('/') {
+        "" => "index.html".to_string(),
+        path => format!("{}/index.html", path),
+    }
+}
+
+/// `../` for every directory between the file and the root
+fn prefix(file: &str) -> String {
+    "../".repeat(file.matches('/').count())
+}
+
+/// maps a site path to the exported file, relative to `prefix`.
+/// Asset paths are recorded so they get copied.
+fn relative_url(url: &str, prefix: &str, assets: &mut BTreeSet<String>) -> Option<String> {
+    if !url.starts_with('/') || url.starts_with("//") {
+        return None;
+    }
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, rest) = url.split_at(end);
+    // queries don't exist on a static host
+    let fragment = rest.find('#').map(|i| &rest[i..]).unwrap_or_default();
+
+    if path.starts_with("/api/") {
+        return None;
+    }
+    if path.starts_with("/static/") {
+        assets.insert(path.to_string());
+        return Some(format!("{}{}{}", prefix, path.trim_start_matches('/'), fragment));
+    }
+    let last = path.rsplit('/').next().unwrap_or_default();
+    let file = match last.contains('.') {
+        true => path.trim_start_matches('/').to_string(),
+        false => page_file(path),
+    };
+    Some(format!("{}{}{}", prefix, file, fragment))
+}
+
+/// rewrites root relative urls in url attributes, `srcset` and wasm demos.
+/// Templates quote attributes with `"`, the markdown renderer with `'`.
+fn relative_links(html: &str, prefix: &str, assets: &mut BTreeSet<String>) -> String {
+    // templates escape slashes, html doesn't need that
+    let html = html.replace("&#x2f;", "/");
+    let html = relative_wasm_urls(&html, prefix, assets);
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+
+    while let Some((start, name, quote)) = next_attribute(rest) {
+        let value_start = start + name.len() + 2;
+        let Some(len) = rest[value_start..].find(quote) else {
+            break;
+        };
+        let value = &rest[value_start..value_start + len];
+        out.push_str(&rest[..value_start]);
+
+        if name == "srcset" {
+            let candidates = value
+                .split(',')
+                .map(|candidate| {
+                    let candidate = candidate.trim();
+                    let (url, size) = candidate.split_once(' ').unwrap_or((candidate, ""));
+                    let url = relative_url(url, prefix, assets).unwrap_or(url.to_string());
+                    format!("{} {}", url, size).trim_end().to_string()
+                })
+                .collect::<Vec<_>>();
+            out.push_str(&candidates.join(", "));
+        } else {
+            out.push_str(&relative_url(value, prefix, assets).unwrap_or(value.to_string()));
+        }
+        rest = &rest[value_start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// the next url attribute, as `(offset, name, quote)`
+fn next_attribute(html: &str) -> Option<(usize, &'static str, char)> {
+    URL_ATTRIBUTES
+        .iter()
+        .chain(["srcset"].iter())
+        .flat_map(|name| ['"', '\''].map(|quote| (*name, quote)))
+        .filter_map(|(name, quote)| {
+            html.match_indices(&format!("{}={}", name, quote))
+                .map(|(i, _)| i)
+                .find(|i| {
+                    html[..*i]
+                        .chars()
+                        .next_back()
+                        .is_some_and(|c| c.is_ascii_whitespace())
+                })
+                .map(|i| (i, name, quote))
+        })
+        .min_by_key(|(i, _, _)| *i)
+}
+
+/// wasm demos pass their files to `run_wasm('<wasm>', '<script>', ..)`,
+/// see [crate::render::WasmDemo::to_html]
+fn relative_wasm_urls(html: &str, prefix: &str, assets: &mut BTreeSet<String>) -> String {
+    const CALL: &str = "run_wasm(";
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(CALL) {
+        out.push_str(&rest[..start + CALL.len()]);
+        rest = &rest[start + CALL.len()..];
+
+        for separator in ["'", ", '"] {
+            let Some(value) = rest.strip_prefix(separator) else {
+                break;
+            };
+            let Some(len) = value.find('\'') else {
+                break;
+            };
+            let url = &value[..len];
+            out.push_str(separator);
+            out.push_str(&relative_url(url, prefix, assets).unwrap_or(url.to_string()));
+            out.push('\'');
+            rest = &value[len + 1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn collect_css_urls(css: &str, assets: &mut BTreeSet<String>) {
+    for part in css.split("url(").skip(1) {
+        let url = part
+            .split(')')
+            .next()
+            .unwrap_or_default()
+            .trim_matches(['"', '\'', ' ']);
+        if url.starts_with("/static/") {
+            assets.insert(url.split(['?', '#']).next().unwrap_or_default().to_string());
+        }
+    }
+}
+
+fn relative_css_urls(css: &str, prefix: &str) -> String {
+    ["url(", "url(\"", "url('"]
+        .iter()
+        .rev()
+        .fold(css.to_string(), |css, open| {
+            css.replace(&format!("{}/static/", open), &format!("{}{}static/", open, prefix))
+        })
+}
+
+/// asset paths come from rendered html, only plain relative paths are copied
+fn is_safe(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+fn create_parent(file: &Path) -> Result<(), std::io::Error> {
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+fn write(file: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    create_parent(file)?;
+    std::fs::write(file, content)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    let time = chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap_or_default();
+    chrono::DateTime::<chrono::Utc>::from_utc(time, chrono::Utc)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// atom feed of the public articles, urls are absolute using the base url of the settings
+fn atom_feed(articles: &[Article], settings: &Settings) -> String {
+    let updated = articles.iter().map(|a| a.updated_at).max().unwrap_or_default();
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         \t<title>{}</title>\n\
+         \t<id>{}</id>\n\
+         \t<link href=\"{}\" />\n\
+         \t<link rel=\"self\" href=\"{}\" />\n\
+         \t<updated>{}</updated>\n\
+         \t<author><name>{}</name></author>\n",
+        xml_escape(&settings.site_title),
+        xml_escape(&settings.link("/")),
+        xml_escape(&settings.link("/")),
+        xml_escape(&settings.link("/feed.xml")),
+        rfc3339(updated),
+        xml_escape(&settings.author),
+    );
+    for article in articles {
+        let url = xml_escape(&settings.link(&article.url()));
+        feed.push_str(&format!(
+            "\t<entry>\n\
+             \t\t<title>{}</title>\n\
+             \t\t<id>{}</id>\n\
+             \t\t<link href=\"{}\" />\n\
+             \t\t<published>{}</published>\n\
+             \t\t<updated>{}</updated>\n\
+             \t\t<summary>{}</summary>\n\
+             \t</entry>\n",
+            xml_escape(&article.title),
+            url,
+            url,
+            rfc3339(article.publish_at.unwrap_or(article.created_at)),
+            rfc3339(article.updated_at),
+            xml_escape(&article.teaser),
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn sitemap(pages: &[Page], settings: &Settings) -> String {
+    let mut sitemap = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for page in pages {
+        sitemap.push_str(&format!(
+            "\t<url><loc>{}</loc>",
+            xml_escape(&settings.link(&page.path))
+        ));
+        if let Some(updated_at) = page.updated_at {
+            sitemap.push_str(&format!("<lastmod>{}</lastmod>", rfc3339(updated_at)));
+        }
+        sitemap.push_str("</url>\n");
+    }
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::pages::Menu;
+    use crate::store::paragraphs::{Paragraph, ParagraphType};
+    use crate::store::related::Related;
+    use crate::store::SchemaUp;
+    use crate::GlobalContext;
+    use std::sync::RwLock;
+
+    fn state() -> SharedState {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&db).unwrap();
+        Paragraph::up(&db).unwrap();
+        Series::up(&db).unwrap();
+        Related::up(&db).unwrap();
+        SitePage::up(&db).unwrap();
+
+        let menu = Menu::default();
+        let site = GlobalContext::default();
+        SharedState {
+            db,
+            db_path: String::new(),
+            templates: crate::load_templates(menu.clone(), site.clone()),
+            menu,
+            site,
+            sessions: RwLock::new(Vec::new()),
+            rate_limit: crate::spam::RateLimiter::default(),
+            used_tokens: crate::spam::UsedTokens::default(),
+        }
+    }
+
+    fn paragraph(article_id: i64, paragraph_type: ParagraphType, content: &str) -> Paragraph {
+        Paragraph {
+            id: None,
+            article_id: Some(article_id),
+            page_id: None,
+            title: String::new(),
+            description: String::new(),
+            paragraph_type,
+            position: 0,
+            content: content.to_string(),
+            rendered: None,
+        }
+    }
+
+    #[test]
+    fn exports_relative_links() {
+        let state = state();
+        let mut article = Article::new("Fixture".to_string());
+        article.alias = "fixture".to_string();
+        article.published = true;
+        article.insert(&state.db).unwrap();
+        let id = article.id.unwrap();
+        paragraph(
+            id,
+            ParagraphType::Markdown,
+            "See [the other one](/article/other#intro) ![banner](/static/images/mcu.jpg)",
+        )
+        .insert(&state.db)
+        .unwrap();
+        paragraph(
+            id,
+            ParagraphType::Wasm,
+            r#"{"wasm": "/static/media/1/demo_bg.wasm", "script": "/static/media/1/demo.js"}"#,
+        )
+        .insert(&state.db)
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("export-{:x}", rand::random::<u64>()));
+        assert_eq!(export(&dir, &state).unwrap(), 1);
+
+        let html = std::fs::read_to_string(dir.join("article/fixture/index.html")).unwrap();
+        assert!(html.contains("href='../../article/other/index.html#intro'"));
+        assert!(html.contains("src='../../static/images/mcu.jpg'"));
+        assert!(html.contains(
+            "run_wasm('../../static/media/1/demo_bg.wasm', '../../static/media/1/demo.js', "
+        ));
+        assert!(!html.contains("=\"/static/"));
+        assert!(!html.contains("='/static/"));
+        assert!(dir.join("static/images/mcu.jpg").is_file());
+        assert!(dir.join("static/main.js").is_file());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
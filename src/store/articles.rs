@@ -1,5 +1,7 @@
 use super::paragraphs::Paragraph;
-use super::{Crud, SchemaDown, SchemaUp};
+use crate::render::TocEntry;
+use super::{add_column, Crud, SchemaDown, SchemaUp};
+use super::redirects::ArticleAlias;
 use axum::async_trait;
 use axum::extract::{FromRequest, FromRequestParts};
 use axum::http::Request;
@@ -10,6 +12,61 @@ use rusqlite::{
 };
 use serde::{Deserialize, Serialize};
 
+const ARTICLE_COLUMNS: &str = "id, title, teaser, cover, created_at, updated_at, published, alias, tags,
+    word_count, reading_time, code_blocks, images, last_edited_paragraph, publish_at, comments_open";
+
+/// derived from the paragraphs, recomputed whenever the article or one of its paragraphs is saved
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ArticleMetrics {
+    pub word_count: i64,
+    /// minutes
+    pub reading_time: i64,
+    pub code_blocks: i64,
+    pub images: i64,
+    pub last_edited_paragraph: Option<i64>,
+}
+
+impl ArticleMetrics {
+    const WORDS_PER_MINUTE: i64 = 200;
+
+    pub fn from_paragraphs(paragraphs: &[Paragraph]) -> Self {
+        let mut metrics = ArticleMetrics::default();
+        for para in paragraphs {
+            let (words, code_blocks, images) = crate::render::text_metrics(para);
+            metrics.word_count += words;
+            metrics.code_blocks += code_blocks;
+            metrics.images += images;
+        }
+        metrics.reading_time =
+            (metrics.word_count + Self::WORDS_PER_MINUTE - 1) / Self::WORDS_PER_MINUTE;
+        metrics
+    }
+}
+
+/// sort orders offered by the list endpoints
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArticleOrder {
+    #[default]
+    Created,
+    Updated,
+    Title,
+    WordCount,
+    ReadingTime,
+}
+
+impl ArticleOrder {
+    fn sql(&self) -> &'static str {
+        match self {
+            ArticleOrder::Created => "created_at DESC",
+            ArticleOrder::Updated => "updated_at DESC",
+            ArticleOrder::Title => "title ASC",
+            ArticleOrder::WordCount => "word_count DESC",
+            ArticleOrder::ReadingTime => "reading_time DESC",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Article {
     pub id: Option<i64>,
@@ -19,9 +76,15 @@ pub struct Article {
     pub created_at: i64,
     pub updated_at: i64,
     pub published: bool,
+    /// scheduled publication, cleared by the scheduler once the article went live
+    pub publish_at: Option<i64>,
+    /// readers may post comments
+    pub comments_open: bool,
     pub tags : String,
     pub alias: String,
+    pub metrics: ArticleMetrics,
     pub paragraphs: Option<Vec<Paragraph>>,
+    pub toc: Option<Vec<TocEntry>>,
 }
 
 impl Article {
@@ -35,70 +98,370 @@ impl Article {
             created_at: now,
             updated_at: now,
             published: false,
+            publish_at: None,
+            comments_open: true,
+            metrics: ArticleMetrics::default(),
             paragraphs: None,
+            toc: None,
             tags: String::new(),
             alias: "".to_string(),
         }
     }
 
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Article {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            teaser: row.get(2)?,
+            cover: row.get(3)?,
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+            published: row.get(6)?,
+            alias: row.get(7)?,
+            tags: row.get(8)?,
+            metrics: ArticleMetrics {
+                word_count: row.get(9)?,
+                reading_time: row.get(10)?,
+                code_blocks: row.get(11)?,
+                images: row.get(12)?,
+                last_edited_paragraph: row.get(13)?,
+            },
+            publish_at: row.get(14)?,
+            comments_open: row.get(15)?,
+            paragraphs: None,
+            toc: None,
+        })
+    }
+
+    /// published, or scheduled and due. The scheduler only catches up
+    /// with the stored state, visibility switches at the exact time.
+    pub fn is_public(&self) -> bool {
+        match self.publish_at {
+            Some(at) => at <= chrono::offset::Local::now().timestamp(),
+            None => self.published,
+        }
+    }
+
+    /// attaches the paragraphs and builds the table of contents from their headings
+    pub fn set_paragraphs(&mut self, mut paragraphs: Vec<Paragraph>) {
+        self.toc = Some(Paragraph::anchor_headings(&mut paragraphs));
+        self.paragraphs = Some(paragraphs);
+    }
+
+    /// renders the paragraphs again with render errors shown, for admins
+    pub fn render_for_admin(&mut self) {
+        if let Some(mut paragraphs) = self.paragraphs.take() {
+            paragraphs.iter_mut().for_each(|para| para.render(true));
+            self.set_paragraphs(paragraphs);
+        }
+    }
+
+    /// recomputes the stored metrics from the current paragraphs.
+    /// `edited` is the paragraph that was just changed, if any.
+    pub fn refresh_metrics(
+        id: i64,
+        edited: Option<i64>,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let paragraphs = Paragraph::find_by_article_id(id, con)?;
+        let mut metrics = ArticleMetrics::from_paragraphs(&paragraphs);
+
+        metrics.last_edited_paragraph = match edited {
+            Some(edited) => Some(edited),
+            None => {
+                let mut stmt =
+                    con.prepare("SELECT last_edited_paragraph FROM article WHERE id = ?")?;
+                stmt.query_row([&id], |row| row.get(0))?
+            }
+        };
+
+        let mut stmt = con.prepare(
+            "UPDATE article SET word_count = ?, reading_time = ?, code_blocks = ?, images = ?, last_edited_paragraph = ? WHERE id = ?",
+        )?;
+        stmt.execute(params![
+            &metrics.word_count,
+            &metrics.reading_time,
+            &metrics.code_blocks,
+            &metrics.images,
+            &metrics.last_edited_paragraph,
+            &id
+        ])?;
+        Ok(())
+    }
+
+    /// recomputes the metrics of every article, for databases created before they were stored
+    pub fn refresh_all_metrics(con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let articles = Article::find_all_ordered(Default::default(), &tx)?;
+        for article in articles.iter() {
+            Article::refresh_metrics(article.id.ok_or(rusqlite::Error::InvalidQuery)?, None, &tx)?;
+        }
+        tx.commit()?;
+        Ok(articles.len())
+    }
+
+    /// aliases end up in urls, a numeric one would be taken for an id
+    pub fn alias_slug(text: &str) -> String {
+        let slug = crate::render::slugify(text);
+        match slug.chars().all(|c| c.is_ascii_digit()) {
+            true => format!("article-{}", slug),
+            false => slug,
+        }
+    }
+
+    /// true if another article uses `alias`, now or as one of its old aliases
+    pub fn alias_taken(
+        alias: &str,
+        id: Option<i64>,
+        con: &rusqlite::Connection,
+    ) -> Result<bool, rusqlite::Error> {
+        let count: i64 = con.query_row(
+            "SELECT (SELECT COUNT(*) FROM article WHERE alias = ?1 AND id IS NOT ?2)
+                  + (SELECT COUNT(*) FROM article_alias WHERE alias = ?1 AND article_id IS NOT ?2)",
+            params![alias, id],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// slug of `title`, suffixed with `-2`, `-3` .. until no other article uses it
+    pub fn unique_alias(
+        title: &str,
+        id: Option<i64>,
+        con: &rusqlite::Connection,
+    ) -> Result<String, rusqlite::Error> {
+        let base = Article::alias_slug(title);
+        let mut alias = base.clone();
+        let mut n = 1;
+        while Article::alias_taken(&alias, id, con)? {
+            n += 1;
+            alias = format!("{}-{}", base, n);
+        }
+        Ok(alias)
+    }
+
+    /// switches to a new alias, the old one is kept to redirect existing links
+    pub fn change_alias(
+        &mut self,
+        alias: String,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        if alias == self.alias {
+            return Ok(());
+        }
+        if let (Some(id), false) = (self.id, self.alias.is_empty()) {
+            ArticleAlias::record(id, &self.alias, con)?;
+        }
+        ArticleAlias::release(&alias, con)?;
+        self.alias = alias;
+        Ok(())
+    }
+
+    /// path the article is canonically served under
+    pub fn url(&self) -> String {
+        match self.alias.is_empty() {
+            true => format!("/article/{}", self.id.unwrap_or_default()),
+            false => format!("/article/{}", self.alias),
+        }
+    }
+
     pub fn find_by_alias(alias: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT id, title, teaser, cover, created_at, updated_at, published, alias, tags FROM article WHERE alias = ?")?;
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article WHERE alias = ? AND deleted_at IS NULL",
+            ARTICLE_COLUMNS
+        ))?;
         let mut rows = stmt.query([&alias])?;
         if let Some(row) = rows.next()? {
-            let mut article = Article {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                teaser: row.get(2)?,
-                cover: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                published: row.get(6)?,
-                alias: row.get(7)?,
-                tags: row.get(8)?,
-                paragraphs: None,
-            };
-            article.paragraphs = Some(Paragraph::find_by_article_id(article.id.unwrap(), con)?);
+            let mut article = Article::from_row(row)?;
+            article.set_paragraphs(Paragraph::find_by_article_id(article.id.unwrap(), con)?);
             return Ok(article);
         }
         Err(rusqlite::Error::QueryReturnedNoRows)
     }
 
+    /// articles waiting for their publication date, next one first
+    pub fn find_scheduled(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article WHERE publish_at IS NOT NULL AND deleted_at IS NULL ORDER BY publish_at ASC",
+            ARTICLE_COLUMNS
+        ))?;
+        let articles = stmt
+            .query_map([], Article::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(articles)
+    }
+
+    /// publishes every article whose date has come and returns their ids.
+    /// The publication date becomes the creation date, so the article shows up as new.
+    pub fn publish_due(now: i64, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let ids = {
+            let mut stmt =
+                tx.prepare("SELECT id FROM article WHERE publish_at IS NOT NULL AND publish_at <= ? AND deleted_at IS NULL")?;
+            let ids = stmt
+                .query_map([&now], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            ids
+        };
+        tx.execute(
+            "UPDATE article SET published = 1, created_at = publish_at, updated_at = publish_at, publish_at = NULL
+             WHERE publish_at IS NOT NULL AND publish_at <= ? AND deleted_at IS NULL",
+            [&now],
+        )?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// articles in the order of `ids`, without paragraphs. Unknown and trashed ids are skipped.
+    pub fn find_many(ids: &[i64], con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article WHERE id = ? AND deleted_at IS NULL",
+            ARTICLE_COLUMNS
+        ))?;
+        let mut articles = Vec::with_capacity(ids.len());
+        for id in ids {
+            let mut rows = stmt.query([id])?;
+            if let Some(row) = rows.next()? {
+                articles.push(Article::from_row(row)?);
+            }
+        }
+        Ok(articles)
+    }
+
+    /// parts of a series in order, without paragraphs
+    pub fn find_by_series(
+        series_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article JOIN series_article ON series_article.article_id = article.id
+             WHERE series_article.series_id = ? AND deleted_at IS NULL
+             ORDER BY series_article.position",
+            ARTICLE_COLUMNS
+        ))?;
+        let articles = stmt
+            .query_map([&series_id], Article::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(articles)
+    }
+
+    pub fn find_all_ordered(
+        order: ArticleOrder,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut articles = Vec::new();
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article WHERE deleted_at IS NULL ORDER BY {}",
+            ARTICLE_COLUMNS,
+            order.sql()
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            articles.push(Article::from_row(row)?)
+        }
+
+        Ok(articles)
+    }
+
     pub fn find_articles_paginated(
         con: &rusqlite::Connection,
         tag: &str,
+        order: ArticleOrder,
         offset: i64,
         limit: i64,
     ) -> Result<Vec<Self>, rusqlite::Error> {
         let mut articles = Vec::new();
-        let mut stmt = con.prepare(
-            "SELECT id, title, teaser, cover, created_at, updated_at, published, alias, tags
+        let mut stmt = con.prepare(&format!(
+            "SELECT {}
              FROM article
-             WHERE tags LIKE ?
-             ORDER BY created_at DESC LIMIT ?, ?",
-        )?;
+             WHERE tags LIKE ? AND deleted_at IS NULL
+             ORDER BY {} LIMIT ?, ?",
+            ARTICLE_COLUMNS,
+            order.sql()
+        ))?;
 
         let like_tag = format!("%{}%", tag);
 
         let mut rows = stmt.query(params![like_tag, offset, limit])?;
         while let Some(row) = rows.next()? {
-            articles.push(Article {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                teaser: row.get(2)?,
-                cover: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                published: row.get(6)?,
-                alias: row.get(7)?,
-                tags: row.get(8)?,
-                paragraphs: None,
-            })
+            articles.push(Article::from_row(row)?)
         }
 
         Ok(articles)
     }
 }
 
+// --------------------------------------------------------
+// trash
+// --------------------------------------------------------
+impl Article {
+    /// brings back a trashed article together with the paragraphs trashed along with it
+    pub fn restore(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let restored = tx.execute(
+            "UPDATE article SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if restored == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.execute(
+            "UPDATE paragraph SET deleted_at = NULL, deleted_with_article = 0 WHERE article_id = ? AND deleted_with_article = 1",
+            [&id],
+        )?;
+        tx.execute(
+            "UPDATE media SET deleted_at = NULL, deleted_with_article = 0 WHERE article_id = ? AND deleted_with_article = 1",
+            [&id],
+        )?;
+        tx.commit()
+    }
+
+    /// removes a trashed article, its paragraphs, preview tokens, old aliases
+    /// and series membership for good.
+    /// Media files are left to the caller, see `trash::purge_article`.
+    pub fn purge(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM article WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if deleted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.execute("DELETE FROM paragraph WHERE article_id = ?", [&id])?;
+        tx.execute("DELETE FROM preview_token WHERE article_id = ?", [&id])?;
+        tx.execute("DELETE FROM article_alias WHERE article_id = ?", [&id])?;
+        tx.execute("DELETE FROM series_article WHERE article_id = ?", [&id])?;
+        tx.execute(
+            "DELETE FROM related_article WHERE article_id = ?1 OR related_id = ?1",
+            [&id],
+        )?;
+        tx.execute("DELETE FROM comment WHERE article_id = ?", [&id])?;
+        tx.commit()
+    }
+
+    pub fn set_comments_open(
+        id: i64,
+        open: bool,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "UPDATE article SET comments_open = ? WHERE id = ?",
+            params![open, id],
+        )?;
+        Ok(())
+    }
+
+    /// ids of articles trashed before `cutoff`
+    pub fn find_expired(cutoff: i64, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let mut stmt = con.prepare("SELECT id FROM article WHERE deleted_at <= ?")?;
+        let ids = stmt
+            .query_map([&cutoff], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+}
+
 impl SchemaUp for Article {
     fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         con.execute(
@@ -107,7 +470,7 @@ impl SchemaUp for Article {
             title TEXT,
             teaser TEXT,
             cover TEXT,
-            tags TEXT,
+            tags TEXT DEFAULT '',
             created_at INTEGER,
             updated_at INTEGER,
             published BOOLEAN,
@@ -115,37 +478,27 @@ impl SchemaUp for Article {
             );",
             (),
         )?;
+        add_column(con, "article", "word_count", "INTEGER DEFAULT 0")?;
+        add_column(con, "article", "reading_time", "INTEGER DEFAULT 0")?;
+        add_column(con, "article", "code_blocks", "INTEGER DEFAULT 0")?;
+        add_column(con, "article", "images", "INTEGER DEFAULT 0")?;
+        add_column(con, "article", "last_edited_paragraph", "INTEGER")?;
+        add_column(con, "article", "publish_at", "INTEGER")?;
+        add_column(con, "article", "deleted_at", "INTEGER")?;
+        add_column(con, "article", "comments_open", "BOOLEAN DEFAULT 1")?;
+        // tags were not saved at first, `tags LIKE ?` never matches NULL
+        con.execute("UPDATE article SET tags = '' WHERE tags IS NULL", ())?;
         Ok(())
     }
 }
 
 impl Crud for Article {
     fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
-        let mut articles = Vec::new();
-        let mut stmt = con.prepare(
-            "SELECT id, title, teaser, cover, created_at, updated_at, published, alias, tags FROM article ORDER BY created_at DESC",
-        )?;
-        let mut rows = stmt.query([])?;
-        while let Some(row) = rows.next()? {
-            articles.push(Article {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                teaser: row.get(2)?,
-                cover: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                published: row.get(6)?,
-                alias: row.get(7)?,
-                tags: row.get(8)?,
-                paragraphs: None,
-            })
-        }
-
-        Ok(articles)
+        Article::find_all_ordered(ArticleOrder::Created, con)
     }
     fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "INSERT INTO article (title, teaser, cover, created_at, updated_at, published, alias) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO article (title, teaser, cover, created_at, updated_at, published, alias, tags, publish_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )?;
 
         stmt.execute(params![
@@ -155,7 +508,9 @@ impl Crud for Article {
             &self.created_at,
             &self.updated_at,
             &self.published,
-            &self.alias
+            &self.alias,
+            &self.tags,
+            &self.publish_at
         ])?;
 
         self.id = Some(con.last_insert_rowid());
@@ -163,23 +518,19 @@ impl Crud for Article {
         Ok(())
     }
     fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
-        let mut stmt = con.prepare(
-            "SELECT id, title, teaser, cover, created_at, updated_at, published, alias, tags FROM article WHERE id = ?"
-        )?;
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM article WHERE id = ? AND deleted_at IS NULL",
+            ARTICLE_COLUMNS
+        ))?;
         let mut rows = stmt.query([&id])?;
         match rows.next()? {
-            Some(row) => Ok(Article {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                teaser: row.get(2)?,
-                cover: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                published: row.get(6)?,
-                alias: row.get(7)?,
-                tags: row.get(8)?,
-                paragraphs: Paragraph::find_by_article_id(id, con).ok(),
-            }),
+            Some(row) => {
+                let mut article = Article::from_row(row)?;
+                if let Ok(paragraphs) = Paragraph::find_by_article_id(id, con) {
+                    article.set_paragraphs(paragraphs);
+                }
+                Ok(article)
+            }
 
             None => Err(rusqlite::Error::QueryReturnedNoRows),
         }
@@ -187,7 +538,7 @@ impl Crud for Article {
 
     fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "UPDATE article SET title = ?, teaser = ?, cover = ?, created_at = ?, updated_at = ?, published = ?, alias = ? WHERE id = ?",
+            "UPDATE article SET title = ?, teaser = ?, cover = ?, created_at = ?, updated_at = ?, published = ?, alias = ?, tags = ?, publish_at = ? WHERE id = ?",
         )?;
 
         stmt.execute(params![
@@ -198,15 +549,35 @@ impl Crud for Article {
             &self.updated_at,
             &self.published,
             &self.alias,
+            &self.tags,
+            &self.publish_at,
             &self.id
         ])?;
 
         Ok(())
     }
 
+    /// moves the article, its paragraphs and the files only it uses to the trash, see [Article::purge]
     fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
-        let mut stmt = con.prepare("DELETE FROM article WHERE id = ?")?;
-        stmt.execute([&id])?;
-        Ok(())
+        let now = chrono::offset::Local::now().timestamp();
+        let tx = con.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE article SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+            params![now, id],
+        )?;
+        // flagged to tell them apart from paragraphs trashed on their own
+        tx.execute(
+            "UPDATE paragraph SET deleted_at = ?, deleted_with_article = 1 WHERE article_id = ? AND deleted_at IS NULL",
+            params![now, id],
+        )?;
+        // files uploaded for this article, unless a cover or paragraph elsewhere points to them
+        tx.execute(
+            "UPDATE media SET deleted_at = ?1, deleted_with_article = 1
+             WHERE article_id = ?2 AND deleted_at IS NULL
+             AND NOT EXISTS (SELECT 1 FROM article a WHERE a.id != ?2 AND a.cover = media.path)
+             AND NOT EXISTS (SELECT 1 FROM paragraph p WHERE p.article_id IS NOT ?2 AND instr(p.content, media.path) > 0)",
+            params![now, id],
+        )?;
+        tx.commit()
     }
 }
@@ -0,0 +1,495 @@
+use super::{add_column, Crud, SchemaUp};
+use rusqlite::params;
+use rusqlite::types::{FromSql, ToSql, ToSqlOutput};
+use serde::{Deserialize, Serialize};
+
+const COMMENT_COLUMNS: &str =
+    "id, article_id, parent_id, author, email, body, status, ip, notify, created_at, spam_score";
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Spam,
+}
+
+impl CommentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommentStatus::Pending => "pending",
+            CommentStatus::Approved => "approved",
+            CommentStatus::Rejected => "rejected",
+            CommentStatus::Spam => "spam",
+        }
+    }
+}
+
+impl FromSql for CommentStatus {
+    fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "approved" => Ok(CommentStatus::Approved),
+            "rejected" => Ok(CommentStatus::Rejected),
+            "spam" => Ok(CommentStatus::Spam),
+            _ => Ok(CommentStatus::Pending),
+        }
+    }
+}
+
+impl ToSql for CommentStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Comment {
+    pub id: Option<i64>,
+    pub article_id: i64,
+    /// the comment this one replies to
+    pub parent_id: Option<i64>,
+    pub author: String,
+    /// optional, only shown to admins
+    pub email: String,
+    pub body: String,
+    pub status: CommentStatus,
+    pub ip: String,
+    /// the author wants to hear about replies
+    pub notify: bool,
+    pub created_at: i64,
+    /// see [crate::spam::score]
+    pub spam_score: i64,
+}
+
+/// a comment with its visible replies
+#[derive(Debug, Serialize)]
+pub struct CommentThread {
+    pub comment: Comment,
+    pub replies: Vec<CommentThread>,
+}
+
+impl Comment {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Comment {
+            id: row.get(0)?,
+            article_id: row.get(1)?,
+            parent_id: row.get(2)?,
+            author: row.get(3)?,
+            email: row.get(4)?,
+            body: row.get(5)?,
+            status: row.get(6)?,
+            ip: row.get(7)?,
+            notify: row.get(8)?,
+            created_at: row.get(9)?,
+            spam_score: row.get(10)?,
+        })
+    }
+
+    /// comments of an article with the given status, oldest first
+    pub fn find_by_article(
+        article_id: i64,
+        status: CommentStatus,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM comment WHERE article_id = ? AND status = ? ORDER BY created_at ASC, id ASC",
+            COMMENT_COLUMNS
+        ))?;
+        let comments = stmt
+            .query_map(params![article_id, status], Comment::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(comments)
+    }
+
+    /// the moderation queue, newest first
+    pub fn find_by_status(
+        status: CommentStatus,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM comment WHERE status = ? ORDER BY created_at DESC, id DESC",
+            COMMENT_COLUMNS
+        ))?;
+        let comments = stmt
+            .query_map([status], Comment::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(comments)
+    }
+
+    pub fn count_by_status(
+        status: CommentStatus,
+        con: &rusqlite::Connection,
+    ) -> Result<i64, rusqlite::Error> {
+        con.query_row(
+            "SELECT COUNT(*) FROM comment WHERE status = ?",
+            [status],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn set_status(
+        id: i64,
+        status: CommentStatus,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let updated = con.execute(
+            "UPDATE comment SET status = ? WHERE id = ?",
+            params![status, id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// marks all pending comments of a banned email or ip as spam
+    pub fn flag_banned(ban: &Ban, con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+        con.execute(
+            &format!(
+                "UPDATE comment SET status = ? WHERE status = ? AND {} = ?",
+                ban.kind.column()
+            ),
+            params![CommentStatus::Spam, CommentStatus::Pending, ban.value],
+        )
+    }
+
+    /// nests comments below their parents. Replies to comments
+    /// missing from `comments`, e.g. unapproved ones, are dropped.
+    pub fn thread(comments: Vec<Comment>) -> Vec<CommentThread> {
+        fn replies(parent_id: Option<i64>, comments: &[Comment]) -> Vec<CommentThread> {
+            comments
+                .iter()
+                .filter(|c| c.parent_id == parent_id)
+                .map(|c| CommentThread {
+                    comment: c.clone(),
+                    replies: replies(c.id, comments),
+                })
+                .collect()
+        }
+        replies(None, &comments)
+    }
+}
+
+impl SchemaUp for Comment {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS comment (
+                id INTEGER PRIMARY KEY,
+                article_id INTEGER,
+                parent_id INTEGER,
+                author TEXT,
+                email TEXT DEFAULT '',
+                body TEXT,
+                status TEXT DEFAULT 'pending',
+                ip TEXT DEFAULT '',
+                notify BOOLEAN DEFAULT 0,
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        add_column(con, "comment", "spam_score", "INTEGER DEFAULT 0")?;
+        con.execute(
+            "CREATE INDEX IF NOT EXISTS comment_article ON comment (article_id);",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+impl Crud for Comment {
+    fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM comment WHERE id = ?",
+            COMMENT_COLUMNS
+        ))?;
+        stmt.query_row([&id], Comment::from_row)
+    }
+
+    fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM comment ORDER BY created_at DESC",
+            COMMENT_COLUMNS
+        ))?;
+        let comments = stmt
+            .query_map([], Comment::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(comments)
+    }
+
+    fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO comment (article_id, parent_id, author, email, body, status, ip, notify, created_at, spam_score) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.article_id,
+            &self.parent_id,
+            &self.author,
+            &self.email,
+            &self.body,
+            &self.status,
+            &self.ip,
+            &self.notify,
+            &self.created_at,
+            &self.spam_score,
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "UPDATE comment SET author = ?, email = ?, body = ?, status = ?, notify = ? WHERE id = ?",
+        )?;
+        stmt.execute(params![
+            &self.author,
+            &self.email,
+            &self.body,
+            &self.status,
+            &self.notify,
+            &self.id.ok_or(rusqlite::Error::InvalidQuery)?,
+        ])?;
+        Ok(())
+    }
+
+    /// deletes the comment and all replies below it
+    fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "WITH RECURSIVE subtree(id) AS (
+                SELECT ?
+                UNION ALL
+                SELECT comment.id FROM comment JOIN subtree ON comment.parent_id = subtree.id
+            )
+            DELETE FROM comment WHERE id IN subtree",
+            [&id],
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BanKind {
+    Email,
+    Ip,
+}
+
+impl BanKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BanKind::Email => "email",
+            BanKind::Ip => "ip",
+        }
+    }
+
+    /// the comment column compared against a ban, normalized like [Ban::new]
+    fn column(&self) -> &'static str {
+        match self {
+            BanKind::Email => "lower(trim(email))",
+            BanKind::Ip => "trim(ip)",
+        }
+    }
+}
+
+impl FromSql for BanKind {
+    fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "ip" => Ok(BanKind::Ip),
+            _ => Ok(BanKind::Email),
+        }
+    }
+}
+
+impl ToSql for BanKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+/// a blocked email address or ip, applies to comments and the contact form
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Ban {
+    pub id: Option<i64>,
+    pub kind: BanKind,
+    pub value: String,
+    pub created_at: i64,
+}
+
+impl Ban {
+    pub fn new(kind: BanKind, value: String) -> Self {
+        Ban {
+            id: None,
+            kind,
+            value: match kind {
+                BanKind::Email => value.trim().to_lowercase(),
+                BanKind::Ip => value.trim().to_string(),
+            },
+            created_at: chrono::offset::Local::now().timestamp(),
+        }
+    }
+
+    pub fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt =
+            con.prepare("SELECT id, kind, value, created_at FROM ban ORDER BY created_at DESC")?;
+        let bans = stmt
+            .query_map([], |row| {
+                Ok(Ban {
+                    id: row.get(0)?,
+                    kind: row.get(1)?,
+                    value: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(bans)
+    }
+
+    pub fn is_banned(email: &str, ip: &str, con: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+        con.query_row(
+            "SELECT EXISTS (SELECT 1 FROM ban WHERE (kind = 'email' AND value = ?) OR (kind = 'ip' AND value = ?))",
+            params![email.trim().to_lowercase(), ip],
+            |row| row.get(0),
+        )
+    }
+
+    /// banning the same value twice is a no-op
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT OR IGNORE INTO ban (kind, value, created_at) VALUES (?, ?, ?)",
+            params![self.kind, self.value, self.created_at],
+        )?;
+        self.id = Some(con.query_row(
+            "SELECT id FROM ban WHERE kind = ? AND value = ?",
+            params![self.kind, self.value],
+            |row| row.get(0),
+        )?);
+        Ok(())
+    }
+
+    pub fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM ban WHERE id = ?", [&id])?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for Ban {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS ban (
+                id INTEGER PRIMARY KEY,
+                kind TEXT,
+                value TEXT,
+                created_at INTEGER,
+                UNIQUE (kind, value)
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Comment::up(&con).unwrap();
+        Ban::up(&con).unwrap();
+        con
+    }
+
+    fn comment(parent_id: Option<i64>, email: &str, ip: &str, con: &rusqlite::Connection) -> i64 {
+        let mut comment = Comment {
+            id: None,
+            article_id: 1,
+            parent_id,
+            author: "Reader".to_string(),
+            email: email.to_string(),
+            body: "Nice post".to_string(),
+            status: CommentStatus::Pending,
+            ip: ip.to_string(),
+            notify: false,
+            created_at: 0,
+            spam_score: 0,
+        };
+        comment.insert(con).unwrap();
+        comment.id.unwrap()
+    }
+
+    #[test]
+    fn delete_takes_the_whole_subtree() {
+        let con = db();
+        let root = comment(None, "", "", &con);
+        let reply = comment(Some(root), "", "", &con);
+        let nested = comment(Some(reply), "", "", &con);
+        let sibling = comment(Some(root), "", "", &con);
+        let other = comment(None, "", "", &con);
+
+        Comment::delete(reply, &con).unwrap();
+        let left: Vec<_> = Comment::find_all(&con)
+            .unwrap()
+            .into_iter()
+            .filter_map(|c| c.id)
+            .collect();
+        assert!(!left.contains(&reply));
+        assert!(!left.contains(&nested));
+        assert!(left.contains(&root) && left.contains(&sibling) && left.contains(&other));
+
+        Comment::delete(root, &con).unwrap();
+        let left: Vec<_> = Comment::find_all(&con)
+            .unwrap()
+            .into_iter()
+            .filter_map(|c| c.id)
+            .collect();
+        assert_eq!(left, vec![other]);
+    }
+
+    #[test]
+    fn bans_match_normalized_email_and_ip() {
+        let con = db();
+        Ban::new(BanKind::Email, " Spammer@Example.com ".to_string())
+            .insert(&con)
+            .unwrap();
+        Ban::new(BanKind::Ip, "10.0.0.1".to_string()).insert(&con).unwrap();
+
+        assert!(Ban::is_banned("spammer@example.COM", "127.0.0.1", &con).unwrap());
+        assert!(Ban::is_banned("", "10.0.0.1", &con).unwrap());
+        assert!(!Ban::is_banned("reader@example.com", "10.0.0.2", &con).unwrap());
+        // an ip ban must not match an email with the same text and vice versa
+        assert!(!Ban::is_banned("10.0.0.1", "", &con).unwrap());
+    }
+
+    #[test]
+    fn banning_twice_keeps_one_row() {
+        let con = db();
+        let mut first = Ban::new(BanKind::Email, "a@example.com".to_string());
+        first.insert(&con).unwrap();
+        let mut second = Ban::new(BanKind::Email, "A@example.com".to_string());
+        second.insert(&con).unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(Ban::find_all(&con).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn flag_banned_only_touches_pending_matches() {
+        let con = db();
+        let pending = comment(None, "Spammer@Example.com", "10.0.0.9", &con);
+        let approved = comment(None, "spammer@example.com", "10.0.0.9", &con);
+        Comment::set_status(approved, CommentStatus::Approved, &con).unwrap();
+        let innocent = comment(None, "reader@example.com", "10.0.0.1", &con);
+        let same_ip = comment(None, "other@example.com", "10.0.0.1", &con);
+
+        let mut ban = Ban::new(BanKind::Email, "spammer@example.com".to_string());
+        ban.insert(&con).unwrap();
+        assert_eq!(Comment::flag_banned(&ban, &con).unwrap(), 1);
+        assert_eq!(Comment::find(pending, &con).unwrap().status, CommentStatus::Spam);
+        assert_eq!(Comment::find(approved, &con).unwrap().status, CommentStatus::Approved);
+        assert_eq!(Comment::find(innocent, &con).unwrap().status, CommentStatus::Pending);
+
+        let mut ban = Ban::new(BanKind::Ip, "10.0.0.1".to_string());
+        ban.insert(&con).unwrap();
+        assert_eq!(Comment::flag_banned(&ban, &con).unwrap(), 2);
+        assert_eq!(Comment::find(same_ip, &con).unwrap().status, CommentStatus::Spam);
+    }
+}
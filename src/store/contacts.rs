@@ -1,7 +1,10 @@
-use super::{Crud, SchemaUp};
+use super::{add_column, Crud, SchemaUp};
 use rusqlite::params;
 use serde::{Deserialize, Serialize};
 
+const CONTACT_COLUMNS: &str =
+    "id, created, email, subject, message, spam_score, read_at, archived, labels";
+
 #[derive(Serialize, Deserialize)]
 pub struct ContactRequest {
     pub id: Option<i64>,
@@ -9,9 +12,59 @@ pub struct ContactRequest {
     pub email: String,
     pub subject: String,
     pub message: String,
+    /// see [crate::spam::score]
+    pub spam_score: i64,
+    /// `None` while unread
+    pub read_at: Option<i64>,
+    pub archived: bool,
+    /// comma separated
+    pub labels: String,
+}
+
+/// filters of the inbox view
+#[derive(Debug, Default, Serialize)]
+pub struct InboxFilter {
+    /// matches sender and subject
+    pub search: Option<String>,
+    pub label: Option<String>,
+    pub archived: bool,
+}
+
+impl InboxFilter {
+    /// where clause and its parameters
+    fn clause(&self) -> (String, Vec<String>) {
+        let mut clause = String::from("archived = ?");
+        let mut values = vec![(self.archived as i64).to_string()];
+
+        if let Some(search) = self.search.as_deref().filter(|s| !s.trim().is_empty()) {
+            clause.push_str(" AND (email LIKE ? OR subject LIKE ?)");
+            let like = format!("%{}%", search.trim());
+            values.push(like.clone());
+            values.push(like);
+        }
+        if let Some(label) = self.label.as_deref().filter(|l| !l.trim().is_empty()) {
+            clause.push_str(" AND (',' || labels || ',') LIKE ?");
+            values.push(format!("%,{},%", label.trim()));
+        }
+        (clause, values)
+    }
 }
 
 impl ContactRequest {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(ContactRequest {
+            id: row.get(0)?,
+            created: row.get(1)?,
+            email: row.get(2)?,
+            subject: row.get(3)?,
+            message: row.get(4)?,
+            spam_score: row.get(5)?,
+            read_at: row.get(6)?,
+            archived: row.get(7)?,
+            labels: row.get(8)?,
+        })
+    }
+
     pub fn count_all(con: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
         let mut stmt = con.prepare("SELECT COUNT(*) FROM contacts")?;
         let mut rows = stmt.query([])?;
@@ -21,25 +74,124 @@ impl ContactRequest {
         }
     }
 
+    pub fn count_unread(con: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
+        con.query_row(
+            "SELECT COUNT(*) FROM contacts WHERE read_at IS NULL AND archived = 0",
+            [],
+            |row| row.get(0),
+        )
+    }
+
     pub fn find_all_orderd(
         limit: i64,
         offset: i64,
         con: &rusqlite::Connection,
     ) -> Result<Vec<ContactRequest>, rusqlite::Error> {
-        let mut stmt =
-            con.prepare("SELECT * FROM contacts ORDER BY created DESC LIMIT ? OFFSET ?")?;
-        let mut rows = stmt.query([limit, offset])?;
-        let mut result = Vec::new();
-        while let Some(row) = rows.next()? {
-            result.push(ContactRequest {
-                id: row.get(0)?,
-                created: row.get(1)?,
-                email: row.get(2)?,
-                subject: row.get(3)?,
-                message: row.get(4)?,
-            })
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM contacts ORDER BY created DESC LIMIT ? OFFSET ?",
+            CONTACT_COLUMNS
+        ))?;
+        let contacts = stmt
+            .query_map([limit, offset], ContactRequest::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(contacts)
+    }
+
+    /// one page of the inbox, newest first
+    pub fn find_filtered(
+        filter: &InboxFilter,
+        limit: i64,
+        offset: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<ContactRequest>, rusqlite::Error> {
+        let (clause, mut values) = filter.clause();
+        values.push(limit.to_string());
+        values.push(offset.to_string());
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM contacts WHERE {} ORDER BY created DESC, id DESC LIMIT ? OFFSET ?",
+            CONTACT_COLUMNS, clause
+        ))?;
+        let contacts = stmt
+            .query_map(rusqlite::params_from_iter(values), ContactRequest::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(contacts)
+    }
+
+    pub fn count_filtered(
+        filter: &InboxFilter,
+        con: &rusqlite::Connection,
+    ) -> Result<i64, rusqlite::Error> {
+        let (clause, values) = filter.clause();
+        con.query_row(
+            &format!("SELECT COUNT(*) FROM contacts WHERE {}", clause),
+            rusqlite::params_from_iter(values),
+            |row| row.get(0),
+        )
+    }
+
+    /// all labels in use, sorted
+    pub fn find_labels(con: &rusqlite::Connection) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = con.prepare("SELECT labels FROM contacts WHERE labels != ''")?;
+        let mut labels = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .flat_map(|labels| ContactRequest::split_labels(labels))
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        Ok(labels)
+    }
+
+    pub fn split_labels(labels: &str) -> Vec<String> {
+        labels
+            .split(',')
+            .map(|label| label.trim().to_lowercase())
+            .filter(|label| !label.is_empty())
+            .collect()
+    }
+
+    pub fn set_labels(id: i64, labels: &str, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut labels = ContactRequest::split_labels(labels);
+        let mut seen = std::collections::HashSet::new();
+        labels.retain(|label| seen.insert(label.clone()));
+        con.execute(
+            "UPDATE contacts SET labels = ? WHERE id = ?",
+            params![labels.join(","), id],
+        )?;
+        Ok(())
+    }
+
+    /// `read_at` keeps the first time a message was opened
+    pub fn set_read(ids: &[i64], read: bool, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let read_at = read.then(|| chrono::offset::Local::now().timestamp());
+        let mut stmt = con.prepare(
+            "UPDATE contacts SET read_at = CASE WHEN ?1 IS NULL THEN NULL ELSE COALESCE(read_at, ?1) END WHERE id = ?2",
+        )?;
+        for id in ids {
+            stmt.execute(params![read_at, id])?;
         }
-        Ok(result)
+        Ok(())
+    }
+
+    pub fn set_archived(
+        ids: &[i64],
+        archived: bool,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare("UPDATE contacts SET archived = ? WHERE id = ?")?;
+        for id in ids {
+            stmt.execute(params![archived, id])?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_many(ids: &[i64], con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        for id in ids {
+            ContactRequest::delete(*id, &tx)?;
+        }
+        tx.commit()
     }
 }
 
@@ -55,51 +207,41 @@ impl SchemaUp for ContactRequest {
             )",
             (),
         )?;
+        add_column(con, "contacts", "spam_score", "INTEGER DEFAULT 0")?;
+        add_column(con, "contacts", "read_at", "INTEGER")?;
+        add_column(con, "contacts", "archived", "BOOLEAN DEFAULT 0")?;
+        add_column(con, "contacts", "labels", "TEXT DEFAULT ''")?;
         Ok(())
     }
 }
 
 impl Crud for ContactRequest {
     fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT * FROM contacts WHERE id = ?")?;
-        let mut rows = stmt.query([&id])?;
-        match rows.next()? {
-            Some(row) => Ok(ContactRequest {
-                id: row.get(0)?,
-                created: row.get(1)?,
-                email: row.get(2)?,
-                subject: row.get(3)?,
-                message: row.get(4)?,
-            }),
-            None => Err(rusqlite::Error::QueryReturnedNoRows),
-        }
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM contacts WHERE id = ?",
+            CONTACT_COLUMNS
+        ))?;
+        stmt.query_row([&id], ContactRequest::from_row)
     }
 
     fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT * FROM contacts")?;
-        let mut rows = stmt.query([])?;
-        let mut contacts = Vec::new();
-        while let Some(row) = rows.next()? {
-            contacts.push(ContactRequest {
-                id: row.get(0)?,
-                created: row.get(1)?,
-                email: row.get(2)?,
-                subject: row.get(3)?,
-                message: row.get(4)?,
-            })
-        }
+        let mut stmt = con.prepare(&format!("SELECT {} FROM contacts", CONTACT_COLUMNS))?;
+        let contacts = stmt
+            .query_map([], ContactRequest::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(contacts)
     }
 
     fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "INSERT INTO contacts (email ,created, subject, message) VALUES (?, ?, ?, ?)",
+            "INSERT INTO contacts (email ,created, subject, message, spam_score) VALUES (?, ?, ?, ?, ?)",
         )?;
         stmt.execute(params![
             &self.email,
             &self.created,
             &self.subject,
-            &self.message
+            &self.message,
+            &self.spam_score
         ])?;
         self.id = Some(con.last_insert_rowid());
         Ok(())
@@ -120,6 +262,208 @@ impl Crud for ContactRequest {
     fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare("DELETE FROM contacts WHERE id = ?")?;
         stmt.execute([&id])?;
+        con.execute("DELETE FROM contact_reply WHERE contact_id = ?", [&id])?;
         Ok(())
     }
 }
+
+/// an answer to a contact message, sent by mail
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactReply {
+    pub id: Option<i64>,
+    pub contact_id: i64,
+    pub body: String,
+    /// without angle brackets, later replies reference it
+    pub message_id: String,
+    pub sent_by: String,
+    pub sent_at: i64,
+    /// why sending failed, `None` once the relay accepted it
+    pub error: Option<String>,
+}
+
+impl ContactReply {
+    /// the thread of a message, oldest first
+    pub fn find_by_contact(
+        contact_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, contact_id, body, message_id, sent_by, sent_at, error FROM contact_reply WHERE contact_id = ? ORDER BY sent_at ASC, id ASC",
+        )?;
+        let replies = stmt
+            .query_map([&contact_id], |row| {
+                Ok(ContactReply {
+                    id: row.get(0)?,
+                    contact_id: row.get(1)?,
+                    body: row.get(2)?,
+                    message_id: row.get(3)?,
+                    sent_by: row.get(4)?,
+                    sent_at: row.get(5)?,
+                    error: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(replies)
+    }
+
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT INTO contact_reply (contact_id, body, message_id, sent_by, sent_at, error) VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                &self.contact_id,
+                &self.body,
+                &self.message_id,
+                &self.sent_by,
+                &self.sent_at,
+                &self.error,
+            ],
+        )?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+}
+
+impl SchemaUp for ContactReply {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS contact_reply (
+                id INTEGER PRIMARY KEY,
+                contact_id INTEGER,
+                body TEXT,
+                message_id TEXT,
+                sent_by TEXT,
+                sent_at INTEGER,
+                error TEXT
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        ContactRequest::up(&con).unwrap();
+        ContactReply::up(&con).unwrap();
+        con
+    }
+
+    fn contact(email: &str, subject: &str, labels: &str, con: &rusqlite::Connection) -> i64 {
+        let mut contact = ContactRequest {
+            id: None,
+            created: 0,
+            email: email.to_string(),
+            subject: subject.to_string(),
+            message: "Hello there".to_string(),
+            spam_score: 0,
+            read_at: None,
+            archived: false,
+            labels: String::new(),
+        };
+        contact.insert(con).unwrap();
+        let id = contact.id.unwrap();
+        ContactRequest::set_labels(id, labels, con).unwrap();
+        id
+    }
+
+    fn ids(filter: &InboxFilter, con: &rusqlite::Connection) -> Vec<i64> {
+        let mut ids: Vec<_> = ContactRequest::find_filtered(filter, 50, 0, con)
+            .unwrap()
+            .into_iter()
+            .filter_map(|c| c.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ContactRequest::count_filtered(filter, con).unwrap(), ids.len() as i64);
+        ids
+    }
+
+    #[test]
+    fn clause_skips_blank_filters() {
+        let filter = InboxFilter {
+            search: Some("  ".to_string()),
+            label: Some(String::new()),
+            archived: true,
+        };
+        assert_eq!(filter.clause(), ("archived = ?".to_string(), vec!["1".to_string()]));
+
+        let filter = InboxFilter {
+            search: Some(" invoice ".to_string()),
+            label: Some(" work ".to_string()),
+            archived: false,
+        };
+        let (clause, values) = filter.clause();
+        assert_eq!(clause.matches('?').count(), values.len());
+        assert_eq!(values, vec!["0", "%invoice%", "%invoice%", "%,work,%"]);
+    }
+
+    #[test]
+    fn filters_by_search_label_and_archive() {
+        let con = db();
+        let invoice = contact("billing@example.com", "Invoice 42", "work, urgent", &con);
+        let sender = contact("invoice@example.com", "Hi", "", &con);
+        let private = contact("friend@example.com", "Dinner", "Private", &con);
+        let workshop = contact("shop@example.com", "Workshop", "workshop", &con);
+        let archived = contact("old@example.com", "Invoice 1", "work", &con);
+        ContactRequest::set_archived(&[archived], true, &con).unwrap();
+
+        let inbox = InboxFilter::default();
+        assert_eq!(ids(&inbox, &con), vec![invoice, sender, private, workshop]);
+
+        let search = InboxFilter {
+            search: Some("invoice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&search, &con), vec![invoice, sender]);
+
+        // whole labels only, `work` does not match `workshop`
+        let label = InboxFilter {
+            label: Some("work".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&label, &con), vec![invoice]);
+        let label = InboxFilter {
+            label: Some("private".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&label, &con), vec![private]);
+
+        let archive = InboxFilter {
+            search: Some("invoice".to_string()),
+            label: Some("work".to_string()),
+            archived: true,
+        };
+        assert_eq!(ids(&archive, &con), vec![archived]);
+    }
+
+    #[test]
+    fn set_read_keeps_the_first_read_at() {
+        let con = db();
+        let opened = contact("a@example.com", "Old", "", &con);
+        let fresh = contact("b@example.com", "New", "", &con);
+        con.execute("UPDATE contacts SET read_at = 100 WHERE id = ?", [opened])
+            .unwrap();
+        assert_eq!(ContactRequest::count_unread(&con).unwrap(), 1);
+
+        ContactRequest::set_read(&[opened, fresh], true, &con).unwrap();
+        assert_eq!(ContactRequest::find(opened, &con).unwrap().read_at, Some(100));
+        assert!(ContactRequest::find(fresh, &con).unwrap().read_at.unwrap() > 100);
+        assert_eq!(ContactRequest::count_unread(&con).unwrap(), 0);
+
+        ContactRequest::set_read(&[opened], false, &con).unwrap();
+        assert_eq!(ContactRequest::find(opened, &con).unwrap().read_at, None);
+        assert_eq!(ContactRequest::count_unread(&con).unwrap(), 1);
+    }
+
+    #[test]
+    fn set_labels_normalizes() {
+        let con = db();
+        let id = contact("a@example.com", "Hi", " Work,,work , Urgent ", &con);
+        assert_eq!(ContactRequest::find(id, &con).unwrap().labels, "work,urgent");
+        contact("b@example.com", "Hi", "billing", &con);
+        assert_eq!(ContactRequest::find_labels(&con).unwrap(), vec!["billing", "urgent", "work"]);
+    }
+}
@@ -0,0 +1,343 @@
+use super::{add_column, Crud, SchemaUp};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+const MEDIA_COLUMNS: &str =
+    "id, path, article_id, mime, size, hash, width, height, alt, uploaded_by, created_at, deleted_at";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Media {
+    pub id: Option<i64>,
+    /// url path, e.g. `/static/media/3/cover.png`
+    pub path: String,
+    pub article_id: Option<i64>,
+    pub mime: String,
+    pub size: i64,
+    /// sha256 of the uploaded file, hex encoded. Kept when exif is stripped
+    /// from the file, uploads are matched against it.
+    pub hash: String,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub alt: String,
+    pub uploaded_by: String,
+    pub created_at: i64,
+    /// set while the file sits in the trash, see [Media::purge]
+    pub deleted_at: Option<i64>,
+}
+
+impl Media {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Media {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            article_id: row.get(2)?,
+            mime: row.get(3)?,
+            size: row.get(4)?,
+            hash: row.get(5)?,
+            width: row.get(6)?,
+            height: row.get(7)?,
+            alt: row.get(8)?,
+            uploaded_by: row.get(9)?,
+            created_at: row.get(10)?,
+            deleted_at: row.get(11)?,
+        })
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// file name without extension
+    pub fn stem(&self) -> &str {
+        let name = self.file_name();
+        name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name)
+    }
+
+    /// path on disk, relative to the working directory
+    pub fn disk_path(&self) -> std::path::PathBuf {
+        self.path.trim_start_matches('/').into()
+    }
+
+    pub fn find_by_hash(hash: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media WHERE hash = ?",
+            MEDIA_COLUMNS
+        ))?;
+        stmt.query_row([hash], Media::from_row)
+    }
+
+    pub fn find_by_article(
+        article_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media WHERE article_id = ?",
+            MEDIA_COLUMNS
+        ))?;
+        let media = stmt
+            .query_map([&article_id], Media::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(media)
+    }
+
+    pub fn find_by_path(path: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media WHERE path = ?",
+            MEDIA_COLUMNS
+        ))?;
+        stmt.query_row([path], Media::from_row)
+    }
+
+    /// takes the file out of the trash, see [crate::trash]
+    pub fn restore(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let restored = con.execute(
+            "UPDATE media SET deleted_at = NULL, deleted_with_article = 0 WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if restored == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// removes the row of a trashed file and its variants.
+    /// The files on disk are left to the caller, see `trash::purge_media`.
+    pub fn purge(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM media WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if deleted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.execute("DELETE FROM media_variant WHERE media_id = ?", [&id])?;
+        tx.commit()
+    }
+
+    /// files trashed on their own before `cutoff`, those of trashed
+    /// articles are purged with their article
+    pub fn find_expired(cutoff: i64, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id FROM media WHERE deleted_at <= ? AND deleted_with_article = 0",
+        )?;
+        let ids = stmt
+            .query_map([&cutoff], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    /// searches path, alt text and mime type
+    pub fn search(
+        query: &str,
+        limit: i64,
+        offset: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media
+             WHERE (path LIKE ?1 OR alt LIKE ?1 OR mime LIKE ?1) AND deleted_at IS NULL
+             ORDER BY created_at DESC LIMIT ?2 OFFSET ?3",
+            MEDIA_COLUMNS
+        ))?;
+        let like = format!("%{}%", query);
+        let media = stmt
+            .query_map(params![like, limit, offset], Media::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(media)
+    }
+
+    /// number of article covers and paragraphs pointing to this file.
+    /// Trashed ones count as well, they may still be restored.
+    pub fn reference_count(&self, con: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
+        let covers: i64 = con.query_row(
+            "SELECT COUNT(*) FROM article WHERE cover = ?",
+            [&self.path],
+            |row| row.get(0),
+        )?;
+        let paragraphs: i64 = con.query_row(
+            "SELECT COUNT(*) FROM paragraph WHERE instr(content, ?) > 0",
+            [&self.path],
+            |row| row.get(0),
+        )?;
+        Ok(covers + paragraphs)
+    }
+}
+
+/// a resized or converted copy of a media file
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MediaVariant {
+    pub id: Option<i64>,
+    pub media_id: i64,
+    pub path: String,
+    pub width: i64,
+    pub height: i64,
+    pub mime: String,
+    pub size: i64,
+    pub created_at: i64,
+}
+
+impl MediaVariant {
+    pub fn find_by_media(
+        media_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, media_id, path, width, height, mime, size, created_at FROM media_variant WHERE media_id = ? ORDER BY mime, width",
+        )?;
+        let variants = stmt
+            .query_map([&media_id], |row| {
+                Ok(MediaVariant {
+                    id: row.get(0)?,
+                    media_id: row.get(1)?,
+                    path: row.get(2)?,
+                    width: row.get(3)?,
+                    height: row.get(4)?,
+                    mime: row.get(5)?,
+                    size: row.get(6)?,
+                    created_at: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(variants)
+    }
+
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO media_variant (media_id, path, width, height, mime, size, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.media_id,
+            &self.path,
+            &self.width,
+            &self.height,
+            &self.mime,
+            &self.size,
+            &self.created_at,
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    pub fn delete_by_media(media_id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare("DELETE FROM media_variant WHERE media_id = ?")?;
+        stmt.execute([&media_id])?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for MediaVariant {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS media_variant (
+                id INTEGER PRIMARY KEY,
+                media_id INTEGER,
+                path TEXT,
+                width INTEGER,
+                height INTEGER,
+                mime TEXT,
+                size INTEGER,
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for Media {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS media (
+                id INTEGER PRIMARY KEY,
+                path TEXT UNIQUE,
+                article_id INTEGER,
+                mime TEXT,
+                size INTEGER,
+                hash TEXT,
+                width INTEGER,
+                height INTEGER,
+                alt TEXT DEFAULT '',
+                uploaded_by TEXT DEFAULT '',
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        add_column(con, "media", "deleted_at", "INTEGER")?;
+        add_column(con, "media", "deleted_with_article", "BOOLEAN DEFAULT 0")?;
+        con.execute(
+            "CREATE INDEX IF NOT EXISTS media_hash ON media (hash);",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+impl Crud for Media {
+    fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media WHERE id = ?",
+            MEDIA_COLUMNS
+        ))?;
+        stmt.query_row([&id], Media::from_row)
+    }
+
+    fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM media WHERE deleted_at IS NULL ORDER BY created_at DESC",
+            MEDIA_COLUMNS
+        ))?;
+        let media = stmt
+            .query_map([], Media::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(media)
+    }
+
+    fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO media (path, article_id, mime, size, hash, width, height, alt, uploaded_by, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.path,
+            &self.article_id,
+            &self.mime,
+            &self.size,
+            &self.hash,
+            &self.width,
+            &self.height,
+            &self.alt,
+            &self.uploaded_by,
+            &self.created_at,
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "UPDATE media SET path = ?, article_id = ?, mime = ?, size = ?, hash = ?, width = ?, height = ?, alt = ?, uploaded_by = ? WHERE id = ?",
+        )?;
+        stmt.execute(params![
+            &self.path,
+            &self.article_id,
+            &self.mime,
+            &self.size,
+            &self.hash,
+            &self.width,
+            &self.height,
+            &self.alt,
+            &self.uploaded_by,
+            &self.id.ok_or(rusqlite::Error::InvalidQuery)?,
+        ])?;
+        Ok(())
+    }
+
+    /// moves the file to the trash, see [Media::purge]
+    fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "UPDATE media SET deleted_at = ?, deleted_with_article = 0 WHERE id = ? AND deleted_at IS NULL",
+        )?;
+        stmt.execute(params![chrono::offset::Local::now().timestamp(), &id])?;
+        Ok(())
+    }
+}
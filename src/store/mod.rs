@@ -1,12 +1,53 @@
 pub mod articles;
 pub mod paragraphs;
 pub mod stats;
+pub mod comments;
 pub mod contacts;
+pub mod media;
+pub mod pages;
+pub mod previews;
+pub mod redirects;
+pub mod related;
+pub mod series;
+pub mod settings;
+pub mod sync;
+pub mod webhooks;
 
 pub fn schema_up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
     Ok(())
 }
 
+/// opens a connection that waits for locks instead of failing,
+/// background jobs use their own connection next to the shared one
+pub fn open(path: &str) -> Result<rusqlite::Connection, rusqlite::Error> {
+    let con = rusqlite::Connection::open(path)?;
+    con.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(con)
+}
+
+/// adds a column to an existing table, so `init` can migrate older databases
+pub fn add_column(
+    con: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = con.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        con.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
 pub trait Crud
 where
     Self: Sized,
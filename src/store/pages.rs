@@ -0,0 +1,582 @@
+use super::paragraphs::{Owner, Paragraph, ParagraphType};
+use super::stats::Stats;
+use super::{add_column, Crud, SchemaUp};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+const PAGE_COLUMNS: &str =
+    "id, slug, title, description, template, published, menu_position, created_at, updated_at";
+
+/// first path segments taken by other routes, a page there could never be reached
+const RESERVED_SLUGS: [&str; 13] = [
+    "api",
+    "static",
+    "article",
+    "series",
+    "media",
+    "schedule",
+    "redirects",
+    "trash",
+    "comments",
+    "inbox",
+    "webhooks",
+    "pages",
+    "settings",
+];
+
+/// the layout a page is rendered with, each has its own file in `templates/pages`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageTemplate {
+    /// title and paragraphs
+    Default,
+    /// paragraphs above the article list
+    Home,
+    /// paragraphs above the contact form
+    Contact,
+}
+
+impl PageTemplate {
+    pub const ALL: [PageTemplate; 3] = [
+        PageTemplate::Default,
+        PageTemplate::Home,
+        PageTemplate::Contact,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PageTemplate::Default => "default",
+            PageTemplate::Home => "home",
+            PageTemplate::Contact => "contact",
+        }
+    }
+
+    pub fn file(&self) -> &'static str {
+        match self {
+            PageTemplate::Default => "pages/page.html",
+            PageTemplate::Home => "pages/home.html",
+            PageTemplate::Contact => "pages/contact.html",
+        }
+    }
+}
+
+impl rusqlite::types::FromSql for PageTemplate {
+    fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "home" => Ok(PageTemplate::Home),
+            "contact" => Ok(PageTemplate::Contact),
+            _ => Ok(PageTemplate::Default),
+        }
+    }
+}
+
+impl rusqlite::types::ToSql for PageTemplate {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+/// a static page like about or contact, served at `/<slug>`. Its content is
+/// made of paragraphs, the same as an article.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Page {
+    pub id: Option<i64>,
+    /// empty for the front page
+    pub slug: String,
+    pub title: String,
+    /// meta description
+    pub description: String,
+    pub template: PageTemplate,
+    pub published: bool,
+    /// position in the navigation, `None` keeps the page out of it
+    pub menu_position: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub paragraphs: Option<Vec<Paragraph>>,
+}
+
+/// a link of the navigation
+#[derive(Debug, Clone, Serialize)]
+pub struct MenuItem {
+    pub title: String,
+    pub url: String,
+}
+
+impl Page {
+    pub fn new(title: String) -> Self {
+        let now = chrono::offset::Local::now().timestamp();
+        Page {
+            id: None,
+            slug: crate::render::slugify(&title),
+            title,
+            description: String::new(),
+            template: PageTemplate::Default,
+            published: false,
+            menu_position: None,
+            created_at: now,
+            updated_at: now,
+            paragraphs: None,
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Page {
+            id: row.get(0)?,
+            slug: row.get(1)?,
+            title: row.get(2)?,
+            description: row.get(3)?,
+            template: row.get(4)?,
+            published: row.get(5)?,
+            menu_position: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+            paragraphs: None,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("/{}", self.slug)
+    }
+
+    /// a slug for `/<slug>`, empty for the front page.
+    /// Fails for slugs other routes already answer.
+    pub fn normalize_slug(slug: &str) -> Result<String, &'static str> {
+        let slug = slug.trim().trim_matches('/');
+        if slug.is_empty() {
+            return Ok(String::new());
+        }
+        let slug = crate::render::slugify(slug);
+        match RESERVED_SLUGS.contains(&slug.as_str()) {
+            true => Err("slug is reserved"),
+            false => Ok(slug),
+        }
+    }
+
+    /// trashed pages keep their slug until they are purged
+    pub fn slug_taken(
+        slug: &str,
+        except: Option<i64>,
+        con: &rusqlite::Connection,
+    ) -> Result<bool, rusqlite::Error> {
+        let count: i64 = con.query_row(
+            "SELECT COUNT(*) FROM page WHERE slug = ? AND id IS NOT ?",
+            params![slug, except],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// the page with its rendered paragraphs
+    pub fn find_by_slug(slug: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM page WHERE slug = ? AND deleted_at IS NULL",
+            PAGE_COLUMNS
+        ))?;
+        let mut page = stmt.query_row([slug], Page::from_row)?;
+        page.load_paragraphs(con)?;
+        Ok(page)
+    }
+
+    pub fn load_paragraphs(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let id = self.id.ok_or(rusqlite::Error::InvalidQuery)?;
+        let mut paragraphs = Paragraph::find_by_owner(Owner::Page(id), con)?;
+        Paragraph::anchor_headings(&mut paragraphs);
+        self.paragraphs = Some(paragraphs);
+        Ok(())
+    }
+
+    /// renders the paragraphs again with render errors shown, for admins
+    pub fn render_for_admin(&mut self) {
+        if let Some(paragraphs) = self.paragraphs.as_mut() {
+            paragraphs.iter_mut().for_each(|para| para.render(true));
+            Paragraph::anchor_headings(paragraphs);
+        }
+    }
+
+    /// marks the page as changed, its paragraphs were edited
+    pub fn touch(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "UPDATE page SET updated_at = ? WHERE id = ?",
+            params![chrono::offset::Local::now().timestamp(), id],
+        )?;
+        Ok(())
+    }
+
+    /// published pages with a menu position, in menu order
+    pub fn find_menu(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM page WHERE published = 1 AND menu_position IS NOT NULL AND deleted_at IS NULL
+             ORDER BY menu_position, title",
+            PAGE_COLUMNS
+        ))?;
+        let pages = stmt
+            .query_map([], Page::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pages)
+    }
+
+    /// the pages that used to be hard coded, for a fresh or older database.
+    /// Does nothing once any page exists.
+    pub fn seed(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let count: i64 = con.query_row("SELECT COUNT(*) FROM page", [], |row| row.get(0))?;
+        if count > 0 {
+            return Ok(());
+        }
+
+        let tx = con.unchecked_transaction()?;
+        let mut ids = Vec::new();
+        for (slug, title, description, template, menu_position, paragraph) in SEED {
+            let mut page = Page::new(title.to_string());
+            page.slug = slug.to_string();
+            page.description = description.to_string();
+            page.template = template;
+            page.published = true;
+            page.menu_position = menu_position;
+            page.insert(&tx)?;
+
+            if let Some((paragraph_type, content)) = paragraph {
+                Paragraph {
+                    id: None,
+                    article_id: None,
+                    page_id: page.id,
+                    title: String::new(),
+                    description: String::new(),
+                    paragraph_type,
+                    position: 0,
+                    content: content.to_string(),
+                    rendered: None,
+                }
+                .insert(&tx)?;
+            }
+            ids.push((slug, page.id.unwrap_or_default()));
+        }
+
+        let id = |slug: &str| ids.iter().find(|(s, _)| *s == slug).map(|(_, id)| *id);
+        Stats::migrate_page_views(
+            &[
+                ("home_views", id("")),
+                ("about_views", id("about")),
+                ("donate_views", id("donate")),
+            ],
+            &tx,
+        )?;
+        tx.commit()
+    }
+}
+
+type SeedPage = (
+    &'static str,
+    &'static str,
+    &'static str,
+    PageTemplate,
+    Option<i64>,
+    Option<(ParagraphType, &'static str)>,
+);
+
+const SEED: [SeedPage; 4] = [
+    (
+        "",
+        "Blog",
+        "My name is Lorenz, I am a web/game developer from Germany. Driven by passion and curiosity, I am always eager to learn new things and explore. On this blog I share my projects, opinions, experiences and some tips and tricks along the way.",
+        PageTemplate::Home,
+        Some(0),
+        None,
+    ),
+    (
+        "contact",
+        "Contact",
+        "",
+        PageTemplate::Contact,
+        Some(1),
+        Some((
+            ParagraphType::Markdown,
+            "Feedback or questions? Leave me a message.",
+        )),
+    ),
+    (
+        "about",
+        "About",
+        "My name is Lorenz, I am a web/game developer from Germany. On this Blog I share my projects, opinions and experiences.",
+        PageTemplate::Default,
+        Some(2),
+        Some((
+            ParagraphType::Html,
+            r#"<p>
+	My name is Lorenz, a web/game developer from Germany with a passion for exploring and learning new things.
+</p>
+<p>
+	I am driven by curiosity and a desire to continuously learn and explore new ideas, which has led me on a journey from hardware development to embedded systems and eventually to web development and gaming.
+</p>
+<p>
+	My interests lie in the field of system design, experimentation, and improving upon existing technologies through constant exploration and seeking out new ways of doing things.
+</p>
+
+<div class="flex justify-center text-center">
+	<div>
+		<img src="/static/images/mcu.jpg"/>
+		<span class="text-xs">(Crystal DDR Sdram display driver, vUsb Programming Adapter)</span>
+	</div>
+</div>"#,
+        )),
+    ),
+    (
+        "donate",
+        "Support me",
+        "Support my work by liking, sharing or donating",
+        PageTemplate::Default,
+        None,
+        Some((
+            ParagraphType::Html,
+            r#"<p>
+	Do you like what I am doing? Does any of my projects inspire you?
+	If so then leave me a star on <a href="https://github.com/Lommix">GitHub</a>.
+</p>
+<p>
+	If you want to support me even further, any donation is welcome.
+</p>
+<a href="https://www.paypal.com/donate/?hosted_button_id=8Q4QRN5ALF5AA" target="_blank"
+	class="text-xl font-bold bg-blue-800 hover:bg-blue-600 p-4 no-underline text-white flex flex-row w-fit px-8 mt-8">
+	<img class="mx-2" src="/static/images/paypal.svg" />
+	Donate
+</a>"#,
+        )),
+    ),
+];
+
+impl SchemaUp for Page {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS page (
+                id INTEGER PRIMARY KEY,
+                slug TEXT UNIQUE,
+                title TEXT,
+                description TEXT DEFAULT '',
+                template TEXT DEFAULT 'default',
+                published BOOLEAN DEFAULT 0,
+                menu_position INTEGER,
+                created_at INTEGER,
+                updated_at INTEGER
+            );",
+            (),
+        )?;
+        add_column(con, "page", "deleted_at", "INTEGER")?;
+        Ok(())
+    }
+}
+
+// --------------------------------------------------------
+// trash
+// --------------------------------------------------------
+impl Page {
+    /// brings back a trashed page together with the paragraphs trashed along with it
+    pub fn restore(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let restored = tx.execute(
+            "UPDATE page SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if restored == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.execute(
+            "UPDATE paragraph SET deleted_at = NULL, deleted_with_article = 0 WHERE page_id = ? AND deleted_with_article = 1",
+            [&id],
+        )?;
+        tx.commit()
+    }
+
+    /// removes a trashed page and all its paragraphs for good
+    pub fn purge(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM page WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        if deleted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Paragraph::delete_by_page(id, &tx)?;
+        tx.commit()
+    }
+
+    /// ids of pages trashed before `cutoff`
+    pub fn find_expired(cutoff: i64, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let mut stmt = con.prepare("SELECT id FROM page WHERE deleted_at <= ?")?;
+        let ids = stmt
+            .query_map([&cutoff], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+}
+
+impl Crud for Page {
+    fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM page WHERE id = ? AND deleted_at IS NULL",
+            PAGE_COLUMNS
+        ))?;
+        stmt.query_row([&id], Page::from_row)
+    }
+
+    /// all pages, in menu order with the pages outside of the menu last
+    fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM page WHERE deleted_at IS NULL
+             ORDER BY menu_position IS NULL, menu_position, title",
+            PAGE_COLUMNS
+        ))?;
+        let pages = stmt
+            .query_map([], Page::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pages)
+    }
+
+    fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO page (slug, title, description, template, published, menu_position, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.slug,
+            &self.title,
+            &self.description,
+            &self.template,
+            &self.published,
+            &self.menu_position,
+            &self.created_at,
+            &self.updated_at,
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "UPDATE page SET slug = ?, title = ?, description = ?, template = ?, published = ?, menu_position = ?, updated_at = ?
+             WHERE id = ?",
+        )?;
+        stmt.execute(params![
+            &self.slug,
+            &self.title,
+            &self.description,
+            &self.template,
+            &self.published,
+            &self.menu_position,
+            &self.updated_at,
+            &self.id.ok_or(rusqlite::Error::InvalidQuery)?,
+        ])?;
+        Ok(())
+    }
+
+    /// moves the page and its paragraphs to the trash, see [Page::purge]
+    fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let now = chrono::offset::Local::now().timestamp();
+        let tx = con.unchecked_transaction()?;
+        let deleted = tx.execute(
+            "UPDATE page SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+            params![now, id],
+        )?;
+        if deleted == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        // flagged like the paragraphs of a trashed article
+        tx.execute(
+            "UPDATE paragraph SET deleted_at = ?, deleted_with_article = 1 WHERE page_id = ? AND deleted_at IS NULL",
+            params![now, id],
+        )?;
+        tx.commit()
+    }
+}
+
+/// the navigation built from the page records, shared with the templates
+/// through `menu()`. Refreshed whenever a page changes.
+#[derive(Debug, Clone, Default)]
+pub struct Menu(Arc<RwLock<Vec<MenuItem>>>);
+
+impl Menu {
+    pub fn refresh(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let items = Page::find_menu(con)?
+            .into_iter()
+            .map(|page| MenuItem {
+                url: page.url(),
+                title: page.title,
+            })
+            .collect();
+        if let Ok(mut menu) = self.0.write() {
+            *menu = items;
+        }
+        Ok(())
+    }
+
+    pub fn items(&self) -> Vec<MenuItem> {
+        self.0.read().map(|menu| menu.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Page::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        con
+    }
+
+    fn page(title: &str, published: bool, menu_position: Option<i64>, con: &rusqlite::Connection) -> i64 {
+        let mut page = Page::new(title.to_string());
+        page.published = published;
+        page.menu_position = menu_position;
+        page.insert(con).unwrap();
+        page.id.unwrap()
+    }
+
+    #[test]
+    fn reserved_slugs_are_refused() {
+        assert_eq!(Page::normalize_slug(" /About Me/ "), Ok("about-me".to_string()));
+        assert_eq!(Page::normalize_slug("/"), Ok(String::new()));
+        for slug in ["api", "/static/", "Trash", "settings"] {
+            assert_eq!(Page::normalize_slug(slug), Err("slug is reserved"), "{}", slug);
+        }
+        assert_eq!(Page::normalize_slug("articles"), Ok("articles".to_string()));
+    }
+
+    #[test]
+    fn trashed_pages_keep_their_slug() {
+        let con = db();
+        let about = page("About", true, None, &con);
+        assert!(Page::slug_taken("about", None, &con).unwrap());
+        assert!(!Page::slug_taken("about", Some(about), &con).unwrap());
+        assert!(!Page::slug_taken("contact", None, &con).unwrap());
+
+        Page::delete(about, &con).unwrap();
+        assert!(Page::find_by_slug("about", &con).is_err());
+        assert!(Page::slug_taken("about", None, &con).unwrap());
+
+        Page::purge(about, &con).unwrap();
+        assert!(!Page::slug_taken("about", None, &con).unwrap());
+    }
+
+    #[test]
+    fn menu_follows_position_then_title() {
+        let con = db();
+        page("Contact", true, Some(2), &con);
+        page("Blog", true, Some(1), &con);
+        page("About", true, Some(2), &con);
+        page("Draft", false, Some(0), &con);
+        page("Imprint", true, None, &con);
+        let trashed = page("Old", true, Some(0), &con);
+        Page::delete(trashed, &con).unwrap();
+
+        let menu = Menu::default();
+        menu.refresh(&con).unwrap();
+        let items: Vec<_> = menu.items().into_iter().map(|item| item.url).collect();
+        assert_eq!(items, vec!["/blog", "/about", "/contact"]);
+
+        // the admin list keeps pages outside of the menu, last
+        let titles: Vec<_> = Page::find_all(&con).unwrap().into_iter().map(|p| p.title).collect();
+        assert_eq!(titles, vec!["Draft", "Blog", "About", "Contact", "Imprint"]);
+    }
+}
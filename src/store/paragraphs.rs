@@ -1,4 +1,5 @@
-use super::{Crud, SchemaDown, SchemaUp};
+use super::{add_column, Crud, SchemaDown, SchemaUp};
+use crate::render;
 use rusqlite::{
     params,
     types::{FromSql, ToSqlOutput},
@@ -10,6 +11,7 @@ use serde::{Deserialize, Serialize};
 pub enum ParagraphType {
     Markdown,
     Html,
+    Wasm,
 }
 
 impl FromSql for ParagraphType {
@@ -17,6 +19,7 @@ impl FromSql for ParagraphType {
         match value.as_str()? {
             "markdown" => Ok(ParagraphType::Markdown),
             "html" => Ok(ParagraphType::Html),
+            "wasm" => Ok(ParagraphType::Wasm),
             _ => Ok(ParagraphType::Html),
         }
     }
@@ -27,14 +30,43 @@ impl ToSql for ParagraphType {
         match self {
             ParagraphType::Markdown => Ok("markdown".into()),
             ParagraphType::Html => Ok("html".into()),
+            ParagraphType::Wasm => Ok("wasm".into()),
         }
     }
 }
 
+/// what a paragraph belongs to
+#[derive(Debug, Clone, Copy)]
+pub enum Owner {
+    Article(i64),
+    Page(i64),
+}
+
+impl Owner {
+    /// the column naming the owner and its id
+    fn column(&self) -> (&'static str, i64) {
+        match self {
+            Owner::Article(id) => ("article_id", *id),
+            Owner::Page(id) => ("page_id", *id),
+        }
+    }
+}
+
+/// where a new paragraph goes inside its article
+#[derive(Debug, Clone, Copy)]
+pub enum Placement {
+    End,
+    After(i64),
+    Before(i64),
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Paragraph {
     pub id: Option<i64>,
-    pub article_id: i64,
+    /// `None` for paragraphs of a page
+    pub article_id: Option<i64>,
+    /// set for paragraphs of a page, see [crate::store::pages::Page]
+    pub page_id: Option<i64>,
     pub title: String,
     pub description: String,
     pub paragraph_type: ParagraphType,
@@ -44,37 +76,257 @@ pub struct Paragraph {
 }
 
 impl Paragraph {
+    pub fn owner(&self) -> Owner {
+        match self.page_id {
+            Some(id) => Owner::Page(id),
+            None => Owner::Article(self.article_id.unwrap_or_default()),
+        }
+    }
+
+    /// `admin` keeps render errors, e.g. of invalid tex, in the output.
+    /// Paragraphs loaded from the db are rendered for readers.
+    pub fn render(&mut self, admin: bool) {
+        self.rendered = match self.paragraph_type {
+            ParagraphType::Markdown => Some(render::markdown(&self.content, admin)),
+            ParagraphType::Html => Some(self.content.clone()),
+            ParagraphType::Wasm => match render::WasmDemo::parse(&self.content)
+                .and_then(|demo| demo.to_html(self.id.unwrap_or_default()))
+            {
+                Ok(html) => Some(html),
+                Err(err) => Some(err.to_string()),
+            },
+        };
+    }
+
+    /// assigns slug ids to the headings of all markdown and html paragraphs of an article
+    /// and returns the nested table of contents
+    pub fn anchor_headings(paragraphs: &mut [Paragraph]) -> Vec<render::TocEntry> {
+        let mut headings = render::Headings::default();
+        for para in paragraphs.iter() {
+            if let Some((rendered, keep_ids)) = para.headings_source() {
+                headings.claim(rendered, para.id.unwrap_or_default(), keep_ids);
+            }
+        }
+        for para in paragraphs.iter_mut() {
+            if let Some((rendered, keep_ids)) = para.headings_source() {
+                para.rendered = Some(headings.anchor(rendered, para.id.unwrap_or_default(), keep_ids));
+            }
+        }
+        headings.toc()
+    }
+
+    /// the rendered html of paragraphs whose headings go into the table of contents.
+    /// Ids in html paragraphs are written by hand and kept, the markdown renderer's are replaced.
+    fn headings_source(&self) -> Option<(&str, bool)> {
+        match (&self.paragraph_type, &self.rendered) {
+            (ParagraphType::Markdown, Some(rendered)) => Some((rendered, false)),
+            (ParagraphType::Html, Some(rendered)) => Some((rendered, true)),
+            _ => None,
+        }
+    }
+
+    /// checks the content before it is stored
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self.paragraph_type {
+            ParagraphType::Wasm => render::WasmDemo::parse(&self.content)?.validate(),
+            _ => Ok(()),
+        }
+    }
+
     pub fn find_by_article_id(
         article_id: i64,
         con: &rusqlite::Connection,
     ) -> Result<Vec<Self>, rusqlite::Error> {
-        let mut stmt = con.prepare(
-            "SELECT id, article_id, title, description, paragraph_type, position, content FROM paragraph WHERE article_id = ?"
-        )?;
-        let mut rows = stmt.query([&article_id])?;
+        Self::find_by_owner(Owner::Article(article_id), con)
+    }
+
+    pub fn find_by_owner(owner: Owner, con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let (column, id) = owner.column();
+        let mut stmt = con.prepare(&format!(
+            "SELECT id, article_id, page_id, title, description, paragraph_type, position, content FROM paragraph WHERE {} = ? AND deleted_at IS NULL ORDER BY position, id",
+            column
+        ))?;
+        let mut rows = stmt.query([&id])?;
         let mut paragraphs = Vec::new();
         while let Some(row) = rows.next()? {
             let mut para = Paragraph {
                 id: row.get(0)?,
                 article_id: row.get(1)?,
-                title: row.get(2)?,
-                description: row.get(3)?,
-                paragraph_type: row.get(4)?,
-                position: row.get(5)?,
-                content: row.get(6)?,
+                page_id: row.get(2)?,
+                title: row.get(3)?,
+                description: row.get(4)?,
+                paragraph_type: row.get(5)?,
+                position: row.get(6)?,
+                content: row.get(7)?,
                 rendered: None,
             };
 
-            match para.paragraph_type {
-                ParagraphType::Markdown => para.rendered = Some(markdown::to_html(&para.content)),
-                _ => (),
-            };
+            para.render(false);
 
             paragraphs.push(para);
         }
 
         Ok(paragraphs)
     }
+    pub fn ordered_ids(owner: Owner, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let (column, id) = owner.column();
+        let mut stmt = con.prepare(&format!(
+            "SELECT id FROM paragraph WHERE {} = ? AND deleted_at IS NULL ORDER BY position, id",
+            column
+        ))?;
+        let ids = stmt
+            .query_map([&id], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    fn write_positions(ids: &[i64], con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare("UPDATE paragraph SET position = ? WHERE id = ?")?;
+        for (position, id) in ids.iter().enumerate() {
+            stmt.execute(params![position as i64, id])?;
+        }
+        Ok(())
+    }
+
+    /// inserts the paragraph and renumbers the positions of its article
+    pub fn insert_at(
+        &mut self,
+        placement: Placement,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let mut order = Self::ordered_ids(self.owner(), &tx)?;
+
+        let index = match placement {
+            Placement::End => order.len(),
+            Placement::After(anchor) => order
+                .iter()
+                .position(|id| *id == anchor)
+                .map(|i| i + 1)
+                .ok_or(rusqlite::Error::QueryReturnedNoRows)?,
+            Placement::Before(anchor) => order
+                .iter()
+                .position(|id| *id == anchor)
+                .ok_or(rusqlite::Error::QueryReturnedNoRows)?,
+        };
+
+        self.position = index as i64;
+        self.insert(&tx)?;
+        order.insert(index, self.id.ok_or(rusqlite::Error::InvalidQuery)?);
+        Self::write_positions(&order, &tx)?;
+        tx.commit()
+    }
+
+    /// applies a new order. The ids must be exactly the paragraphs of the owner,
+    /// otherwise nothing is changed.
+    pub fn reorder(owner: Owner, ids: &[i64], con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+
+        let mut current = Self::ordered_ids(owner, &tx)?;
+        let mut requested = ids.to_vec();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+
+        Self::write_positions(ids, &tx)?;
+        tx.commit()
+    }
+
+    /// copies a paragraph and places the copy right after the original
+    pub fn duplicate(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut copy = Self::find(id, con)?;
+        copy.id = None;
+        copy.insert_at(Placement::After(id), con)?;
+        copy.render(false);
+        Ok(copy)
+    }
+
+    /// removes all paragraphs of an article, trashed ones included
+    pub fn delete_by_article(article_id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM paragraph WHERE article_id = ?", [&article_id])?;
+        Ok(())
+    }
+
+    /// removes all paragraphs of a page, trashed ones included
+    pub fn delete_by_page(page_id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM paragraph WHERE page_id = ?", [&page_id])?;
+        Ok(())
+    }
+
+    /// moves a paragraph to the end of another article.
+    /// Paragraphs of a page stay on their page.
+    pub fn move_to(
+        id: i64,
+        article_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let mut paragraph = Self::find(id, &tx)?;
+        let source = match paragraph.owner() {
+            Owner::Article(source_id) => Owner::Article(source_id),
+            Owner::Page(_) => return Err(rusqlite::Error::InvalidQuery),
+        };
+
+        paragraph.article_id = Some(article_id);
+        paragraph.position = Self::ordered_ids(Owner::Article(article_id), &tx)?.len() as i64;
+        paragraph.update(&tx)?;
+
+        Self::write_positions(&Self::ordered_ids(source, &tx)?, &tx)?;
+        tx.commit()
+    }
+}
+
+// --------------------------------------------------------
+// trash
+// --------------------------------------------------------
+impl Paragraph {
+    /// takes a paragraph out of the trash and appends it to its article or page.
+    /// Paragraphs of a trashed article or page come back with it.
+    pub fn restore(id: i64, con: &rusqlite::Connection) -> Result<Owner, rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        let owner = tx.query_row(
+            "SELECT p.article_id, p.page_id FROM paragraph p
+             LEFT JOIN article a ON a.id = p.article_id
+             LEFT JOIN page g ON g.id = p.page_id
+             WHERE p.id = ? AND p.deleted_at IS NOT NULL
+             AND ((g.id IS NOT NULL AND g.deleted_at IS NULL) OR (a.id IS NOT NULL AND a.deleted_at IS NULL))",
+            [&id],
+            |row| {
+                Ok(match row.get::<_, Option<i64>>(1)? {
+                    Some(page_id) => Owner::Page(page_id),
+                    None => Owner::Article(row.get(0)?),
+                })
+            },
+        )?;
+        let position = Self::ordered_ids(owner, &tx)?.len() as i64;
+        tx.execute(
+            "UPDATE paragraph SET deleted_at = NULL, position = ? WHERE id = ?",
+            params![position, id],
+        )?;
+        tx.commit()?;
+        Ok(owner)
+    }
+
+    /// removes a trashed paragraph for good
+    pub fn purge(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "DELETE FROM paragraph WHERE id = ? AND deleted_at IS NOT NULL",
+            [&id],
+        )?;
+        Ok(())
+    }
+
+    /// purges paragraphs trashed before `cutoff` and those left behind
+    /// by articles that were deleted before the trash existed
+    pub fn purge_expired(cutoff: i64, con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+        con.execute(
+            "DELETE FROM paragraph WHERE deleted_at <= ?
+             OR (page_id IS NULL AND article_id NOT IN (SELECT id FROM article))",
+            [&cutoff],
+        )
+    }
 }
 
 impl SchemaUp for Paragraph {
@@ -91,6 +343,14 @@ impl SchemaUp for Paragraph {
             );",
             (),
         )?;
+        add_column(con, "paragraph", "deleted_at", "INTEGER")?;
+        add_column(con, "paragraph", "deleted_with_article", "BOOLEAN DEFAULT 0")?;
+        add_column(con, "paragraph", "page_id", "INTEGER")?;
+        // page paragraphs used to be stored with article id 0
+        con.execute(
+            "UPDATE paragraph SET article_id = NULL WHERE page_id IS NOT NULL",
+            (),
+        )?;
         Ok(())
     }
 }
@@ -99,25 +359,23 @@ impl Crud for Paragraph {
     fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
         let mut paragraphs = Vec::new();
         let mut stmt = con.prepare(
-            "SELECT id, article_id, title, description, paragraph_type, position, content FROM paragraph",
+            "SELECT id, article_id, page_id, title, description, paragraph_type, position, content FROM paragraph WHERE deleted_at IS NULL",
         )?;
         let mut rows = stmt.query([])?;
         while let Some(row) = rows.next()? {
             let mut para = Paragraph {
                 id: row.get(0)?,
                 article_id: row.get(1)?,
-                title: row.get(2)?,
-                description: row.get(3)?,
-                paragraph_type: row.get(4)?,
-                position: row.get(5)?,
-                content: row.get(6)?,
+                page_id: row.get(2)?,
+                title: row.get(3)?,
+                description: row.get(4)?,
+                paragraph_type: row.get(5)?,
+                position: row.get(6)?,
+                content: row.get(7)?,
                 rendered: None,
             };
 
-            match para.paragraph_type {
-                ParagraphType::Markdown => para.rendered = Some(markdown::to_html(&para.content)),
-                _ => (),
-            }
+            para.render(false);
 
             paragraphs.push(para);
         }
@@ -126,7 +384,7 @@ impl Crud for Paragraph {
 
     fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
         let mut stmt = con.prepare(
-            "SELECT id, article_id, title, description, paragraph_type, position, content FROM paragraph WHERE id = ?;"
+            "SELECT id, article_id, page_id, title, description, paragraph_type, position, content FROM paragraph WHERE id = ? AND deleted_at IS NULL;"
         )?;
         let mut rows = stmt.query([&id])?;
         match rows.next()? {
@@ -134,17 +392,15 @@ impl Crud for Paragraph {
                 let mut para = Paragraph {
                     id: row.get(0)?,
                     article_id: row.get(1)?,
-                    title: row.get(2)?,
-                    description: row.get(3)?,
-                    paragraph_type: row.get(4)?,
-                    position: row.get(5)?,
-                    content: row.get(6)?,
+                    page_id: row.get(2)?,
+                    title: row.get(3)?,
+                    description: row.get(4)?,
+                    paragraph_type: row.get(5)?,
+                    position: row.get(6)?,
+                    content: row.get(7)?,
                     rendered: None,
                 };
-                match para.paragraph_type {
-                    ParagraphType::Markdown => para.rendered = Some(markdown::to_html(&para.content)),
-                    _ => (),
-                }
+                para.render(false);
                 Ok(para)
             }
             None => Err(rusqlite::Error::QueryReturnedNoRows),
@@ -153,10 +409,11 @@ impl Crud for Paragraph {
 
     fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "INSERT INTO paragraph (article_id, title, description, paragraph_type, position, content) VALUES (?, ?, ?, ?, ?, ?);",
+            "INSERT INTO paragraph (article_id, page_id, title, description, paragraph_type, position, content) VALUES (?, ?, ?, ?, ?, ?, ?);",
         )?;
         stmt.execute(params![
             &self.article_id,
+            &self.page_id,
             &self.title,
             &self.description,
             &self.paragraph_type,
@@ -169,11 +426,12 @@ impl Crud for Paragraph {
 
     fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "UPDATE paragraph SET article_id = ?, title = ?, description = ?, paragraph_type = ?, position = ?, content = ? WHERE id = ?;",
+            "UPDATE paragraph SET article_id = ?, page_id = ?, title = ?, description = ?, paragraph_type = ?, position = ?, content = ? WHERE id = ?;",
         )?;
 
         stmt.execute(params![
             &self.article_id,
+            &self.page_id,
             &self.title,
             &self.description,
             &self.paragraph_type,
@@ -185,9 +443,12 @@ impl Crud for Paragraph {
         Ok(())
     }
 
+    /// moves the paragraph to the trash, see [Paragraph::purge]
     fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
-        let mut stmt = con.prepare("DELETE FROM paragraph WHERE id = ?")?;
-        stmt.execute([&id])?;
+        let mut stmt = con.prepare(
+            "UPDATE paragraph SET deleted_at = ?, deleted_with_article = 0 WHERE id = ? AND deleted_at IS NULL",
+        )?;
+        stmt.execute(params![chrono::offset::Local::now().timestamp(), &id])?;
         Ok(())
     }
 }
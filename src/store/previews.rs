@@ -0,0 +1,111 @@
+use super::SchemaUp;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// grants read access to one unpublished article via `?preview=<token>`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PreviewToken {
+    pub id: Option<i64>,
+    pub article_id: i64,
+    pub token: String,
+    pub created_at: i64,
+    /// `None` never expires
+    pub expires_at: Option<i64>,
+}
+
+impl PreviewToken {
+    pub fn new(article_id: i64, expires_at: Option<i64>) -> Self {
+        PreviewToken {
+            id: None,
+            article_id,
+            token: format!("{:032x}", rand::random::<u128>()),
+            created_at: chrono::offset::Local::now().timestamp(),
+            expires_at,
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(PreviewToken {
+            id: row.get(0)?,
+            article_id: row.get(1)?,
+            token: row.get(2)?,
+            created_at: row.get(3)?,
+            expires_at: row.get(4)?,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| at <= chrono::offset::Local::now().timestamp())
+    }
+
+    pub fn find_by_article(
+        article_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, article_id, token, created_at, expires_at FROM preview_token WHERE article_id = ? ORDER BY created_at DESC",
+        )?;
+        let tokens = stmt
+            .query_map([&article_id], PreviewToken::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens)
+    }
+
+    /// true if `token` belongs to the article and has not expired
+    pub fn is_valid(
+        article_id: i64,
+        token: &str,
+        con: &rusqlite::Connection,
+    ) -> Result<bool, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, article_id, token, created_at, expires_at FROM preview_token WHERE article_id = ? AND token = ?",
+        )?;
+        let mut rows = stmt.query(params![article_id, token])?;
+        match rows.next()? {
+            Some(row) => Ok(!PreviewToken::from_row(row)?.is_expired()),
+            None => Ok(false),
+        }
+    }
+
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO preview_token (article_id, token, created_at, expires_at) VALUES (?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.article_id,
+            &self.token,
+            &self.created_at,
+            &self.expires_at
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    /// revokes a token, returns the article it belonged to
+    pub fn revoke(id: i64, con: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
+        let article_id = con.query_row(
+            "SELECT article_id FROM preview_token WHERE id = ?",
+            [&id],
+            |row| row.get(0),
+        )?;
+        con.execute("DELETE FROM preview_token WHERE id = ?", [&id])?;
+        Ok(article_id)
+    }
+}
+
+impl SchemaUp for PreviewToken {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS preview_token (
+                id INTEGER PRIMARY KEY,
+                article_id INTEGER,
+                token TEXT UNIQUE,
+                created_at INTEGER,
+                expires_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
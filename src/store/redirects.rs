@@ -0,0 +1,155 @@
+use super::SchemaUp;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// an alias an article was reachable under before it was renamed
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ArticleAlias {
+    pub id: Option<i64>,
+    pub article_id: i64,
+    pub alias: String,
+    pub created_at: i64,
+}
+
+impl ArticleAlias {
+    pub fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, article_id, alias, created_at FROM article_alias ORDER BY article_id, created_at DESC",
+        )?;
+        let aliases = stmt
+            .query_map([], |row| {
+                Ok(ArticleAlias {
+                    id: row.get(0)?,
+                    article_id: row.get(1)?,
+                    alias: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(aliases)
+    }
+
+    pub fn find_article_id(alias: &str, con: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
+        con.query_row(
+            "SELECT article_id FROM article_alias WHERE alias = ?",
+            [alias],
+            |row| row.get(0),
+        )
+    }
+
+    /// remembers `alias` for `article_id`, taking it over from any other article
+    pub fn record(
+        article_id: i64,
+        alias: &str,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT OR REPLACE INTO article_alias (article_id, alias, created_at) VALUES (?, ?, ?)",
+            params![article_id, alias, chrono::offset::Local::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// forgets an old alias, e.g. because it is the current one again
+    pub fn release(alias: &str, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM article_alias WHERE alias = ?", [alias])?;
+        Ok(())
+    }
+
+    pub fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM article_alias WHERE id = ?", [&id])?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for ArticleAlias {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS article_alias (
+                id INTEGER PRIMARY KEY,
+                article_id INTEGER,
+                alias TEXT UNIQUE,
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+/// admin defined redirect from an unknown path
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RedirectRule {
+    pub id: Option<i64>,
+    /// path without query, e.g. `/old-blog/post`
+    pub source: String,
+    /// local path or absolute url
+    pub target: String,
+    /// 301 if set, 302 otherwise
+    pub permanent: bool,
+    pub created_at: i64,
+}
+
+impl RedirectRule {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(RedirectRule {
+            id: row.get(0)?,
+            source: row.get(1)?,
+            target: row.get(2)?,
+            permanent: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+
+    pub fn find_by_source(source: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, source, target, permanent, created_at FROM redirect WHERE source = ?",
+        )?;
+        stmt.query_row([source], RedirectRule::from_row)
+    }
+
+    pub fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, source, target, permanent, created_at FROM redirect ORDER BY source",
+        )?;
+        let redirects = stmt
+            .query_map([], RedirectRule::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(redirects)
+    }
+
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO redirect (source, target, permanent, created_at) VALUES (?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.source,
+            &self.target,
+            &self.permanent,
+            &self.created_at
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    pub fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM redirect WHERE id = ?", [&id])?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for RedirectRule {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS redirect (
+                id INTEGER PRIMARY KEY,
+                source TEXT UNIQUE,
+                target TEXT,
+                permanent BOOLEAN DEFAULT 1,
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
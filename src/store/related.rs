@@ -0,0 +1,323 @@
+use super::articles::Article;
+use super::series::Series;
+use super::SchemaUp;
+use rusqlite::params;
+use std::collections::HashMap;
+
+/// how many related articles are kept per article
+const CACHED: usize = 10;
+const TAG_WEIGHT: f64 = 3.0;
+const SERIES_WEIGHT: f64 = 5.0;
+/// the best text match gets this, the others proportionally less
+const TEXT_WEIGHT: f64 = 3.0;
+
+/// tags are free text, separated by commas or whitespace
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// fts5 query matching any word of `text`
+fn match_query(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(|word| format!("\"{}\"", word.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+/// rebuilds the full text index and the related articles of every article,
+/// used by `init` to fill the cache. Saves go through [refresh].
+pub fn refresh_all(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let tx = con.unchecked_transaction()?;
+    let articles = Article::find_all_ordered(Default::default(), &tx)?;
+
+    tx.execute("DELETE FROM article_fts", ())?;
+    {
+        let mut stmt =
+            tx.prepare("INSERT INTO article_fts (rowid, title, teaser) VALUES (?, ?, ?)")?;
+        for article in &articles {
+            stmt.execute(params![article.id, article.title, article.teaser])?;
+        }
+    }
+
+    tx.execute("DELETE FROM related_article", ())?;
+    for article in &articles {
+        store(article, &articles, &tx)?;
+    }
+
+    tx.commit()
+}
+
+/// updates the full text row and the related articles of one article after it
+/// was saved or deleted. Every article sharing a tag, the series or a word with it,
+/// before or after the change, is ranked again as well, and so is every article
+/// that listed it. Any other article cannot score it and keeps its list.
+pub fn refresh(article_id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let tx = con.unchecked_transaction()?;
+    let articles = Article::find_all_ordered(Default::default(), &tx)?;
+    let article = articles.iter().find(|a| a.id == Some(article_id));
+
+    let mut affected = {
+        let mut stmt = tx.prepare("SELECT article_id FROM related_article WHERE related_id = ?")?;
+        let ids = stmt
+            .query_map([&article_id], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        ids
+    };
+
+    // words it no longer has still count, the articles matching them may rank it lower now
+    let previous = {
+        let mut stmt = tx.prepare("SELECT title, teaser FROM article_fts WHERE rowid = ?")?;
+        let mut rows = stmt.query([&article_id])?;
+        match rows.next()? {
+            Some(row) => Some(format!("{} {}", row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            None => None,
+        }
+    };
+    if let Some(text) = previous {
+        affected.extend(text_matches(&text, article_id, &tx)?.into_iter().map(|(id, _)| id));
+    }
+
+    tx.execute("DELETE FROM article_fts WHERE rowid = ?", [&article_id])?;
+    if let Some(article) = article {
+        tx.execute(
+            "INSERT INTO article_fts (rowid, title, teaser) VALUES (?, ?, ?)",
+            params![article.id, article.title, article.teaser],
+        )?;
+    }
+
+    tx.execute("DELETE FROM related_article WHERE article_id = ?", [&article_id])?;
+    if let Some(article) = article {
+        affected.extend(store(article, &articles, &tx)?);
+    }
+
+    affected.sort_unstable();
+    affected.dedup();
+    for other in articles
+        .iter()
+        .filter(|a| a.id != Some(article_id) && a.id.is_some_and(|id| affected.contains(&id)))
+    {
+        tx.execute("DELETE FROM related_article WHERE article_id = ?", [&other.id])?;
+        store(other, &articles, &tx)?;
+    }
+
+    tx.commit()
+}
+
+/// ranks the other articles for `article` and caches the best ones.
+/// Returns the ids of all articles it shares anything with, cached or not.
+fn store(
+    article: &Article,
+    articles: &[Article],
+    con: &rusqlite::Connection,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    let id = article.id.ok_or(rusqlite::Error::InvalidQuery)?;
+    let scores = score(article, articles, con)?;
+    let mut stmt = con.prepare(
+        "INSERT INTO related_article (article_id, related_id, score, position) VALUES (?, ?, ?, ?)",
+    )?;
+    for (position, (related_id, score)) in scores.iter().take(CACHED).enumerate() {
+        stmt.execute(params![id, related_id, score, position as i64])?;
+    }
+    Ok(scores.into_iter().map(|(id, _)| id).collect())
+}
+
+/// articles whose title or teaser shares a word with `text`, with their bm25 rank
+fn text_matches(
+    text: &str,
+    except: i64,
+    con: &rusqlite::Connection,
+) -> Result<Vec<(i64, f64)>, rusqlite::Error> {
+    let query = match_query(text);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stmt = con.prepare(
+        "SELECT rowid, -bm25(article_fts) FROM article_fts WHERE article_fts MATCH ? AND rowid != ?",
+    )?;
+    let matches = stmt
+        .query_map(params![query, except], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(matches)
+}
+
+/// scores all other articles by shared tags, series and text similarity, best first
+fn score(
+    article: &Article,
+    articles: &[Article],
+    con: &rusqlite::Connection,
+) -> Result<Vec<(i64, f64)>, rusqlite::Error> {
+    let id = article.id.unwrap_or_default();
+    let mut scores: HashMap<i64, f64> = HashMap::new();
+
+    let tags = split_tags(&article.tags);
+    for other in articles.iter().filter(|a| a.id != article.id) {
+        let shared = split_tags(&other.tags)
+            .iter()
+            .filter(|tag| tags.contains(tag))
+            .count();
+        if shared > 0 {
+            *scores.entry(other.id.unwrap_or_default()).or_default() += shared as f64 * TAG_WEIGHT;
+        }
+    }
+
+    if let Some(series_id) = Series::find_by_article(id, con)?.and_then(|s| s.id) {
+        for other in Article::find_by_series(series_id, con)? {
+            if let Some(other_id) = other.id.filter(|other_id| *other_id != id) {
+                *scores.entry(other_id).or_default() += SERIES_WEIGHT;
+            }
+        }
+    }
+
+    let matches = text_matches(&format!("{} {}", article.title, article.teaser), id, con)?;
+    let best = matches.iter().map(|(_, rank)| *rank).fold(0.0, f64::max);
+    if best > 0.0 {
+        for (other_id, rank) in matches {
+            *scores.entry(other_id).or_default() += rank / best * TEXT_WEIGHT;
+        }
+    }
+
+    let mut scores = scores.into_iter().collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+    Ok(scores)
+}
+
+/// cached related articles, best first, without paragraphs
+pub fn find(article_id: i64, con: &rusqlite::Connection) -> Result<Vec<Article>, rusqlite::Error> {
+    let mut stmt = con.prepare(
+        "SELECT related_id FROM related_article WHERE article_id = ? ORDER BY position",
+    )?;
+    let ids = stmt
+        .query_map([&article_id], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+    Article::find_many(&ids, con)
+}
+
+pub struct Related;
+
+impl SchemaUp for Related {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS article_fts USING fts5(title, teaser);",
+            (),
+        )?;
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS related_article (
+                article_id INTEGER,
+                related_id INTEGER,
+                score REAL,
+                position INTEGER
+            );",
+            (),
+        )?;
+        con.execute(
+            "CREATE INDEX IF NOT EXISTS related_article_id ON related_article (article_id);",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::media::Media;
+    use crate::store::paragraphs::Paragraph;
+    use crate::store::Crud;
+
+    fn cached(con: &rusqlite::Connection) -> Vec<(i64, i64, i64)> {
+        let mut stmt = con
+            .prepare("SELECT article_id, related_id, position FROM related_article ORDER BY 1, 3")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn refresh_reaches_articles_outside_its_own_list() {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+
+        let insert = |title: &str, tags: &str| {
+            let mut article = Article::new(title.to_string());
+            article.tags = tags.to_string();
+            article.insert(&con).unwrap();
+            article.id.unwrap()
+        };
+        // lists only articles tagged rust, none of which can fill its ten slots with better ones
+        let loose = insert("Loose", "rust");
+        let names = [
+            "Alpha", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot", "Golf", "Hotel", "India",
+            "Juliett", "Kilo", "Lima",
+        ];
+        for name in names {
+            insert(name, "rust,bevy,ecs");
+        }
+        let changed = insert("Mike", "go");
+        refresh_all(&con).unwrap();
+        assert!(CACHED < names.len());
+
+        // now ranks the twelve far above `loose`, which still ranks it first
+        let mut article = Article::find(changed, &con).unwrap();
+        article.tags = "rust,bevy,ecs".to_string();
+        article.update(&con).unwrap();
+        refresh(changed, &con).unwrap();
+        let refreshed = cached(&con);
+        assert_eq!(find(loose, &con).unwrap()[0].id, Some(changed));
+
+        refresh_all(&con).unwrap();
+        assert_eq!(refreshed, cached(&con));
+
+        // and back, `loose` must drop it again
+        let mut article = Article::find(changed, &con).unwrap();
+        article.tags = "go".to_string();
+        article.update(&con).unwrap();
+        refresh(changed, &con).unwrap();
+        let refreshed = cached(&con);
+        refresh_all(&con).unwrap();
+        assert_eq!(refreshed, cached(&con));
+    }
+
+    #[test]
+    fn refresh_matches_full_rebuild() {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+
+        let mut ids = Vec::new();
+        for (title, tags) in [("Rust ecs", "rust,bevy"), ("Rust shaders", "rust"), ("Go servers", "go")] {
+            let mut article = Article::new(title.to_string());
+            article.tags = tags.to_string();
+            article.insert(&con).unwrap();
+            ids.push(article.id.unwrap());
+        }
+        refresh_all(&con).unwrap();
+
+        let mut article = Article::find(ids[2], &con).unwrap();
+        article.title = "Rust servers".to_string();
+        article.tags = "rust,bevy".to_string();
+        article.update(&con).unwrap();
+        refresh(ids[2], &con).unwrap();
+        let refreshed = cached(&con);
+        assert_eq!(find(ids[0], &con).unwrap()[0].id, Some(ids[2]));
+
+        refresh_all(&con).unwrap();
+        assert_eq!(refreshed, cached(&con));
+
+        Article::delete(ids[1], &con).unwrap();
+        refresh(ids[1], &con).unwrap();
+        assert!(cached(&con).iter().all(|(a, r, _)| *a != ids[1] && *r != ids[1]));
+    }
+}
@@ -0,0 +1,245 @@
+use super::articles::Article;
+use super::{Crud, SchemaUp};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// a multi-part tutorial, its articles are ordered by `series_article.position`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Series {
+    pub id: Option<i64>,
+    pub title: String,
+    pub slug: String,
+    pub description: String,
+    pub created_at: i64,
+}
+
+/// a link to a neighbouring part
+#[derive(Debug, Serialize)]
+pub struct SeriesLink {
+    pub title: String,
+    pub url: String,
+}
+
+/// "Part N of M" with previous and next links, shown on the article page
+#[derive(Debug, Serialize)]
+pub struct SeriesNav {
+    pub series: Series,
+    pub part: usize,
+    pub total: usize,
+    pub previous: Option<SeriesLink>,
+    pub next: Option<SeriesLink>,
+}
+
+impl Series {
+    pub fn new(title: String) -> Self {
+        Series {
+            slug: crate::render::slugify(&title),
+            id: None,
+            title,
+            description: String::new(),
+            created_at: chrono::offset::Local::now().timestamp(),
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Series {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            slug: row.get(2)?,
+            description: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+
+    pub fn find_by_slug(slug: &str, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, title, slug, description, created_at FROM series WHERE slug = ?",
+        )?;
+        stmt.query_row([slug], Series::from_row)
+    }
+
+    /// the series an article belongs to, if any
+    pub fn find_by_article(
+        article_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Option<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT s.id, s.title, s.slug, s.description, s.created_at
+             FROM series s JOIN series_article sa ON sa.series_id = s.id
+             WHERE sa.article_id = ?",
+        )?;
+        let mut rows = stmt.query([&article_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(Series::from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn member_ids(series_id: i64, con: &rusqlite::Connection) -> Result<Vec<i64>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT article_id FROM series_article WHERE series_id = ? ORDER BY position",
+        )?;
+        let ids = stmt
+            .query_map([&series_id], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    fn write_positions(
+        series_id: i64,
+        ids: &[i64],
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT OR REPLACE INTO series_article (series_id, article_id, position) VALUES (?, ?, ?)",
+        )?;
+        for (position, id) in ids.iter().enumerate() {
+            stmt.execute(params![series_id, id, position as i64])?;
+        }
+        Ok(())
+    }
+
+    /// puts an article into a series at `part` (1 based, `None` appends),
+    /// or takes it out of its series with `series_id = None`
+    pub fn set_membership(
+        article_id: i64,
+        series_id: Option<i64>,
+        part: Option<usize>,
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+
+        if let Some(current) = Series::find_by_article(article_id, &tx)?.and_then(|s| s.id) {
+            tx.execute(
+                "DELETE FROM series_article WHERE article_id = ?",
+                [&article_id],
+            )?;
+            Series::write_positions(current, &Series::member_ids(current, &tx)?, &tx)?;
+        }
+
+        if let Some(series_id) = series_id {
+            let mut ids = Series::member_ids(series_id, &tx)?;
+            let index = part
+                .map(|part| part.saturating_sub(1).min(ids.len()))
+                .unwrap_or(ids.len());
+            ids.insert(index, article_id);
+            Series::write_positions(series_id, &ids, &tx)?;
+        }
+
+        tx.commit()
+    }
+
+    /// part number and neighbours of an article. With `public_only`, drafts are
+    /// neither counted nor linked.
+    pub fn nav(
+        article_id: i64,
+        public_only: bool,
+        con: &rusqlite::Connection,
+    ) -> Result<Option<SeriesNav>, rusqlite::Error> {
+        let series = match Series::find_by_article(article_id, con)? {
+            Some(series) => series,
+            None => return Ok(None),
+        };
+
+        let parts = Article::find_by_series(series.id.unwrap_or_default(), con)?
+            .into_iter()
+            .filter(|a| !public_only || a.is_public())
+            .collect::<Vec<_>>();
+
+        let index = match parts.iter().position(|a| a.id == Some(article_id)) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let link = |article: &Article| SeriesLink {
+            title: article.title.clone(),
+            url: article.url(),
+        };
+
+        Ok(Some(SeriesNav {
+            part: index + 1,
+            total: parts.len(),
+            previous: index.checked_sub(1).map(|i| link(&parts[i])),
+            next: parts.get(index + 1).map(link),
+            series,
+        }))
+    }
+}
+
+impl SchemaUp for Series {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS series (
+                id INTEGER PRIMARY KEY,
+                title TEXT,
+                slug TEXT UNIQUE,
+                description TEXT DEFAULT '',
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS series_article (
+                series_id INTEGER,
+                article_id INTEGER PRIMARY KEY,
+                position INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+impl Crud for Series {
+    fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, title, slug, description, created_at FROM series WHERE id = ?",
+        )?;
+        stmt.query_row([&id], Series::from_row)
+    }
+
+    fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "SELECT id, title, slug, description, created_at FROM series ORDER BY title",
+        )?;
+        let series = stmt
+            .query_map([], Series::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(series)
+    }
+
+    fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "INSERT INTO series (title, slug, description, created_at) VALUES (?, ?, ?, ?)",
+        )?;
+        stmt.execute(params![
+            &self.title,
+            &self.slug,
+            &self.description,
+            &self.created_at
+        ])?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare(
+            "UPDATE series SET title = ?, slug = ?, description = ? WHERE id = ?",
+        )?;
+        stmt.execute(params![
+            &self.title,
+            &self.slug,
+            &self.description,
+            &self.id.ok_or(rusqlite::Error::InvalidQuery)?,
+        ])?;
+        Ok(())
+    }
+
+    /// deletes the series, its articles stay untouched
+    fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        tx.execute("DELETE FROM series_article WHERE series_id = ?", [&id])?;
+        tx.execute("DELETE FROM series WHERE id = ?", [&id])?;
+        tx.commit()
+    }
+}
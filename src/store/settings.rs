@@ -0,0 +1,142 @@
+use super::SchemaUp;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// site wide strings shown by the templates as `site.*`, editable in the admin.
+/// Stored as key/value rows, keys without a row keep their default.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Settings {
+    /// name of the site, shown in the header and the feed
+    pub site_title: String,
+    /// put in front of article and series titles
+    pub title_prefix: String,
+    pub author: String,
+    /// default meta description
+    pub description: String,
+    /// handle for twitter cards, with the `@`
+    pub twitter: String,
+    /// prefix of absolute urls in meta tags, mails, the feed and the sitemap, without the trailing slash
+    pub base_url: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            site_title: "Lommix's Blog".to_string(),
+            title_prefix: "Lommix - ".to_string(),
+            author: "Lommix".to_string(),
+            description: "Blog about game development and web development using modern technologies like rust & go".to_string(),
+            twitter: "@lommix1".to_string(),
+            base_url: "https://lommix.de".to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// absolute url of a site path, for mails, the feed and the sitemap
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// the stored settings over the defaults, unknown keys are ignored
+    pub fn load(con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut fields = match serde_json::to_value(Settings::default()) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => return Ok(Settings::default()),
+        };
+
+        let mut stmt = con.prepare("SELECT key, value FROM setting")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (key, value) in rows {
+            if let Some(field) = fields.get_mut(&key) {
+                *field = serde_json::Value::String(value);
+            }
+        }
+
+        serde_json::from_value(serde_json::Value::Object(fields))
+            .map_err(|_| rusqlite::Error::InvalidQuery)
+    }
+
+    /// writes every field, replacing the stored values
+    pub fn save(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let fields = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => return Err(rusqlite::Error::InvalidQuery),
+        };
+
+        let tx = con.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare("INSERT OR REPLACE INTO setting (key, value) VALUES (?, ?)")?;
+            for (key, value) in fields.iter() {
+                stmt.execute(params![key, value.as_str().unwrap_or_default()])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+impl SchemaUp for Settings {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS setting (
+                key TEXT PRIMARY KEY,
+                value TEXT
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Settings::up(&con).unwrap();
+        con
+    }
+
+    #[test]
+    fn missing_keys_keep_their_default() {
+        let con = db();
+        let defaults = Settings::default();
+        let empty = Settings::load(&con).unwrap();
+        assert_eq!(empty.site_title, defaults.site_title);
+        assert_eq!(empty.base_url, defaults.base_url);
+
+        con.execute(
+            "INSERT INTO setting (key, value) VALUES ('site_title', 'My Site'), ('retired', 'x')",
+            (),
+        )
+        .unwrap();
+        let settings = Settings::load(&con).unwrap();
+        assert_eq!(settings.site_title, "My Site");
+        assert_eq!(settings.author, defaults.author);
+        assert_eq!(settings.twitter, defaults.twitter);
+        assert_eq!(settings.link("/feed.xml"), format!("{}/feed.xml", defaults.base_url));
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let con = db();
+        let mut settings = Settings {
+            author: "Someone".to_string(),
+            base_url: "http://localhost:8080".to_string(),
+            ..Default::default()
+        };
+        settings.save(&con).unwrap();
+        settings.description = "Replaced".to_string();
+        settings.save(&con).unwrap();
+
+        let loaded = Settings::load(&con).unwrap();
+        assert_eq!(loaded.author, "Someone");
+        assert_eq!(loaded.description, "Replaced");
+        assert_eq!(loaded.link("/article/1"), "http://localhost:8080/article/1");
+        let rows: i64 = con.query_row("SELECT COUNT(*) FROM setting", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 6);
+    }
+}
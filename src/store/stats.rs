@@ -7,43 +7,56 @@ use rusqlite::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::{Crud, SchemaUp};
+use super::{add_column, Crud, SchemaUp};
 
+/// views per article or page id, stored as json
 #[derive(Debug, Default, Deserialize, Serialize)]
-pub struct ArticleViews {
+pub struct ViewCounts {
     data: HashMap<i64, i64>,
 }
 
-impl ArticleViews {
-    pub fn add(&mut self, article_id: i64) {
-        *self.data.entry(article_id).or_insert(0) += 1
+impl ViewCounts {
+    pub fn add(&mut self, id: i64) {
+        *self.data.entry(id).or_insert(0) += 1
     }
 }
 
-impl ToSql for ArticleViews {
+impl ToSql for ViewCounts {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
         let string = serde_json::to_string(self).map_err(|err| rusqlite::Error::InvalidQuery)?;
         Ok(string.into())
     }
 }
 
-impl FromSql for ArticleViews {
+impl FromSql for ViewCounts {
     fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
-        serde_json::from_str::<ArticleViews>(value.as_str()?)
+        // days counted before the column existed
+        if let rusqlite::types::ValueRef::Null = value {
+            return Ok(ViewCounts::default());
+        }
+        serde_json::from_str::<ViewCounts>(value.as_str()?)
             .map_err(|er| rusqlite::types::FromSqlError::InvalidType)
     }
 }
 
+const STATS_COLUMNS: &str = "date, article_views, page_views";
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Stats {
     pub date: i64,
-    pub home_views: i64,
-    pub donate_views: i64,
-    pub about_views: i64,
-    pub article_views: ArticleViews,
+    pub article_views: ViewCounts,
+    pub page_views: ViewCounts,
 }
 
 impl Stats {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Stats {
+            date: row.get(0)?,
+            article_views: row.get(1)?,
+            page_views: row.get(2)?,
+        })
+    }
+
     pub fn find_or_create_today(con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
         let now = chrono::offset::Local::now()
             .date_naive()
@@ -66,20 +79,47 @@ impl Stats {
         days: i64,
         con: &rusqlite::Connection,
     ) -> Result<Vec<Self>, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT * FROM stats ORDER BY date DESC LIMIT ?")?;
-        let mut rows = stmt.query([days])?;
-        let mut result = Vec::new();
-
-        while let Some(row) = rows.next()? {
-            result.push(Stats {
-                date: row.get(0)?,
-                home_views: row.get(1)?,
-                about_views: row.get(2)?,
-                donate_views: row.get(3)?,
-                article_views: row.get(4)?,
-            });
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM stats ORDER BY date DESC LIMIT ?",
+            STATS_COLUMNS
+        ))?;
+        let stats = stmt
+            .query_map([days], Stats::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stats)
+    }
+
+    /// moves the counters of the old hard coded pages into `page_views`.
+    /// `columns` pairs a legacy column with the id of the page that replaced it,
+    /// the column is zeroed afterwards. Databases without the columns are left alone.
+    pub fn migrate_page_views(
+        columns: &[(&str, Option<i64>)],
+        con: &rusqlite::Connection,
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = con.prepare("PRAGMA table_info(stats)")?;
+        let existing = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (column, page_id) in columns {
+            let page_id = match page_id {
+                Some(id) if existing.iter().any(|name| name == column) => *id,
+                _ => continue,
+            };
+            let mut stmt = con.prepare(&format!(
+                "SELECT {}, {} FROM stats WHERE {} > 0",
+                STATS_COLUMNS, column, column
+            ))?;
+            let days = stmt
+                .query_map([], |row| Ok((Stats::from_row(row)?, row.get::<_, i64>(3)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (mut stats, views) in days {
+                *stats.page_views.data.entry(page_id).or_insert(0) += views;
+                stats.update(con)?;
+            }
+            con.execute(&format!("UPDATE stats SET {} = 0", column), ())?;
         }
-        Ok(result)
+        Ok(())
     }
 }
 
@@ -88,75 +128,43 @@ impl SchemaUp for Stats {
         con.execute(
             "CREATE TABLE IF NOT EXISTS stats (
             date INTEGER PRIMARY KEY,
-            home_views INTEGER DEFAULT 0,
-            about_views INTEGER DEFAULT 0,
-            donate_views INTEGER DEFAULT 0,
             article_views TEXT
             );",
             (),
         )?;
+        add_column(con, "stats", "page_views", "TEXT")?;
         Ok(())
     }
 }
 
 impl Crud for Stats {
     fn find(date: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT * FROM stats WHERE date = ?")?;
-        let mut rows = stmt.query([&date])?;
-        if let Some(row) = rows.next()? {
-            return Ok(Stats {
-                date: row.get(0)?,
-                home_views: row.get(1)?,
-                about_views: row.get(2)?,
-                donate_views: row.get(3)?,
-                article_views: row.get(4)?,
-            });
-        }
-        Err(rusqlite::Error::QueryReturnedNoRows)
+        let mut stmt = con.prepare(&format!("SELECT {} FROM stats WHERE date = ?", STATS_COLUMNS))?;
+        stmt.query_row([&date], Stats::from_row)
     }
 
     fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
-        let mut stmt = con.prepare("SELECT * FROM stats")?;
-        let mut rows = stmt.query([])?;
-        let mut result = Vec::new();
-        while let Some(row) = rows.next()? {
-            result.push(Stats {
-                date: row.get(0)?,
-                home_views: row.get(1)?,
-                about_views: row.get(2)?,
-                donate_views: row.get(3)?,
-                article_views: row.get(4)?,
-            })
-        }
-        Ok(result)
+        let mut stmt = con.prepare(&format!("SELECT {} FROM stats", STATS_COLUMNS))?;
+        let stats = stmt
+            .query_map([], Stats::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stats)
     }
 
     fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "INSERT INTO stats (date, home_views, about_views, donate_views, article_views) VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO stats (date, article_views, page_views) VALUES (?, ?, ?)",
         )?;
-        stmt.execute(params![
-            &self.date,
-            &self.home_views,
-            &self.about_views,
-            &self.donate_views,
-            &self.article_views,
-        ])?;
+        stmt.execute(params![&self.date, &self.article_views, &self.page_views])?;
 
         Ok(())
     }
 
     fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
         let mut stmt = con.prepare(
-            "UPDATE stats SET home_views = ?, about_views = ?, donate_views = ?, article_views = ? WHERE date = ?",
+            "UPDATE stats SET article_views = ?, page_views = ? WHERE date = ?",
         )?;
-        stmt.execute(params![
-            &self.home_views,
-            &self.about_views,
-            &self.donate_views,
-            &self.article_views,
-            &self.date,
-        ])?;
+        stmt.execute(params![&self.article_views, &self.page_views, &self.date])?;
         Ok(())
     }
 
@@ -0,0 +1,87 @@
+use super::SchemaUp;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+const SYNC_COLUMNS: &str = "article_id, path, hash, synced_at";
+
+/// what both sides agreed on at the last sync of an article, see [crate::sync]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    pub article_id: i64,
+    /// directory of the article, relative to the sync tree
+    pub path: String,
+    /// sha256 of the markdown file as it was written or applied
+    pub hash: String,
+    pub synced_at: i64,
+}
+
+impl SyncState {
+    pub fn new(article_id: i64, path: String, hash: String) -> Self {
+        SyncState {
+            article_id,
+            path,
+            hash,
+            synced_at: chrono::offset::Local::now().timestamp(),
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(SyncState {
+            article_id: row.get(0)?,
+            path: row.get(1)?,
+            hash: row.get(2)?,
+            synced_at: row.get(3)?,
+        })
+    }
+
+    pub fn find_by_article(
+        article_id: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM sync_state WHERE article_id = ?",
+            SYNC_COLUMNS
+        ))?;
+        stmt.query_row([&article_id], SyncState::from_row)
+    }
+
+    pub fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM sync_state ORDER BY path ASC",
+            SYNC_COLUMNS
+        ))?;
+        let states = stmt
+            .query_map([], SyncState::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(states)
+    }
+
+    /// records the state, replacing the previous one of the article
+    pub fn save(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT OR REPLACE INTO sync_state (article_id, path, hash, synced_at) VALUES (?, ?, ?, ?)",
+            params![&self.article_id, &self.path, &self.hash, &self.synced_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete(article_id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute("DELETE FROM sync_state WHERE article_id = ?", [&article_id])?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for SyncState {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS sync_state (
+                article_id INTEGER PRIMARY KEY,
+                path TEXT,
+                hash TEXT,
+                synced_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
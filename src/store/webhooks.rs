@@ -0,0 +1,390 @@
+use super::{Crud, SchemaUp};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+const WEBHOOK_COLUMNS: &str = "id, url, secret, events, active, created_at";
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, payload, status, attempts, next_attempt_at,
+    response_code, error, created_at, delivered_at";
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WebhookEvent {
+    #[serde(rename = "article.published")]
+    ArticlePublished,
+    #[serde(rename = "article.updated")]
+    ArticleUpdated,
+    #[serde(rename = "article.deleted")]
+    ArticleDeleted,
+    #[serde(rename = "contact.received")]
+    ContactReceived,
+    #[serde(rename = "comment.posted")]
+    CommentPosted,
+    /// sent by hand to test a hook, every hook receives it
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+impl WebhookEvent {
+    /// events a hook can subscribe to
+    pub const ALL: [WebhookEvent; 5] = [
+        WebhookEvent::ArticlePublished,
+        WebhookEvent::ArticleUpdated,
+        WebhookEvent::ArticleDeleted,
+        WebhookEvent::ContactReceived,
+        WebhookEvent::CommentPosted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ArticlePublished => "article.published",
+            WebhookEvent::ArticleUpdated => "article.updated",
+            WebhookEvent::ArticleDeleted => "article.deleted",
+            WebhookEvent::ContactReceived => "contact.received",
+            WebhookEvent::CommentPosted => "comment.posted",
+            WebhookEvent::Ping => "ping",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        WebhookEvent::ALL
+            .iter()
+            .chain([WebhookEvent::Ping].iter())
+            .find(|event| event.as_str() == name)
+            .copied()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: Option<i64>,
+    pub url: String,
+    /// signs the payloads, see [crate::webhooks]
+    pub secret: String,
+    /// comma separated event names
+    pub events: String,
+    pub active: bool,
+    pub created_at: i64,
+}
+
+impl Webhook {
+    pub fn new(url: String, secret: String, events: &[WebhookEvent]) -> Self {
+        Webhook {
+            id: None,
+            url,
+            secret,
+            events: events
+                .iter()
+                .map(|event| event.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            active: true,
+            created_at: chrono::offset::Local::now().timestamp(),
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Webhook {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            secret: row.get(2)?,
+            events: row.get(3)?,
+            active: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+
+    pub fn subscribes(&self, event: WebhookEvent) -> bool {
+        event == WebhookEvent::Ping || self.events.split(',').any(|name| name == event.as_str())
+    }
+
+    /// active hooks listening for `event`
+    pub fn find_by_event(
+        event: WebhookEvent,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        Ok(Webhook::find_all(con)?
+            .into_iter()
+            .filter(|hook| hook.active && hook.subscribes(event))
+            .collect())
+    }
+
+    pub fn set_active(id: i64, active: bool, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "UPDATE webhook SET active = ? WHERE id = ?",
+            params![active, id],
+        )?;
+        Ok(())
+    }
+}
+
+impl SchemaUp for Webhook {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS webhook (
+                id INTEGER PRIMARY KEY,
+                url TEXT,
+                secret TEXT,
+                events TEXT,
+                active BOOLEAN DEFAULT 1,
+                created_at INTEGER
+            );",
+            (),
+        )?;
+        Ok(())
+    }
+}
+
+impl Crud for Webhook {
+    fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM webhook WHERE id = ?",
+            WEBHOOK_COLUMNS
+        ))?;
+        stmt.query_row([&id], Webhook::from_row)
+    }
+
+    fn find_all(con: &rusqlite::Connection) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM webhook ORDER BY created_at ASC",
+            WEBHOOK_COLUMNS
+        ))?;
+        let hooks = stmt
+            .query_map([], Webhook::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hooks)
+    }
+
+    fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT INTO webhook (url, secret, events, active, created_at) VALUES (?, ?, ?, ?, ?)",
+            params![
+                &self.url,
+                &self.secret,
+                &self.events,
+                &self.active,
+                &self.created_at
+            ],
+        )?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "UPDATE webhook SET url = ?, secret = ?, events = ?, active = ? WHERE id = ?",
+            params![
+                &self.url,
+                &self.secret,
+                &self.events,
+                &self.active,
+                &self.id.ok_or(rusqlite::Error::InvalidQuery)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// removes the hook with its queue and delivery log
+    fn delete(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let tx = con.unchecked_transaction()?;
+        tx.execute("DELETE FROM webhook_delivery WHERE webhook_id = ?", [&id])?;
+        tx.execute("DELETE FROM webhook WHERE id = ?", [&id])?;
+        tx.commit()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// queued, or waiting for a retry
+    Pending,
+    Delivered,
+    /// gave up after the last retry
+    Failed,
+}
+
+impl rusqlite::types::FromSql for DeliveryStatus {
+    fn column_result(value: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Ok(DeliveryStatus::Pending),
+        }
+    }
+}
+
+impl rusqlite::types::ToSql for DeliveryStatus {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+        .into())
+    }
+}
+
+/// one event for one hook. Doubles as queue entry and log line.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Option<i64>,
+    pub webhook_id: i64,
+    pub event: String,
+    /// the exact json body, retries send the same bytes
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: Option<i64>,
+    pub response_code: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+}
+
+impl WebhookDelivery {
+    pub fn new(webhook_id: i64, event: WebhookEvent, payload: String) -> Self {
+        let now = chrono::offset::Local::now().timestamp();
+        WebhookDelivery {
+            id: None,
+            webhook_id,
+            event: event.as_str().to_string(),
+            payload,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Some(now),
+            response_code: None,
+            error: None,
+            created_at: now,
+            delivered_at: None,
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(WebhookDelivery {
+            id: row.get(0)?,
+            webhook_id: row.get(1)?,
+            event: row.get(2)?,
+            payload: row.get(3)?,
+            status: row.get(4)?,
+            attempts: row.get(5)?,
+            next_attempt_at: row.get(6)?,
+            response_code: row.get(7)?,
+            error: row.get(8)?,
+            created_at: row.get(9)?,
+            delivered_at: row.get(10)?,
+        })
+    }
+
+    pub fn find(id: i64, con: &rusqlite::Connection) -> Result<Self, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM webhook_delivery WHERE id = ?",
+            DELIVERY_COLUMNS
+        ))?;
+        stmt.query_row([&id], WebhookDelivery::from_row)
+    }
+
+    /// pending deliveries whose next attempt is due, oldest first
+    pub fn find_due(
+        now: i64,
+        limit: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM webhook_delivery WHERE status = 'pending' AND next_attempt_at <= ?
+             ORDER BY next_attempt_at ASC, id ASC LIMIT ?",
+            DELIVERY_COLUMNS
+        ))?;
+        let deliveries = stmt
+            .query_map(params![now, limit], WebhookDelivery::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
+
+    /// the delivery log of a hook, newest first
+    pub fn find_by_webhook(
+        webhook_id: i64,
+        limit: i64,
+        con: &rusqlite::Connection,
+    ) -> Result<Vec<Self>, rusqlite::Error> {
+        let mut stmt = con.prepare(&format!(
+            "SELECT {} FROM webhook_delivery WHERE webhook_id = ? ORDER BY created_at DESC, id DESC LIMIT ?",
+            DELIVERY_COLUMNS
+        ))?;
+        let deliveries = stmt
+            .query_map(params![webhook_id, limit], WebhookDelivery::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
+
+    pub fn insert(&mut self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "INSERT INTO webhook_delivery (webhook_id, event, payload, status, attempts, next_attempt_at, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                &self.webhook_id,
+                &self.event,
+                &self.payload,
+                &self.status,
+                &self.attempts,
+                &self.next_attempt_at,
+                &self.created_at
+            ],
+        )?;
+        self.id = Some(con.last_insert_rowid());
+        Ok(())
+    }
+
+    /// stores the outcome of an attempt
+    pub fn update(&self, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "UPDATE webhook_delivery SET status = ?, attempts = ?, next_attempt_at = ?, response_code = ?,
+             error = ?, delivered_at = ? WHERE id = ?",
+            params![
+                &self.status,
+                &self.attempts,
+                &self.next_attempt_at,
+                &self.response_code,
+                &self.error,
+                &self.delivered_at,
+                &self.id.ok_or(rusqlite::Error::InvalidQuery)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// queues a delivery again right away, with a fresh set of retries
+    pub fn retry(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        let updated = con.execute(
+            "UPDATE webhook_delivery SET status = 'pending', attempts = 0, next_attempt_at = ? WHERE id = ?",
+            params![chrono::offset::Local::now().timestamp(), id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+}
+
+impl SchemaUp for WebhookDelivery {
+    fn up(con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+        con.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_delivery (
+                id INTEGER PRIMARY KEY,
+                webhook_id INTEGER,
+                event TEXT,
+                payload TEXT,
+                status TEXT DEFAULT 'pending',
+                attempts INTEGER DEFAULT 0,
+                next_attempt_at INTEGER,
+                response_code INTEGER,
+                error TEXT,
+                created_at INTEGER,
+                delivered_at INTEGER
+            );",
+            (),
+        )?;
+        con.execute(
+            "CREATE INDEX IF NOT EXISTS webhook_delivery_due ON webhook_delivery (status, next_attempt_at);",
+            (),
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,679 @@
+use crate::export::{self, ExportError, ARTICLE_FILE};
+use crate::import::{self, Split};
+use crate::store::articles::{Article, ArticleOrder};
+use crate::store::sync::SyncState;
+use crate::store::Crud;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// git working tree the articles are mirrored to, empty disables the sync
+const SYNC_DIR: &str = "SYNC_DIR";
+/// `true` pushes every sync commit to the tracked remote
+const SYNC_PUSH: &str = "SYNC_PUSH";
+/// deepest heading that starts a paragraph in files without paragraph markers
+const HEADING_LEVEL: usize = 2;
+
+/// one writer in the tree at a time, git fails on a locked index
+static TREE: Mutex<()> = Mutex::new(());
+
+#[derive(Debug)]
+pub enum SyncError {
+    /// `SYNC_DIR` is not set
+    Disabled,
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+    Export(ExportError),
+    Git(String),
+}
+
+impl std::fmt::Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncError::Disabled => write!(f, "{} is not set", SYNC_DIR),
+            SyncError::Io(err) => write!(f, "io: {}", err),
+            SyncError::Db(err) => write!(f, "database: {}", err),
+            SyncError::Export(err) => write!(f, "export: {}", err),
+            SyncError::Git(err) => write!(f, "git: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for SyncError {
+    fn from(err: std::io::Error) -> Self {
+        SyncError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for SyncError {
+    fn from(err: rusqlite::Error) -> Self {
+        SyncError::Db(err)
+    }
+}
+
+impl From<ExportError> for SyncError {
+    fn from(err: ExportError) -> Self {
+        SyncError::Export(err)
+    }
+}
+
+/// which side wins when an article changed in the database and in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Side {
+    Tree,
+    Db,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// articles written from the tree into the database
+    pub imported: usize,
+    /// articles written from the database into the tree
+    pub exported: usize,
+    /// articles trashed because their directory was removed
+    pub removed: usize,
+    /// ids of the imported and removed articles
+    pub changed: Vec<i64>,
+    /// files that could not be imported
+    pub failed: usize,
+    /// directories of articles changed on both sides, left untouched
+    pub conflicts: Vec<String>,
+}
+
+pub fn tree() -> Option<PathBuf> {
+    crate::util::env(SYNC_DIR).map(PathBuf::from)
+}
+
+fn push_enabled() -> bool {
+    std::env::var(SYNC_PUSH).is_ok_and(|push| push == "true" || push == "1")
+}
+
+fn hash(text: &str) -> String {
+    crate::util::hex(&Sha256::digest(text.as_bytes()))
+}
+
+fn git(tree: &Path, args: &[&str]) -> Result<String, SyncError> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(tree)
+        .args(args)
+        .output()?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(SyncError::Git(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// runs a git command that creates commits. Without a configured
+/// git identity they are made as `MAIL_FROM`.
+fn git_commit(tree: &Path, args: &[&str]) -> Result<String, SyncError> {
+    let email = match git(tree, &["config", "user.email"]) {
+        Ok(_) => return git(tree, args),
+        Err(_) => std::env::var("MAIL_FROM").unwrap_or("blog@localhost".to_string()),
+    };
+    let email = format!("user.email={}", email);
+    let mut with_identity = vec!["-c", "user.name=blog", "-c", &email];
+    with_identity.extend(args);
+    git(tree, &with_identity)
+}
+
+/// commits everything in the tree, if anything changed
+fn commit(tree: &Path, message: &str) -> Result<bool, SyncError> {
+    git(tree, &["add", "-A"])?;
+    if git(tree, &["status", "--porcelain"])?.trim().is_empty() {
+        return Ok(false);
+    }
+    git_commit(tree, &["commit", "-q", "-m", message])?;
+    Ok(true)
+}
+
+fn push(tree: &Path) -> Result<(), SyncError> {
+    if push_enabled() {
+        git(tree, &["push", "-q"])?;
+    }
+    Ok(())
+}
+
+/// the article's directory name inside the tree
+fn path_of(article: &Article, tree: &Path) -> String {
+    export::article_dir(article, tree)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn find_article(id: i64, con: &rusqlite::Connection) -> Result<Option<Article>, SyncError> {
+    match Article::find(id, con) {
+        Ok(article) => Ok(Some(article)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn find_state(article_id: i64, con: &rusqlite::Connection) -> Result<Option<SyncState>, SyncError> {
+    match SyncState::find_by_article(article_id, con) {
+        Ok(state) => Ok(Some(state)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// writes the article into the tree, or removes it if it is gone from the database.
+/// Returns the commit message, `None` if there was nothing to do.
+fn write(
+    article_id: i64,
+    tree: &Path,
+    con: &rusqlite::Connection,
+) -> Result<Option<String>, SyncError> {
+    let state = find_state(article_id, con)?;
+    match find_article(article_id, con)? {
+        Some(article) => {
+            let path = path_of(&article, tree);
+            // the alias changed, the old directory goes
+            if let Some(state) = state.filter(|state| state.path != path) {
+                let old = tree.join(&state.path);
+                if old.is_dir() {
+                    std::fs::remove_dir_all(old)?;
+                }
+            }
+            let file = export::write_article(&article, tree)?;
+            let text = std::fs::read_to_string(file)?;
+            SyncState::new(article_id, path.clone(), hash(&text)).save(con)?;
+            Ok(Some(format!("Update {}", path)))
+        }
+        None => {
+            let Some(state) = state else {
+                return Ok(None);
+            };
+            let dir = tree.join(&state.path);
+            if dir.is_dir() {
+                std::fs::remove_dir_all(dir)?;
+            }
+            SyncState::delete(article_id, con)?;
+            Ok(Some(format!("Remove {}", state.path)))
+        }
+    }
+}
+
+/// true if the article's file in the tree differs from the last sync
+fn tree_changed(
+    article_id: i64,
+    tree: &Path,
+    con: &rusqlite::Connection,
+) -> Result<bool, SyncError> {
+    let Some(state) = find_state(article_id, con)? else {
+        return Ok(false);
+    };
+    let text = std::fs::read_to_string(tree.join(&state.path).join(ARTICLE_FILE)).ok();
+    Ok(text.map(|text| hash(&text)) != Some(state.hash))
+}
+
+/// mirrors a saved article into the tree and commits it. An article edited in the tree
+/// since the last sync is left alone, `sync pull` resolves it.
+pub fn mirror_article(article_id: i64, con: &rusqlite::Connection) -> Result<(), SyncError> {
+    match tree() {
+        Some(tree) => mirror_article_into(article_id, &tree, con),
+        None => Ok(()),
+    }
+}
+
+fn mirror_article_into(
+    article_id: i64,
+    tree: &Path,
+    con: &rusqlite::Connection,
+) -> Result<(), SyncError> {
+    let _lock = TREE.lock().unwrap_or_else(|err| err.into_inner());
+    if tree_changed(article_id, tree, con)? {
+        tracing::warn!(
+            "sync: article {} changed in the tree and the database, run sync pull",
+            article_id
+        );
+        return Ok(());
+    }
+    if let Some(message) = write(article_id, tree, con)? {
+        if commit(tree, &message)? {
+            push(tree)?;
+        }
+    }
+    Ok(())
+}
+
+/// mirrors in the background with its own connection, a no-op without `SYNC_DIR`
+pub fn mirror(article_id: i64, db_path: String) {
+    if tree().is_none() {
+        return;
+    }
+    tokio::task::spawn_blocking(move || {
+        let result = crate::store::open(&db_path)
+            .map_err(SyncError::from)
+            .and_then(|con| mirror_article(article_id, &con));
+        if let Err(err) = result {
+            tracing::error!("sync: failed to mirror article {}: {}", article_id, err);
+        }
+    });
+}
+
+/// turns `SYNC_DIR` into a git repository if it is none and writes every article into it.
+/// Existing files of the articles are overwritten, use `sync pull` to keep them.
+pub fn init(con: &rusqlite::Connection) -> Result<usize, SyncError> {
+    init_tree(&tree().ok_or(SyncError::Disabled)?, con)
+}
+
+fn init_tree(tree: &Path, con: &rusqlite::Connection) -> Result<usize, SyncError> {
+    let _lock = TREE.lock().unwrap_or_else(|err| err.into_inner());
+    std::fs::create_dir_all(tree)?;
+    if !tree.join(".git").exists() {
+        git(tree, &["init", "-q"])?;
+    }
+
+    let articles = Article::find_all_ordered(ArticleOrder::Created, con)?;
+    for article in articles.iter() {
+        write(article.id.unwrap_or_default(), tree, con)?;
+    }
+    if commit(tree, "Mirror articles")? {
+        push(tree)?;
+    }
+    Ok(articles.len())
+}
+
+/// imports a file of the tree, failures are logged and counted
+fn apply(
+    file: &Path,
+    target: Option<i64>,
+    report: &mut SyncReport,
+    con: &rusqlite::Connection,
+) -> Option<Article> {
+    match import::import_into(file, target, Split::Auto, HEADING_LEVEL, con) {
+        Ok((article, _)) => {
+            tracing::info!("sync: imported {} from {}", article.url(), file.display());
+            Some(article)
+        }
+        Err(err) => {
+            tracing::error!("sync: skipped {}: {}", file.display(), err);
+            report.failed += 1;
+            None
+        }
+    }
+}
+
+/// finishes a merge by taking the conflicting files from one side,
+/// the local tree holds what the database has
+fn resolve(tree: &Path, files: &[&str], side: Side) -> Result<(), SyncError> {
+    let version = match side {
+        Side::Tree => "--theirs",
+        Side::Db => "--ours",
+    };
+    for file in files {
+        // fails if the side deleted the file
+        match git(tree, &["checkout", version, "--", file]) {
+            Ok(_) => git(tree, &["add", "--", file])?,
+            Err(_) => git(tree, &["rm", "-q", "--", file])?,
+        };
+    }
+    git_commit(tree, &["commit", "-q", "--no-edit"])?;
+    Ok(())
+}
+
+/// pulls the tree's remote, then brings both sides together. An article changed only in the
+/// tree is imported, one changed only in the database is written out. Articles changed on
+/// both sides are reported as conflicts and left alone unless `prefer` picks a side,
+/// a pull with conflicts is not merged at all then.
+pub fn pull(con: &rusqlite::Connection, prefer: Option<Side>) -> Result<SyncReport, SyncError> {
+    pull_tree(&tree().ok_or(SyncError::Disabled)?, con, prefer)
+}
+
+fn pull_tree(
+    tree: &Path,
+    con: &rusqlite::Connection,
+    prefer: Option<Side>,
+) -> Result<SyncReport, SyncError> {
+    let _lock = TREE.lock().unwrap_or_else(|err| err.into_inner());
+
+    // hand edits in the tree take part in the merge
+    commit(tree, "Local changes")?;
+    let mut report = SyncReport::default();
+    if !git(tree, &["remote"])?.trim().is_empty() {
+        if let Err(err) = git_commit(tree, &["pull", "-q", "--no-rebase", "--no-edit"]) {
+            let files = git(tree, &["diff", "--name-only", "--diff-filter=U"])?;
+            let files = files.lines().collect::<Vec<_>>();
+            if files.is_empty() {
+                git(tree, &["merge", "--abort"]).ok();
+                return Err(err);
+            }
+            match prefer {
+                Some(side) => resolve(tree, &files, side)?,
+                // the remote is merged again by the next pull
+                None => {
+                    git(tree, &["merge", "--abort"])?;
+                    for file in files {
+                        let dir = file.split('/').next().unwrap_or(file).to_string();
+                        if !report.conflicts.contains(&dir) {
+                            report.conflicts.push(dir);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut tracked = HashSet::new();
+    for state in SyncState::find_all(con)? {
+        tracked.insert(state.path.clone());
+        let file = tree.join(&state.path).join(ARTICLE_FILE);
+        let tree_text = std::fs::read_to_string(&file).ok();
+        let article = find_article(state.article_id, con)?;
+        let db_text = article.as_ref().map(export::markdown).transpose()?;
+
+        let tree_changed = tree_text.as_deref().map(hash) != Some(state.hash.clone());
+        let db_changed = db_text.as_deref().map(hash) != Some(state.hash.clone());
+        let side = match (tree_changed, db_changed) {
+            (false, false) => continue,
+            (false, true) => Side::Db,
+            (true, false) => Side::Tree,
+            // the same edit on both sides
+            (true, true) if tree_text == db_text => Side::Db,
+            (true, true) => match prefer {
+                Some(side) => side,
+                None => {
+                    report.conflicts.push(state.path.clone());
+                    continue;
+                }
+            },
+        };
+
+        match (side, tree_text) {
+            (Side::Db, _) => {
+                write(state.article_id, tree, con)?;
+                report.exported += 1;
+            }
+            (Side::Tree, Some(_)) => {
+                // an article trashed in the database comes back as a new one
+                let target = article.as_ref().map(|_| state.article_id);
+                let Some(imported) = apply(&file, target, &mut report, con) else {
+                    continue;
+                };
+                let id = imported.id.unwrap_or_default();
+                if id != state.article_id {
+                    SyncState::delete(state.article_id, con)?;
+                }
+                write(id, tree, con)?;
+                report.imported += 1;
+                report.changed.push(id);
+            }
+            (Side::Tree, None) => {
+                if article.is_some() {
+                    Article::delete(state.article_id, con)?;
+                }
+                SyncState::delete(state.article_id, con)?;
+                report.removed += 1;
+                report.changed.push(state.article_id);
+            }
+        }
+    }
+
+    // directories added to the tree
+    let mut dirs = std::fs::read_dir(tree)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|dir| dir.join(ARTICLE_FILE).is_file())
+        .filter_map(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .filter(|name| !name.starts_with('.') && !tracked.contains(name))
+        .collect::<Vec<_>>();
+    dirs.sort();
+    for name in dirs {
+        let file = tree.join(&name).join(ARTICLE_FILE);
+        let text = std::fs::read_to_string(&file)?;
+        let front = match import::parse_front_matter(&text) {
+            Ok((front, _)) => front,
+            Err(err) => {
+                tracing::error!("sync: skipped {}: {}", file.display(), err);
+                report.failed += 1;
+                continue;
+            }
+        };
+        let alias = Article::alias_slug(
+            front
+                .alias
+                .as_deref()
+                .or(front.title.as_deref())
+                .unwrap_or(&name),
+        );
+
+        // an article that exists but was never synced
+        let existing = match Article::find_by_alias(&alias, con) {
+            Ok(article) => Some(article),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(article) = existing.as_ref() {
+            let id = article.id.unwrap_or_default();
+            if export::markdown(article)? == text {
+                write(id, tree, con)?;
+                continue;
+            }
+            match prefer {
+                Some(Side::Db) => {
+                    write(id, tree, con)?;
+                    report.exported += 1;
+                    continue;
+                }
+                Some(Side::Tree) => (),
+                None => {
+                    report.conflicts.push(name);
+                    continue;
+                }
+            }
+        }
+
+        let Some(imported) = apply(&file, None, &mut report, con) else {
+            continue;
+        };
+        // the directory is renamed after the alias
+        if path_of(&imported, tree) != name {
+            std::fs::remove_dir_all(tree.join(&name))?;
+        }
+        write(imported.id.unwrap_or_default(), tree, con)?;
+        report.imported += 1;
+        report.changed.push(imported.id.unwrap_or_default());
+    }
+
+    // articles created while the sync was off
+    for article in Article::find_all_ordered(ArticleOrder::Created, con)? {
+        let id = article.id.unwrap_or_default();
+        if find_state(id, con)?.is_none() && !report.conflicts.contains(&path_of(&article, tree)) {
+            write(id, tree, con)?;
+            report.exported += 1;
+        }
+    }
+
+    for id in &report.changed {
+        if let Err(err) = crate::store::related::refresh(*id, con) {
+            tracing::error!("sync: failed to compute related articles: {}", err);
+        }
+    }
+    commit(tree, "Sync articles")?;
+    // the remote is ahead until the conflicts are resolved
+    if report.conflicts.is_empty() {
+        push(tree)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::media::{Media, MediaVariant};
+    use crate::store::paragraphs::Paragraph;
+    use crate::store::redirects::ArticleAlias;
+    use crate::store::related::Related;
+    use crate::store::series::Series;
+    use crate::store::SchemaUp;
+
+    /// a tree with the article `First` mirrored into it, `None` without git
+    fn setup() -> Option<(rusqlite::Connection, PathBuf, i64)> {
+        if std::process::Command::new("git").arg("--version").output().is_err() {
+            return None;
+        }
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        MediaVariant::up(&con).unwrap();
+        ArticleAlias::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+        SyncState::up(&con).unwrap();
+
+        let mut article = Article::new("First".to_string());
+        article.alias = "first".to_string();
+        article.insert(&con).unwrap();
+
+        let tree = PathBuf::from(format!("target/sync-{:x}", rand::random::<u64>()));
+        assert_eq!(init_tree(&tree, &con).unwrap(), 1);
+        Some((con, tree, article.id.unwrap()))
+    }
+
+    fn file(tree: &Path) -> PathBuf {
+        tree.join("first").join(ARTICLE_FILE)
+    }
+
+    fn edit_tree(tree: &Path, title: &str) {
+        let text = std::fs::read_to_string(file(tree)).unwrap();
+        std::fs::write(file(tree), text.replace("title: First", &format!("title: {}", title))).unwrap();
+    }
+
+    fn edit_db(id: i64, title: &str, con: &rusqlite::Connection) {
+        let mut article = Article::find(id, con).unwrap();
+        article.title = title.to_string();
+        article.update(con).unwrap();
+    }
+
+    fn title(id: i64, con: &rusqlite::Connection) -> String {
+        Article::find(id, con).unwrap().title
+    }
+
+    fn tree_title(tree: &Path) -> String {
+        let text = std::fs::read_to_string(file(tree)).unwrap();
+        import::parse_front_matter(&text).unwrap().0.title.unwrap()
+    }
+
+    /// nothing is left to commit after a pull
+    fn assert_clean(tree: &Path) {
+        assert_eq!(git(tree, &["status", "--porcelain"]).unwrap(), "");
+    }
+
+    #[test]
+    fn imports_changes_made_in_the_tree() {
+        let Some((con, tree, id)) = setup() else {
+            return;
+        };
+        edit_tree(&tree, "Edited in the tree");
+
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!((report.imported, report.exported), (1, 0));
+        assert_eq!(report.changed, vec![id]);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(title(id, &con), "Edited in the tree");
+        assert_clean(&tree);
+
+        // nothing changed since
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!((report.imported, report.exported), (0, 0));
+
+        std::fs::remove_dir_all(&tree).unwrap();
+    }
+
+    #[test]
+    fn exports_changes_made_in_the_database() {
+        let Some((con, tree, id)) = setup() else {
+            return;
+        };
+        edit_db(id, "Edited in the database", &con);
+
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!((report.imported, report.exported), (0, 1));
+        assert!(report.conflicts.is_empty());
+        assert_eq!(tree_title(&tree), "Edited in the database");
+        assert_clean(&tree);
+
+        std::fs::remove_dir_all(&tree).unwrap();
+    }
+
+    #[test]
+    fn the_same_edit_on_both_sides_is_no_conflict() {
+        let Some((con, tree, id)) = setup() else {
+            return;
+        };
+        edit_db(id, "Same edit", &con);
+        let text = export::markdown(&Article::find(id, &con).unwrap()).unwrap();
+        std::fs::write(file(&tree), text).unwrap();
+
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!(report.imported, 0);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(title(id, &con), "Same edit");
+        assert_eq!(tree_title(&tree), "Same edit");
+        assert_clean(&tree);
+
+        std::fs::remove_dir_all(&tree).unwrap();
+    }
+
+    #[test]
+    fn conflicts_wait_for_a_side() {
+        let Some((con, tree, id)) = setup() else {
+            return;
+        };
+        edit_tree(&tree, "Tree version");
+        edit_db(id, "Database version", &con);
+
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!(report.conflicts, vec!["first"]);
+        assert_eq!((report.imported, report.exported), (0, 0));
+        assert_eq!(title(id, &con), "Database version");
+        assert_eq!(tree_title(&tree), "Tree version");
+
+        // saving in the editor leaves the tree alone until the conflict is resolved
+        mirror_article_into(id, &tree, &con).unwrap();
+        assert_eq!(tree_title(&tree), "Tree version");
+
+        let report = pull_tree(&tree, &con, Some(Side::Tree)).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.imported, 1);
+        assert_eq!(title(id, &con), "Tree version");
+        assert_clean(&tree);
+
+        edit_tree(&tree, "Tree again");
+        edit_db(id, "Database again", &con);
+        let report = pull_tree(&tree, &con, Some(Side::Db)).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.exported, 1);
+        assert_eq!(title(id, &con), "Database again");
+        assert_eq!(tree_title(&tree), "Database again");
+        assert_clean(&tree);
+
+        std::fs::remove_dir_all(&tree).unwrap();
+    }
+
+    #[test]
+    fn removed_directories_go_to_the_trash() {
+        let Some((con, tree, id)) = setup() else {
+            return;
+        };
+        std::fs::remove_dir_all(tree.join("first")).unwrap();
+
+        let report = pull_tree(&tree, &con, None).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.changed, vec![id]);
+        assert!(matches!(Article::find(id, &con), Err(rusqlite::Error::QueryReturnedNoRows)));
+        let trashed: Option<i64> = con
+            .query_row("SELECT deleted_at FROM article WHERE id = ?", [id], |row| row.get(0))
+            .unwrap();
+        assert!(trashed.is_some());
+        assert!(SyncState::find_all(&con).unwrap().is_empty());
+        assert_clean(&tree);
+
+        std::fs::remove_dir_all(&tree).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use crate::render::MEDIA_DIR;
+use crate::store::articles::Article;
+use crate::store::media::Media;
+use crate::store::pages::Page;
+use crate::store::paragraphs::{Owner, Paragraph};
+use crate::store::Crud;
+use crate::variants;
+use serde::{Deserialize, Serialize};
+
+const TRASH_RETENTION_DAYS: &str = "TRASH_RETENTION_DAYS";
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Article,
+    Paragraph,
+    Media,
+    Page,
+}
+
+/// one row of the trash view
+#[derive(Debug, Serialize)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i64,
+    pub title: String,
+    pub article_id: Option<i64>,
+    /// set for paragraphs of a page
+    pub page_id: Option<i64>,
+    pub deleted_at: i64,
+    /// date of the automatic purge, `None` if disabled
+    pub purge_at: Option<i64>,
+}
+
+/// days until trashed items are purged, configurable via `TRASH_RETENTION_DAYS`.
+/// `0` keeps them until they are purged by hand.
+pub fn retention_days() -> i64 {
+    std::env::var(TRASH_RETENTION_DAYS)
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// trashed articles, pages, files and paragraphs of live articles and pages, newest first.
+/// Paragraphs and files of a trashed article, and paragraphs of a trashed page,
+/// are restored and purged with it.
+pub fn find_all(con: &rusqlite::Connection) -> Result<Vec<TrashItem>, rusqlite::Error> {
+    let mut stmt = con.prepare(
+        "SELECT 'article', id, title, id, deleted_at, NULL FROM article WHERE deleted_at IS NOT NULL
+         UNION ALL
+         SELECT 'page', id, title, NULL, deleted_at, NULL FROM page WHERE deleted_at IS NOT NULL
+         UNION ALL
+         SELECT 'paragraph', p.id, COALESCE(NULLIF(p.title, ''), substr(p.content, 1, 80)), p.article_id, p.deleted_at, p.page_id
+         FROM paragraph p
+         LEFT JOIN article a ON a.id = p.article_id
+         LEFT JOIN page g ON g.id = p.page_id
+         WHERE p.deleted_at IS NOT NULL
+         AND ((g.id IS NOT NULL AND g.deleted_at IS NULL) OR (a.id IS NOT NULL AND a.deleted_at IS NULL))
+         UNION ALL
+         SELECT 'media', id, path, article_id, deleted_at, NULL FROM media
+         WHERE deleted_at IS NOT NULL AND deleted_with_article = 0
+         ORDER BY 5 DESC",
+    )?;
+    let days = retention_days();
+    let items = stmt
+        .query_map([], |row| {
+            let kind: String = row.get(0)?;
+            let deleted_at: i64 = row.get(4)?;
+            Ok(TrashItem {
+                kind: match kind.as_str() {
+                    "article" => TrashKind::Article,
+                    "page" => TrashKind::Page,
+                    "media" => TrashKind::Media,
+                    _ => TrashKind::Paragraph,
+                },
+                id: row.get(1)?,
+                title: row.get(2)?,
+                article_id: row.get(3)?,
+                page_id: row.get(5)?,
+                deleted_at,
+                purge_at: (days > 0).then_some(deleted_at + days * 86400),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// purges the article and the media uploaded for it that nothing else uses anymore
+pub fn purge_article(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let media = Media::find_by_article(id, con)?;
+    Article::purge(id, con)?;
+
+    for media in media {
+        let media_id = media.id.unwrap_or_default();
+        Media::delete(media_id, con)?;
+        purge_media(media_id, con)?;
+    }
+
+    // upload dirs are per article, drop them once empty
+    let dir = std::path::PathBuf::from(MEDIA_DIR).join(id.to_string());
+    std::fs::remove_dir(dir.join(variants::VARIANT_DIR)).ok();
+    std::fs::remove_dir(dir).ok();
+    Ok(())
+}
+
+/// removes a trashed file and its variants from disk and the library.
+/// Files a cover or paragraph points to again are taken out of the trash instead.
+pub fn purge_media(id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    let media = Media::find(id, con)?;
+    if media.deleted_at.is_none() {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    if media.reference_count(con)? > 0 {
+        tracing::info!("trash: {} is still referenced, restored it", media.path);
+        return Media::restore(id, con);
+    }
+
+    if let Err(err) = variants::remove(&media, con) {
+        tracing::warn!("trash: failed to remove variants of {}: {:?}", media.path, err);
+    }
+    match std::fs::remove_file(media.disk_path()) {
+        Ok(_) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => {
+            tracing::warn!("trash: failed to remove {}: {}", media.path, err);
+            return Ok(());
+        }
+    }
+    Media::purge(id, con)
+}
+
+/// brings an item back, returns the article or page it belongs to
+pub fn restore(kind: TrashKind, id: i64, con: &rusqlite::Connection) -> Result<Option<Owner>, rusqlite::Error> {
+    let owner = match kind {
+        TrashKind::Article => Article::restore(id, con).map(|_| Some(Owner::Article(id)))?,
+        TrashKind::Paragraph => Paragraph::restore(id, con).map(Some)?,
+        TrashKind::Media => Media::restore(id, con).map(|_| None)?,
+        TrashKind::Page => Page::restore(id, con).map(|_| Some(Owner::Page(id)))?,
+    };
+    // trashed files are left out when the cache is loaded on start
+    if matches!(kind, TrashKind::Article | TrashKind::Media) {
+        variants::load_cache(con)?;
+    }
+    Ok(owner)
+}
+
+pub fn purge(kind: TrashKind, id: i64, con: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    match kind {
+        TrashKind::Article => purge_article(id, con),
+        TrashKind::Paragraph => Paragraph::purge(id, con),
+        TrashKind::Media => purge_media(id, con),
+        TrashKind::Page => Page::purge(id, con),
+    }
+}
+
+/// purges everything older than the retention period, returns the number of purged items
+pub fn purge_expired(con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    let days = retention_days();
+    if days <= 0 {
+        return Ok(0);
+    }
+
+    let cutoff = chrono::offset::Local::now().timestamp() - days * 86400;
+    let articles = Article::find_expired(cutoff, con)?;
+    for id in &articles {
+        purge_article(*id, con)?;
+    }
+    let media = Media::find_expired(cutoff, con)?;
+    for id in &media {
+        purge_media(*id, con)?;
+    }
+    let pages = Page::find_expired(cutoff, con)?;
+    for id in &pages {
+        Page::purge(*id, con)?;
+    }
+    Ok(articles.len() + media.len() + pages.len() + Paragraph::purge_expired(cutoff, con)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::comments::Comment;
+    use crate::store::media::MediaVariant;
+    use crate::store::pages::Page;
+    use crate::store::paragraphs::ParagraphType;
+    use crate::store::previews::PreviewToken;
+    use crate::store::redirects::ArticleAlias;
+    use crate::store::related::Related;
+    use crate::store::series::Series;
+    use crate::store::SchemaUp;
+
+    fn db() -> rusqlite::Connection {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Article::up(&con).unwrap();
+        Paragraph::up(&con).unwrap();
+        Media::up(&con).unwrap();
+        MediaVariant::up(&con).unwrap();
+        Page::up(&con).unwrap();
+        PreviewToken::up(&con).unwrap();
+        ArticleAlias::up(&con).unwrap();
+        Series::up(&con).unwrap();
+        Related::up(&con).unwrap();
+        Comment::up(&con).unwrap();
+        con
+    }
+
+    fn article(con: &rusqlite::Connection) -> i64 {
+        let mut article = Article::new("Fixture".to_string());
+        article.insert(con).unwrap();
+        article.id.unwrap()
+    }
+
+    fn paragraph(article_id: i64, content: &str, con: &rusqlite::Connection) -> i64 {
+        let mut paragraph = Paragraph {
+            id: None,
+            article_id: Some(article_id),
+            page_id: None,
+            title: String::new(),
+            description: String::new(),
+            paragraph_type: ParagraphType::Markdown,
+            position: 0,
+            content: content.to_string(),
+            rendered: None,
+        };
+        paragraph.insert(con).unwrap();
+        paragraph.id.unwrap()
+    }
+
+    fn media(article_id: i64, name: &str, con: &rusqlite::Connection) -> Media {
+        let mut media = Media {
+            path: format!("/{}/{}/{}", MEDIA_DIR, article_id, name),
+            article_id: Some(article_id),
+            mime: "image/png".to_string(),
+            hash: name.to_string(),
+            ..Default::default()
+        };
+        media.insert(con).unwrap();
+        media
+    }
+
+    fn kinds(con: &rusqlite::Connection) -> Vec<(TrashKind, i64)> {
+        find_all(con)
+            .unwrap()
+            .into_iter()
+            .map(|item| (item.kind, item.id))
+            .collect()
+    }
+
+    #[test]
+    fn article_takes_paragraphs_and_media_along() {
+        let con = db();
+        let id = article(&con);
+        let other = article(&con);
+        paragraph(id, "text", &con);
+        let own = media(id, "own.png", &con);
+        let shared = media(id, "shared.png", &con);
+        paragraph(other, &format!("![shared]({})", shared.path), &con);
+
+        Article::delete(id, &con).unwrap();
+        assert!(Paragraph::find_by_article_id(id, &con).unwrap().is_empty());
+        assert!(Media::find(own.id.unwrap(), &con).unwrap().deleted_at.is_some());
+        // still used by the other article
+        assert!(Media::find(shared.id.unwrap(), &con).unwrap().deleted_at.is_none());
+        // paragraphs and files of the article are listed through the article only
+        assert_eq!(kinds(&con), vec![(TrashKind::Article, id)]);
+
+        assert!(restore(TrashKind::Article, id, &con).unwrap().is_some());
+        assert_eq!(Paragraph::find_by_article_id(id, &con).unwrap().len(), 1);
+        assert!(Media::find(own.id.unwrap(), &con).unwrap().deleted_at.is_none());
+        assert!(kinds(&con).is_empty());
+
+        Article::delete(id, &con).unwrap();
+        purge(TrashKind::Article, id, &con).unwrap();
+        assert!(Article::find(id, &con).is_err());
+        assert!(Media::find(own.id.unwrap(), &con).is_err());
+        let left: i64 = con
+            .query_row("SELECT COUNT(*) FROM paragraph WHERE article_id = ?", [id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn purging_referenced_media_restores_it() {
+        let con = db();
+        let id = article(&con);
+        let file = media(id, "cover.png", &con);
+        let media_id = file.id.unwrap();
+
+        Media::delete(media_id, &con).unwrap();
+        assert_eq!(kinds(&con), vec![(TrashKind::Media, media_id)]);
+        paragraph(id, &format!("![cover]({})", file.path), &con);
+
+        purge(TrashKind::Media, media_id, &con).unwrap();
+        assert!(Media::find(media_id, &con).unwrap().deleted_at.is_none());
+        assert!(kinds(&con).is_empty());
+    }
+
+    #[test]
+    fn purge_expired_respects_the_cutoff() {
+        let con = db();
+        let old_article = article(&con);
+        let new_article = article(&con);
+        let live = article(&con);
+        let old_paragraph = paragraph(live, "old", &con);
+        let new_paragraph = paragraph(live, "new", &con);
+        let old_media = media(live, "old.png", &con).id.unwrap();
+
+        Article::delete(old_article, &con).unwrap();
+        Article::delete(new_article, &con).unwrap();
+        Paragraph::delete(old_paragraph, &con).unwrap();
+        Paragraph::delete(new_paragraph, &con).unwrap();
+        Media::delete(old_media, &con).unwrap();
+
+        let expired = chrono::offset::Local::now().timestamp() - (retention_days() + 1) * 86400;
+        for (table, id) in [
+            ("article", old_article),
+            ("paragraph", old_paragraph),
+            ("media", old_media),
+        ] {
+            con.execute(
+                &format!("UPDATE {} SET deleted_at = ? WHERE id = ?", table),
+                rusqlite::params![expired, id],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(purge_expired(&con).unwrap(), 3);
+        let left = kinds(&con);
+        assert!(left.contains(&(TrashKind::Article, new_article)));
+        assert!(left.contains(&(TrashKind::Paragraph, new_paragraph)));
+        assert!(!left.contains(&(TrashKind::Article, old_article)));
+        assert!(!left.contains(&(TrashKind::Paragraph, old_paragraph)));
+        assert!(Media::find(old_media, &con).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::render::MEDIA_DIR;
+use crate::store::{media::Media, Crud};
+use crate::util::{hex, Util};
+use crate::variants::VARIANT_DIR;
+use crate::SharedState;
+use axum::extract::multipart::Field;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+const MEDIA_MAX_SIZE: &str = "MEDIA_MAX_SIZE";
+const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+const MAX_NAME_LENGTH: usize = 100;
+
+const ALLOWED_MIME: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/svg+xml",
+    "video/mp4",
+    "video/webm",
+    "audio/mpeg",
+    "audio/ogg",
+    "application/pdf",
+    "application/wasm",
+    "application/javascript",
+    "text/javascript",
+];
+
+#[derive(Debug)]
+pub enum UploadError {
+    InvalidName,
+    UnsupportedType,
+    ContentMismatch,
+    TooLarge,
+    Multipart,
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+}
+
+impl UploadError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            UploadError::InvalidName => "invalid file name",
+            UploadError::UnsupportedType => "file type not allowed",
+            UploadError::ContentMismatch => "file content does not match its type",
+            UploadError::TooLarge => "file too large",
+            UploadError::Multipart => "broken upload",
+            UploadError::Io(_) => "failed to write file",
+            UploadError::Db(_) => "failed to store file",
+        }
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(err: std::io::Error) -> Self {
+        UploadError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for UploadError {
+    fn from(err: rusqlite::Error) -> Self {
+        UploadError::Db(err)
+    }
+}
+
+/// upload limit in bytes, configurable via `MEDIA_MAX_SIZE`
+pub fn max_size() -> usize {
+    std::env::var(MEDIA_MAX_SIZE)
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// strips any directories from a client supplied name and keeps only
+/// `[A-Za-z0-9._-]`. Returns `None` if there is nothing usable left.
+pub fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next()?;
+
+    let mut clean = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') => clean.push(c),
+            c if c.is_whitespace() && !clean.ends_with('-') => clean.push('-'),
+            _ => (),
+        }
+    }
+
+    let clean = clean.trim_start_matches(['.', '-']).to_string();
+    let (stem, extension) = clean.rsplit_once('.')?;
+    if stem.is_empty() || extension.is_empty() {
+        return None;
+    }
+
+    let stem = &stem[..stem.len().min(MAX_NAME_LENGTH)];
+    Some(format!("{}.{}", stem, extension.to_ascii_lowercase()))
+}
+
+/// mime type derived from the extension, if it is on the allowlist
+pub fn allowed_mime(name: &str) -> Option<&'static str> {
+    let mime = mime_guess::from_path(name).first_raw()?;
+    ALLOWED_MIME.iter().find(|m| **m == mime).copied()
+}
+
+pub fn is_raster(mime: &str) -> bool {
+    mime.starts_with("image/") && mime != "image/svg+xml"
+}
+
+/// uploading a trashed file again takes it out of the trash
+fn untrash(mut media: Media, con: &rusqlite::Connection) -> Media {
+    if media.deleted_at.is_some() && Media::restore(media.id.unwrap_or_default(), con).is_ok() {
+        media.deleted_at = None;
+    }
+    media
+}
+
+/// picks `name`, `name-1`, `name-2` .. until neither the disk nor the db knows the path
+fn unique_path(
+    dir: &Path,
+    name: &str,
+    con: &rusqlite::Connection,
+) -> Result<(PathBuf, String), UploadError> {
+    let (stem, extension) = name.rsplit_once('.').ok_or(UploadError::InvalidName)?;
+    let mut candidate = name.to_string();
+    let mut n = 0;
+    loop {
+        let path = dir.join(&candidate);
+        let url = format!("/{}", path.to_string_lossy());
+        if !path.exists() && Media::find_by_path(&url, con).is_err() {
+            return Ok((path, url));
+        }
+        n += 1;
+        candidate = format!("{}-{}.{}", stem, n, extension);
+    }
+}
+
+fn dimensions(path: &Path, mime: &str) -> (Option<i64>, Option<i64>) {
+    if !is_raster(mime) {
+        return (None, None);
+    }
+    match imagesize::size(path) {
+        Ok(size) => (Some(size.width as i64), Some(size.height as i64)),
+        Err(_) => (None, None),
+    }
+}
+
+/// streams one multipart field to `static/media/<article_id>/` and records it.
+/// Uploading a file that is already in the library returns the existing record.
+/// Takes the shared state instead of the connection, the connection itself is not `Sync`
+/// and must not be held across an await.
+pub async fn store_field(
+    mut field: Field<'_>,
+    article_id: i64,
+    uploaded_by: &str,
+    state: &SharedState,
+) -> Result<Media, UploadError> {
+    let name = field
+        .file_name()
+        .and_then(sanitize_file_name)
+        .ok_or(UploadError::InvalidName)?;
+    let mime = allowed_mime(&name).ok_or(UploadError::UnsupportedType)?;
+
+    let dir = PathBuf::from(MEDIA_DIR).join(article_id.to_string());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let temp_path = dir.join(format!(".upload-{}.part", rand::random::<u64>()));
+    let mut file = tokio::fs::File::create(&temp_path).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let limit = max_size();
+
+    let written: Result<(), UploadError> = async {
+        while let Some(chunk) = field.chunk().await.map_err(|_| UploadError::Multipart)? {
+            size += chunk.len();
+            if size > limit {
+                return Err(UploadError::TooLarge);
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = written {
+        tokio::fs::remove_file(&temp_path).await.ok();
+        return Err(err);
+    }
+
+    let hash = hex(&hasher.finalize());
+    if let Ok(existing) = Media::find_by_hash(&hash, &state.db) {
+        tokio::fs::remove_file(&temp_path).await.ok();
+        return Ok(untrash(existing, &state.db));
+    }
+
+    let (width, height) = dimensions(&temp_path, mime);
+    if is_raster(mime) && width.is_none() {
+        tokio::fs::remove_file(&temp_path).await.ok();
+        return Err(UploadError::ContentMismatch);
+    }
+
+    let (path, url) = unique_path(&dir, &name, &state.db)?;
+    tokio::fs::rename(&temp_path, &path).await?;
+
+    let mut media = Media {
+        id: None,
+        path: url,
+        article_id: Some(article_id),
+        mime: mime.to_string(),
+        size: size as i64,
+        hash,
+        width,
+        height,
+        alt: String::new(),
+        uploaded_by: uploaded_by.to_string(),
+        created_at: chrono::offset::Local::now().timestamp(),
+        deleted_at: None,
+    };
+
+    if let Err(err) = media.insert(&state.db) {
+        tokio::fs::remove_file(&path).await.ok();
+        return Err(err.into());
+    }
+
+    Ok(media)
+}
+
+/// copies a local file into `static/media/<article_id>/` and records it,
+/// the blocking counterpart of [store_field] for the command line.
+/// Files already in the library return the existing record.
+pub fn import_file(
+    source: &Path,
+    article_id: i64,
+    uploaded_by: &str,
+    con: &rusqlite::Connection,
+) -> Result<Media, UploadError> {
+    let name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(sanitize_file_name)
+        .ok_or(UploadError::InvalidName)?;
+    let mime = allowed_mime(&name).ok_or(UploadError::UnsupportedType)?;
+
+    let data = std::fs::read(source)?;
+    if data.len() > max_size() {
+        return Err(UploadError::TooLarge);
+    }
+
+    let hash = hex(&Sha256::digest(&data));
+    if let Ok(existing) = Media::find_by_hash(&hash, con) {
+        return Ok(untrash(existing, con));
+    }
+
+    let (width, height) = dimensions(source, mime);
+    if is_raster(mime) && width.is_none() {
+        return Err(UploadError::ContentMismatch);
+    }
+
+    let dir = PathBuf::from(MEDIA_DIR).join(article_id.to_string());
+    std::fs::create_dir_all(&dir)?;
+    let (path, url) = unique_path(&dir, &name, con)?;
+    std::fs::write(&path, &data)?;
+
+    let mut media = Media {
+        id: None,
+        path: url,
+        article_id: Some(article_id),
+        mime: mime.to_string(),
+        size: data.len() as i64,
+        hash,
+        width,
+        height,
+        alt: String::new(),
+        uploaded_by: uploaded_by.to_string(),
+        created_at: chrono::offset::Local::now().timestamp(),
+        deleted_at: None,
+    };
+
+    if let Err(err) = media.insert(con) {
+        std::fs::remove_file(&path).ok();
+        return Err(err.into());
+    }
+
+    Ok(media)
+}
+
+/// renames the file on disk and in the db, keeping the extension
+pub fn rename(media: &mut Media, name: &str, con: &rusqlite::Connection) -> Result<(), UploadError> {
+    let stem = sanitize_file_name(&format!("{}.x", name))
+        .and_then(|n| n.strip_suffix(".x").map(str::to_string))
+        .ok_or(UploadError::InvalidName)?;
+    let extension = media
+        .file_name()
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_string())
+        .ok_or(UploadError::InvalidName)?;
+
+    let old_path = media.disk_path();
+    let dir = old_path.parent().ok_or(UploadError::InvalidName)?;
+    let (path, url) = unique_path(dir, &format!("{}.{}", stem, extension), con)?;
+
+    std::fs::rename(&old_path, &path)?;
+    media.path = url;
+    if let Err(err) = media.update(con) {
+        std::fs::rename(&path, &old_path).ok();
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// registers files that were uploaded before the media table existed.
+/// Files an upload would reject are logged and left unregistered.
+pub fn import_existing(con: &rusqlite::Connection) -> Result<usize, UploadError> {
+    import_dir(Path::new(MEDIA_DIR), con)
+}
+
+/// generated variants and half written files are not media of their own
+fn is_derived(name: &str, path: &Path) -> bool {
+    name.starts_with('.')
+        || name.ends_with(".part")
+        || path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .is_some_and(|dir| dir == VARIANT_DIR)
+}
+
+fn import_dir(dir: &Path, con: &rusqlite::Connection) -> Result<usize, UploadError> {
+    let files = match Util::load_files_rec(dir.into()) {
+        Ok(files) => files,
+        Err(_) => return Ok(0),
+    };
+
+    let mut imported = 0;
+    for (name, path) in files {
+        let url = format!("/{}", path.to_string_lossy());
+        if is_derived(&name, &path) || Media::find_by_path(&url, con).is_ok() {
+            continue;
+        }
+
+        let Some(mime) = allowed_mime(&name) else {
+            tracing::warn!("media: skipping {}: {}", url, UploadError::UnsupportedType.message());
+            continue;
+        };
+        let (width, height) = dimensions(&path, mime);
+        if is_raster(mime) && width.is_none() {
+            tracing::warn!("media: skipping {}: {}", url, UploadError::ContentMismatch.message());
+            continue;
+        }
+
+        let data = std::fs::read(&path)?;
+        let article_id = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .and_then(|dir| dir.to_str())
+            .and_then(|dir| dir.parse().ok());
+
+        Media {
+            id: None,
+            path: url,
+            article_id,
+            mime: mime.to_string(),
+            size: data.len() as i64,
+            hash: hex(&Sha256::digest(&data)),
+            width,
+            height,
+            alt: String::new(),
+            uploaded_by: String::new(),
+            created_at: chrono::offset::Local::now().timestamp(),
+            deleted_at: None,
+        }
+        .insert(con)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::media::MediaVariant;
+    use crate::store::SchemaUp;
+
+    #[test]
+    fn init_does_not_register_variants() {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Media::up(&con).unwrap();
+        MediaVariant::up(&con).unwrap();
+
+        // media urls are relative to the working directory
+        let dir = PathBuf::from(format!("target/media-{:x}", rand::random::<u64>()));
+        std::fs::create_dir_all(dir.join("1")).unwrap();
+        image::RgbImage::new(500, 4)
+            .save(dir.join("1/cover.png"))
+            .unwrap();
+        std::fs::write(dir.join("1/.upload-1.part"), b"partial").unwrap();
+        std::fs::write(dir.join("1/notes.txt"), b"not media").unwrap();
+
+        for _ in 0..2 {
+            import_dir(&dir, &con).unwrap();
+            crate::variants::generate_missing(&con).unwrap();
+        }
+
+        let media = Media::find_all(&con).unwrap();
+        assert_eq!(media.len(), 1);
+        assert_eq!(media[0].article_id, Some(1));
+        assert!(!MediaVariant::find_by_media(media[0].id.unwrap(), &con)
+            .unwrap()
+            .is_empty());
+        assert!(!dir.join("1/variants/variants").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 pub struct Util;
 
 impl Util {
+    #[allow(clippy::io_other_error)]
     pub fn load_files_rec(dir: PathBuf) -> Result<Vec<(String, PathBuf)>, std::io::Error> {
         let mut files = Vec::new();
         let dir = std::fs::read_dir(dir)?;
@@ -27,3 +28,25 @@ impl Util {
         Ok(files)
     }
 }
+
+/// an environment variable, `None` if it is unset or blank
+pub fn env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `None` unless `text` is an even number of hex digits
+pub fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,396 @@
+use crate::store::media::{Media, MediaVariant};
+use crate::store::Crud;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// widths offered in `srcset`, the smallest doubles as thumbnail
+pub const WIDTHS: [u32; 4] = [480, 960, 1440, 1920];
+pub const VARIANT_DIR: &str = "variants";
+const JPEG_QUALITY: u8 = 82;
+const AVIF_QUALITY: u8 = 70;
+const AVIF_SPEED: u8 = 8;
+
+/// the variants of every media image by url, so [picture] neither queries the
+/// database nor probes the disk while rendering. Filled by [load_cache],
+/// kept current by [generate] and [remove].
+static CACHE: RwLock<BTreeMap<String, CachedImage>> = RwLock::new(BTreeMap::new());
+
+struct CachedImage {
+    mime: String,
+    /// of the original, `None` if it could not be read
+    width: Option<i64>,
+    variants: Vec<MediaVariant>,
+}
+
+#[derive(Debug)]
+pub enum VariantError {
+    Unsupported,
+    Image(image::ImageError),
+    Io(std::io::Error),
+    Db(rusqlite::Error),
+}
+
+impl From<image::ImageError> for VariantError {
+    fn from(err: image::ImageError) -> Self {
+        VariantError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for VariantError {
+    fn from(err: std::io::Error) -> Self {
+        VariantError::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for VariantError {
+    fn from(err: rusqlite::Error) -> Self {
+        VariantError::Db(err)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Avif,
+    WebP,
+    Jpeg,
+    Png,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Avif => "avif",
+            Format::WebP => "webp",
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            Format::Avif => "image/avif",
+            Format::WebP => "image/webp",
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+        }
+    }
+
+    /// the original format of a media file, if we know how to derive variants from it
+    fn of(mime: &str) -> Option<Self> {
+        match mime {
+            "image/jpeg" => Some(Format::Jpeg),
+            "image/png" => Some(Format::Png),
+            "image/webp" => Some(Format::WebP),
+            _ => None,
+        }
+    }
+
+    /// modern formats first. The webp encoder is lossless only, which
+    /// only pays off for png sources.
+    fn targets(original: Format) -> Vec<Format> {
+        match original {
+            Format::Png => vec![Format::Avif, Format::WebP, Format::Png],
+            Format::WebP => vec![Format::Avif, Format::WebP],
+            _ => vec![Format::Avif, original],
+        }
+    }
+
+    /// writes to a temporary file first, so pages never pick up a half written variant
+    fn write(&self, img: &DynamicImage, path: &Path) -> Result<(), VariantError> {
+        let temp_path = path.with_extension(format!("{}.part", self.extension()));
+        let result = self.encode(img, &temp_path);
+        match result {
+            Ok(_) => std::fs::rename(&temp_path, path)?,
+            Err(_) => {
+                std::fs::remove_file(&temp_path).ok();
+            }
+        }
+        result
+    }
+
+    fn encode(&self, img: &DynamicImage, path: &Path) -> Result<(), VariantError> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let img = match img.color().has_alpha() {
+            true => DynamicImage::ImageRgba8(img.to_rgba8()),
+            false => DynamicImage::ImageRgb8(img.to_rgb8()),
+        };
+        match self {
+            Format::Avif => img.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                file,
+                AVIF_SPEED,
+                AVIF_QUALITY,
+            ))?,
+            Format::WebP => img.write_with_encoder(WebPEncoder::new_lossless(file))?,
+            Format::Jpeg => {
+                let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+                rgb.write_with_encoder(JpegEncoder::new_with_quality(file, JPEG_QUALITY))?
+            }
+            Format::Png => img.write_with_encoder(PngEncoder::new(file))?,
+        }
+        Ok(())
+    }
+}
+
+/// `/static/media/3/cover.png` -> `/static/media/3/variants/cover-480.avif`
+fn variant_url(media_path: &str, width: u32, format: Format) -> Option<String> {
+    let (dir, name) = media_path.rsplit_once('/')?;
+    let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+    Some(format!(
+        "{}/{}/{}-{}.{}",
+        dir,
+        VARIANT_DIR,
+        stem,
+        width,
+        format.extension()
+    ))
+}
+
+fn disk_path(url: &str) -> PathBuf {
+    url.trim_start_matches('/').into()
+}
+
+/// runs [generate] on a blocking thread with its own connection,
+/// the shared one must not be used off the request handlers.
+pub fn spawn(media_id: i64, db_path: String) {
+    tokio::task::spawn_blocking(move || {
+        let con = match crate::store::open(&db_path) {
+            Ok(con) => con,
+            Err(err) => {
+                tracing::error!("variants: failed to open database: {}", err);
+                return;
+            }
+        };
+
+        match generate(media_id, &con) {
+            Ok(count) => tracing::info!("variants: created {} for media {}", count, media_id),
+            Err(VariantError::Unsupported) => (),
+            Err(err) => tracing::error!("variants: media {} failed: {:?}", media_id, err),
+        }
+    });
+}
+
+/// fixes the orientation, strips exif from the original and writes all variants
+pub fn generate(media_id: i64, con: &rusqlite::Connection) -> Result<usize, VariantError> {
+    let mut media = Media::find(media_id, con)?;
+    let original = Format::of(&media.mime).ok_or(VariantError::Unsupported)?;
+    let path = media.disk_path();
+
+    let mut decoder = ImageReader::open(&path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let exif = decoder.exif_metadata()?;
+    let orientation = exif
+        .as_deref()
+        .and_then(Orientation::from_exif_chunk)
+        .unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+
+    // re-encoding drops the exif block, including any location data.
+    // `hash` stays the one of the upload.
+    if exif.is_some() {
+        original.write(&img, &path)?;
+        media.size = std::fs::metadata(&path)?.len() as i64;
+        media.width = Some(img.width() as i64);
+        media.height = Some(img.height() as i64);
+        media.update(con)?;
+    }
+
+    remove(&media, con)?;
+
+    let mut widths = WIDTHS
+        .iter()
+        .copied()
+        .filter(|w| *w < img.width())
+        .collect::<Vec<_>>();
+    if img.width() <= WIDTHS[WIDTHS.len() - 1] {
+        widths.push(img.width());
+    }
+
+    let dir = path
+        .parent()
+        .map(|dir| dir.join(VARIANT_DIR))
+        .ok_or(VariantError::Unsupported)?;
+    std::fs::create_dir_all(&dir)?;
+
+    let mut count = 0;
+    for width in widths {
+        let resized = match width < img.width() {
+            true => img.resize(width, img.height(), FilterType::Lanczos3),
+            false => img.clone(),
+        };
+
+        for format in Format::targets(original) {
+            // the original already covers its own size in its own format
+            if width == img.width() && format.mime() == media.mime {
+                continue;
+            }
+
+            let url = variant_url(&media.path, width, format).ok_or(VariantError::Unsupported)?;
+            let variant_path = disk_path(&url);
+            format.write(&resized, &variant_path)?;
+
+            MediaVariant {
+                id: None,
+                media_id,
+                path: url,
+                width: resized.width() as i64,
+                height: resized.height() as i64,
+                mime: format.mime().to_string(),
+                size: std::fs::metadata(&variant_path)?.len() as i64,
+                created_at: chrono::offset::Local::now().timestamp(),
+            }
+            .insert(con)?;
+            count += 1;
+        }
+    }
+
+    cache(&media, MediaVariant::find_by_media(media_id, con)?);
+    Ok(count)
+}
+
+/// generates variants for media files that have none yet, e.g. after [crate::upload::import_existing]
+pub fn generate_missing(con: &rusqlite::Connection) -> Result<usize, VariantError> {
+    let mut generated = 0;
+    for media in Media::find_all(con)? {
+        let id = match media.id {
+            Some(id) if Format::of(&media.mime).is_some() => id,
+            _ => continue,
+        };
+        if !MediaVariant::find_by_media(id, con)?.is_empty() {
+            continue;
+        }
+        match generate(id, con) {
+            Ok(_) => generated += 1,
+            Err(err) => tracing::warn!("variants: media {} failed: {:?}", id, err),
+        }
+    }
+    Ok(generated)
+}
+
+/// deletes all variant files and records of a media file
+pub fn remove(media: &Media, con: &rusqlite::Connection) -> Result<(), VariantError> {
+    let id = media.id.ok_or(VariantError::Unsupported)?;
+    for variant in MediaVariant::find_by_media(id, con)? {
+        match std::fs::remove_file(disk_path(&variant.path)) {
+            Ok(_) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    MediaVariant::delete_by_media(id, con)?;
+    if let Ok(mut cache) = CACHE.write() {
+        cache.remove(&media.path);
+    }
+    Ok(())
+}
+
+fn cache(media: &Media, variants: Vec<MediaVariant>) {
+    if let Ok(mut cache) = CACHE.write() {
+        cache.insert(
+            media.path.clone(),
+            CachedImage {
+                mime: media.mime.clone(),
+                width: media.width,
+                variants,
+            },
+        );
+    }
+}
+
+/// reads the variants of all media images into the cache used by [picture]
+pub fn load_cache(con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    let mut count = 0;
+    for media in Media::find_all(con)? {
+        let id = match media.id {
+            Some(id) if Format::of(&media.mime).is_some() => id,
+            _ => continue,
+        };
+        cache(&media, MediaVariant::find_by_media(id, con)?);
+        count += 1;
+    }
+    Ok(count)
+}
+
+// --------------------------------------------------------
+// markup
+// --------------------------------------------------------
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `<picture>` markup for a media image, built from the cached variant records.
+/// Images without variants (yet) fall back to a plain `<img>`.
+pub fn picture(src: &str, alt: &str, class: &str, sizes: &str) -> String {
+    let img = |srcset: &str| {
+        format!(
+            r#"<img src="{}"{} sizes="{}" alt="{}" class="{}" loading="lazy" decoding="async" />"#,
+            escape_attr(src),
+            srcset,
+            escape_attr(sizes),
+            escape_attr(alt),
+            escape_attr(class),
+        )
+    };
+
+    let Ok(cache) = CACHE.read() else {
+        return img("");
+    };
+    let (image, original) = match cache.get(src) {
+        Some(image) => match Format::of(&image.mime) {
+            Some(format) => (image, format),
+            None => return img(""),
+        },
+        None => return img(""),
+    };
+
+    let srcset = |format: Format| {
+        image
+            .variants
+            .iter()
+            .filter(|variant| variant.mime == format.mime())
+            .map(|variant| format!("{} {}w", escape_attr(&variant.path), variant.width))
+            .collect::<Vec<_>>()
+    };
+
+    let mut sources = String::new();
+    for format in Format::targets(original) {
+        let set = srcset(format);
+        if set.is_empty() || format.mime() == original.mime() {
+            continue;
+        }
+        sources.push_str(&format!(
+            r#"<source type="{}" srcset="{}" sizes="{}" />"#,
+            format.mime(),
+            set.join(", "),
+            escape_attr(sizes)
+        ));
+    }
+
+    let mut fallback = srcset(original);
+    if let Some(width) = image.width {
+        fallback.push(format!("{} {}w", escape_attr(src), width));
+    }
+
+    if sources.is_empty() && fallback.len() < 2 {
+        return img("");
+    }
+
+    format!(
+        "<picture>{}{}</picture>",
+        sources,
+        img(&format!(r#" srcset="{}""#, fallback.join(", ")))
+    )
+}
@@ -0,0 +1,357 @@
+use crate::store::webhooks::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent};
+use crate::store::articles::Article;
+use crate::store::comments::Comment;
+use crate::store::contacts::ContactRequest;
+use crate::store::Crud;
+use std::time::Duration;
+
+/// how often the queue is polled
+const INTERVAL: Duration = Duration::from_secs(5);
+/// per request, slow receivers count as failed
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// deliveries sent per poll
+const BATCH: i64 = 20;
+/// attempts before a delivery is given up
+pub const MAX_ATTEMPTS: i64 = 8;
+/// first retry delay, doubled after every failed attempt
+const BACKOFF_SECONDS: i64 = 30;
+
+/// queues `event` for every active hook subscribed to it.
+/// All hooks receive the same body: `{"event", "created_at", "data"}`.
+pub fn trigger(
+    event: WebhookEvent,
+    data: serde_json::Value,
+    con: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    let hooks = Webhook::find_by_event(event, con)?;
+    enqueue(event, data, &hooks, con)
+}
+
+/// like [trigger], failures are only logged. Webhooks never break the request that caused them.
+pub fn emit(event: WebhookEvent, data: serde_json::Value, con: &rusqlite::Connection) {
+    if let Err(err) = trigger(event, data, con) {
+        tracing::error!("webhooks: failed to queue {}: {}", event.as_str(), err);
+    }
+}
+
+pub fn enqueue(
+    event: WebhookEvent,
+    data: serde_json::Value,
+    hooks: &[Webhook],
+    con: &rusqlite::Connection,
+) -> Result<usize, rusqlite::Error> {
+    let payload = serde_json::json!({
+        "event": event.as_str(),
+        "created_at": chrono::offset::Local::now().timestamp(),
+        "data": data,
+    })
+    .to_string();
+
+    for hook in hooks {
+        WebhookDelivery::new(hook.id.ok_or(rusqlite::Error::InvalidQuery)?, event, payload.clone())
+            .insert(con)?;
+    }
+    Ok(hooks.len())
+}
+
+pub fn article_data(article: &Article) -> serde_json::Value {
+    serde_json::json!({
+        "id": article.id,
+        "title": article.title,
+        "teaser": article.teaser,
+        "tags": article.tags,
+        "url": article.url(),
+        "published": article.published,
+        "updated_at": article.updated_at,
+    })
+}
+
+pub fn comment_data(comment: &Comment, article: &Article) -> serde_json::Value {
+    serde_json::json!({
+        "id": comment.id,
+        "article_id": comment.article_id,
+        "article_title": article.title,
+        "parent_id": comment.parent_id,
+        "author": comment.author,
+        "body": comment.body,
+        "status": comment.status,
+        "url": format!("{}#comment-{}", article.url(), comment.id.unwrap_or_default()),
+        "created_at": comment.created_at,
+    })
+}
+
+pub fn contact_data(contact: &ContactRequest) -> serde_json::Value {
+    serde_json::json!({
+        "id": contact.id,
+        "email": contact.email,
+        "subject": contact.subject,
+        "message": contact.message,
+        "spam_score": contact.spam_score,
+        "created": contact.created,
+    })
+}
+
+/// `sha256=<hex>` of the body, keyed with the hook's secret
+pub fn signature(secret: &str, payload: &str) -> String {
+    let mac = crate::spam::hmac_sha256(secret.as_bytes(), payload.as_bytes());
+    format!("sha256={}", crate::util::hex(&mac))
+}
+
+/// a random secret for hooks created without one
+pub fn generate_secret() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// polls the delivery queue, see [crate::scheduler::spawn_periodic]
+pub fn spawn(db_path: String) {
+    crate::scheduler::spawn_periodic("webhooks", INTERVAL, db_path, |con| match deliver_due(con) {
+        Ok(0) => (),
+        Ok(count) => tracing::info!("webhooks: attempted {} deliveries", count),
+        Err(err) => tracing::error!("webhooks: failed to read queue: {}", err),
+    });
+}
+
+/// sends every due delivery once, blocking
+pub fn deliver_due(con: &rusqlite::Connection) -> Result<usize, rusqlite::Error> {
+    let now = chrono::offset::Local::now().timestamp();
+    let due = WebhookDelivery::find_due(now, BATCH, con)?;
+    let count = due.len();
+    for mut delivery in due {
+        let hook = match Webhook::find(delivery.webhook_id, con) {
+            Ok(hook) => hook,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.error = Some("webhook was deleted".to_string());
+                delivery.next_attempt_at = None;
+                delivery.update(con)?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        attempt(&hook, &mut delivery);
+        delivery.update(con)?;
+    }
+    Ok(count)
+}
+
+/// posts the payload and records the outcome, scheduling a retry on failure
+fn attempt(hook: &Webhook, delivery: &mut WebhookDelivery) {
+    delivery.attempts += 1;
+    let result = ureq::post(&hook.url)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .set("User-Agent", "lommix-webhooks")
+        .set("X-Webhook-Event", &delivery.event)
+        .set(
+            "X-Webhook-Delivery",
+            &delivery.id.unwrap_or_default().to_string(),
+        )
+        .set(
+            "X-Webhook-Signature",
+            &signature(&hook.secret, &delivery.payload),
+        )
+        .send_string(&delivery.payload);
+
+    let now = chrono::offset::Local::now().timestamp();
+    match result {
+        Ok(response) => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.response_code = Some(response.status() as i64);
+            delivery.error = None;
+            delivery.next_attempt_at = None;
+            delivery.delivered_at = Some(now);
+            return;
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            delivery.response_code = Some(code as i64);
+            delivery.error = Some(format!("receiver answered {}", code));
+        }
+        Err(err) => {
+            delivery.response_code = None;
+            delivery.error = Some(err.to_string());
+        }
+    }
+
+    tracing::warn!(
+        "webhooks: delivery {} to {} failed: {}",
+        delivery.id.unwrap_or_default(),
+        hook.url,
+        delivery.error.as_deref().unwrap_or_default()
+    );
+
+    if delivery.attempts >= MAX_ATTEMPTS {
+        delivery.status = DeliveryStatus::Failed;
+        delivery.next_attempt_at = None;
+    } else {
+        delivery.status = DeliveryStatus::Pending;
+        delivery.next_attempt_at = Some(now + BACKOFF_SECONDS * (1 << (delivery.attempts - 1)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::SchemaUp;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// a received request, header names are lowercase
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// answers one request per status, in order
+    fn receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((key, value)) => {
+                            headers.push((key.to_ascii_lowercase(), value.trim().to_string()))
+                        }
+                        None => break,
+                    }
+                }
+
+                let length = headers
+                    .iter()
+                    .find(|(key, _)| key == "content-length")
+                    .and_then(|(_, value)| value.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                tx.send(Received {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                })
+                .unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn queue(url: String) -> (rusqlite::Connection, Webhook) {
+        let con = rusqlite::Connection::open_in_memory().unwrap();
+        Webhook::up(&con).unwrap();
+        WebhookDelivery::up(&con).unwrap();
+
+        let mut hook = Webhook::new(url, "secret".to_string(), &WebhookEvent::ALL);
+        hook.insert(&con).unwrap();
+        trigger(
+            WebhookEvent::ArticlePublished,
+            serde_json::json!({"id": 1}),
+            &con,
+        )
+        .unwrap();
+        (con, hook)
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn delivers_signed_payload() {
+        let (url, received) = receiver(vec![204]);
+        let (con, hook) = queue(url);
+
+        assert_eq!(deliver_due(&con).unwrap(), 1);
+        let request = received.recv().unwrap();
+        assert_eq!(
+            request.header("x-webhook-signature"),
+            Some(signature("secret", &request.body).as_str())
+        );
+        assert_eq!(request.header("x-webhook-event"), Some("article.published"));
+
+        let delivery = WebhookDelivery::find_by_webhook(hook.id.unwrap(), 1, &con)
+            .unwrap()
+            .remove(0);
+        assert_eq!(delivery.payload, request.body);
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.response_code, Some(204));
+        assert_eq!(delivery.next_attempt_at, None);
+        assert!(delivery.delivered_at.is_some());
+    }
+
+    #[test]
+    fn backs_off_on_server_errors() {
+        let (url, received) = receiver(vec![500, 503]);
+        let (con, hook) = queue(url);
+        let mut delivery = WebhookDelivery::find_by_webhook(hook.id.unwrap(), 1, &con)
+            .unwrap()
+            .remove(0);
+
+        for attempts in 1..=2 {
+            let before = chrono::offset::Local::now().timestamp();
+            attempt(&hook, &mut delivery);
+            let after = chrono::offset::Local::now().timestamp();
+            received.recv().unwrap();
+
+            let delay = BACKOFF_SECONDS << (attempts - 1);
+            let next = delivery.next_attempt_at.unwrap();
+            assert_eq!(delivery.attempts, attempts);
+            assert_eq!(delivery.status, DeliveryStatus::Pending);
+            assert!(next >= before + delay && next <= after + delay);
+        }
+        assert_eq!(delivery.response_code, Some(503));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let (url, received) = receiver(vec![502]);
+        let (con, hook) = queue(url);
+        con.execute(
+            "UPDATE webhook_delivery SET attempts = ?",
+            [MAX_ATTEMPTS - 1],
+        )
+        .unwrap();
+
+        assert_eq!(deliver_due(&con).unwrap(), 1);
+        received.recv().unwrap();
+
+        let delivery = WebhookDelivery::find_by_webhook(hook.id.unwrap(), 1, &con)
+            .unwrap()
+            .remove(0);
+        assert_eq!(delivery.attempts, MAX_ATTEMPTS);
+        assert_eq!(delivery.status, DeliveryStatus::Failed);
+        assert_eq!(delivery.response_code, Some(502));
+        assert_eq!(delivery.next_attempt_at, None);
+        assert_eq!(deliver_due(&con).unwrap(), 0);
+    }
+}
@@ -25,7 +25,8 @@ function slide_down(id, pixel) {
 // @param {string} canvas_id
 // @param {number} height
 async function run_wasm(wasm_path, script_path, canvas_id, height) {
-	const script = await import(script_path);
+	// resolved against the page, static exports pass relative paths
+	const script = await import(new URL(script_path, document.baseURI).href);
 	let canvas = document.getElementById(canvas_id.replace("#", ""));
 	const max_width = canvas.parentNode.clientWidth;
 	const loading_screen = document.createElement("div");
@@ -51,6 +52,34 @@ async function run_wasm(wasm_path, script_path, canvas_id, height) {
 		});
 }
 
+// @description move a paragraph one step and store the new order
+// @param {string} url order endpoint of the article or page
+// @param {number} id
+// @param {number} direction -1 up, 1 down
+function move_paragraph(url, id, direction) {
+	const element = document.getElementById(`para-${id}`);
+	const sibling = direction < 0 ? element.previousElementSibling : element.nextElementSibling;
+	if (!sibling || !sibling.id.startsWith("para-")) {
+		return;
+	}
+
+	if (direction < 0) {
+		sibling.before(element);
+	} else {
+		sibling.after(element);
+	}
+
+	const order = [...document.getElementById("paragraphs").children]
+		.filter((child) => child.id.startsWith("para-"))
+		.map((child) => child.id.replace("para-", ""))
+		.join(",");
+
+	htmx.ajax("PUT", url, {
+		values: { order },
+		swap: "none",
+	});
+}
+
 document.addEventListener("DOMContentLoaded", () => {
 	// highlight markdown code blocks
 	if (typeof hljs !== "undefined") {